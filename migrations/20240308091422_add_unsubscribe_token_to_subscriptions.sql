@@ -0,0 +1,14 @@
+-- Add migration script here
+BEGIN;
+    ALTER TABLE subscriptions ADD COLUMN unsubscribe_token TEXT NULL;
+    ALTER TABLE subscriptions ADD COLUMN unsubscribed_at timestamptz NULL;
+
+    -- Existing subscribers need a token too, otherwise their emails
+    -- cannot carry an unsubscribe link
+    UPDATE subscriptions
+        SET unsubscribe_token = md5(random()::text || id::text)
+        WHERE unsubscribe_token IS NULL;
+
+    ALTER TABLE subscriptions ALTER COLUMN unsubscribe_token SET NOT NULL;
+    ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_unsubscribe_token_key UNIQUE (unsubscribe_token);
+COMMIT;
@@ -0,0 +1,13 @@
+-- Add migration script here
+CREATE TABLE newsletter_issue_deliveries (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues(newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    -- One of 'sent', 'failed' or 'skipped'
+    status TEXT NOT NULL,
+    provider_message_id TEXT NULL,
+    error TEXT NULL,
+    recorded_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX newsletter_issue_deliveries_issue_idx
+ON newsletter_issue_deliveries (newsletter_issue_id);
@@ -0,0 +1,7 @@
+-- Add migration script here
+-- One of 'scheduled', 'published' or 'cancelled'
+ALTER TABLE newsletter_issues
+ADD COLUMN status TEXT NOT NULL DEFAULT 'published';
+
+ALTER TABLE newsletter_issues
+ADD COLUMN scheduled_for timestamptz NULL;
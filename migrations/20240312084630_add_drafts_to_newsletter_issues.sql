@@ -0,0 +1,17 @@
+-- Add migration script here
+-- Drafts have not been published yet
+ALTER TABLE newsletter_issues ALTER COLUMN published_at DROP NOT NULL;
+
+-- One of 'draft', 'scheduled', 'sending', 'sent' or 'cancelled':
+-- 'published' is split between the issues still going out and the others
+UPDATE newsletter_issues i
+SET status = CASE
+    WHEN EXISTS (
+        SELECT 1 FROM issue_delivery_table q
+        WHERE q.newsletter_issue_id = i.newsletter_issue_id
+    ) THEN 'sending'
+    ELSE 'sent'
+END
+WHERE status = 'published';
+
+ALTER TABLE newsletter_issues ALTER COLUMN status SET DEFAULT 'draft';
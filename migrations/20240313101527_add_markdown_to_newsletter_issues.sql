@@ -0,0 +1,4 @@
+-- Add migration script here
+-- The source of the issues written in Markdown: their HTML and plain text
+-- versions are derived from it when saving them
+ALTER TABLE newsletter_issues ADD COLUMN markdown_content TEXT NULL;
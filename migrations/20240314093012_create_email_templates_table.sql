@@ -0,0 +1,21 @@
+-- Add migration script here
+CREATE TABLE email_templates(
+    email_template_id uuid NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    header_html TEXT NOT NULL,
+    footer_html TEXT NOT NULL,
+    css TEXT NOT NULL,
+    -- Required in commercial emails by the CAN-SPAM act
+    company_address TEXT NOT NULL,
+    -- The layout of the confirmation emails and, unless another one is picked, of the issues
+    is_default BOOLEAN NOT NULL DEFAULT false,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (email_template_id)
+);
+
+CREATE UNIQUE INDEX email_templates_single_default ON email_templates (is_default) WHERE is_default;
+
+-- NULL when the issue is sent without a layout
+ALTER TABLE newsletter_issues
+ADD COLUMN email_template_id uuid NULL
+REFERENCES email_templates (email_template_id) ON DELETE SET NULL;
@@ -0,0 +1,12 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL UNIQUE;
+ALTER TABLE newsletter_issues ADD COLUMN is_public BOOLEAN NOT NULL DEFAULT FALSE;
+-- Issues published so far stay out of the public archive,
+-- but get a web address made of their title and the start of their id.
+-- Titles without letters or digits fall back to 'issue', as in `IssueSlug`
+UPDATE newsletter_issues
+SET slug = COALESCE(
+        NULLIF(trim(BOTH '-' FROM regexp_replace(lower(title), '[^a-z0-9]+', '-', 'g')), ''),
+        'issue'
+    ) || '-' || left(replace(newsletter_issue_id::text, '-', ''), 8)
+WHERE status <> 'draft';
@@ -0,0 +1,5 @@
+-- Add migration script here
+-- Publication dates were stored as text, either RFC 3339 or Postgres' own
+-- formatting: both are understood by the timestamptz input parser
+ALTER TABLE newsletter_issues
+    ALTER COLUMN published_at TYPE timestamptz USING published_at::timestamptz;
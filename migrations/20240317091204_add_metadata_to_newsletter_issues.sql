@@ -0,0 +1,11 @@
+-- Add migration script here
+-- published_at became a timestamptz and slug was added along with the public archive
+ALTER TABLE newsletter_issues
+    ADD COLUMN created_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN author_user_id uuid NULL REFERENCES users (user_id) ON DELETE SET NULL,
+    ADD COLUMN subject_preview TEXT NULL;
+-- The publication date is the best guess we have for the issues written so far
+UPDATE newsletter_issues
+SET created_at = LEAST(COALESCE(published_at, now()), now()),
+    updated_at = LEAST(COALESCE(published_at, now()), now());
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- Unknown for the subscribers who confirmed so far
+ALTER TABLE subscriptions ADD COLUMN confirmed_at timestamptz NULL;
+-- The admin list is paginated on the subscription date, newest first
+CREATE INDEX subscriptions_subscribed_at_id_idx ON subscriptions (subscribed_at DESC, id DESC);
+-- The delivery history of a subscriber is looked up by email
+CREATE INDEX newsletter_issue_deliveries_subscriber_email_idx
+ON newsletter_issue_deliveries (subscriber_email);
@@ -0,0 +1,14 @@
+-- Add migration script here
+-- Where the consent of the subscribers added by the admins comes from
+ALTER TABLE subscriptions ADD COLUMN consent_source TEXT NULL;
+-- The extra columns of imported lists
+ALTER TABLE subscriptions ADD COLUMN custom_fields jsonb NOT NULL DEFAULT '{}';
+
+-- Imported subscribers who still have to confirm get their email from the worker
+CREATE TABLE confirmation_email_queue (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id) ON DELETE CASCADE,
+    n_retries SMALLINT NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL DEFAULT now(),
+    enqueued_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (subscriber_id)
+);
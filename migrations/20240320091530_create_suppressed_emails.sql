@@ -0,0 +1,8 @@
+-- Add migration script here
+-- People who asked to be erased. Only a hash of their address is kept,
+-- enough to recognise it without storing it
+CREATE TABLE suppressed_emails (
+    email_hash TEXT NOT NULL,
+    suppressed_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (email_hash)
+);
@@ -0,0 +1,13 @@
+-- Add migration script here
+-- Subscribers can take a break, no issue is sent to them until then
+ALTER TABLE subscriptions ADD COLUMN paused_until timestamptz NULL;
+
+-- A new address only replaces the current one once it has been confirmed
+CREATE TABLE email_change_requests (
+    confirmation_token TEXT NOT NULL,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id) ON DELETE CASCADE,
+    new_email TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    expires_at timestamptz NOT NULL,
+    PRIMARY KEY (confirmation_token)
+);
@@ -0,0 +1,50 @@
+-- Add migration script here
+CREATE TABLE lists (
+    list_id uuid NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    description TEXT NOT NULL DEFAULT '',
+    -- The list of the subscriptions and issues that do not pick one
+    is_default BOOLEAN NOT NULL DEFAULT false,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (list_id)
+);
+
+CREATE UNIQUE INDEX lists_single_default ON lists (is_default) WHERE is_default;
+
+-- Everything sent so far went to this one
+INSERT INTO lists (list_id, name, is_default)
+VALUES ('3f0c6a4e-2b7d-4d8e-9a51-6c2e8f1b7d34', 'Newsletter', true);
+
+-- A subscriber confirms each list they join.
+-- Leaving a list, or unsubscribing entirely, removes the membership
+CREATE TABLE list_memberships (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id) ON DELETE CASCADE,
+    list_id uuid NOT NULL REFERENCES lists(list_id) ON DELETE CASCADE,
+    status TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    confirmed_at timestamptz NULL,
+    PRIMARY KEY (subscriber_id, list_id)
+);
+
+CREATE INDEX list_memberships_list_id_idx ON list_memberships (list_id, status);
+
+INSERT INTO list_memberships (subscriber_id, list_id, status, created_at, confirmed_at)
+SELECT id, '3f0c6a4e-2b7d-4d8e-9a51-6c2e8f1b7d34', status, subscribed_at, confirmed_at
+FROM subscriptions
+WHERE status <> 'unsubscribed';
+
+-- Confirmation links are for one list
+ALTER TABLE subscription_tokens ADD COLUMN list_id uuid NULL REFERENCES lists(list_id) ON DELETE CASCADE;
+UPDATE subscription_tokens SET list_id = '3f0c6a4e-2b7d-4d8e-9a51-6c2e8f1b7d34';
+ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
+
+ALTER TABLE confirmation_email_queue ADD COLUMN list_id uuid NULL REFERENCES lists(list_id) ON DELETE CASCADE;
+UPDATE confirmation_email_queue SET list_id = '3f0c6a4e-2b7d-4d8e-9a51-6c2e8f1b7d34';
+ALTER TABLE confirmation_email_queue ALTER COLUMN list_id SET NOT NULL;
+-- A subscriber can wait for the confirmation of several lists at once
+ALTER TABLE confirmation_email_queue DROP CONSTRAINT confirmation_email_queue_pkey;
+ALTER TABLE confirmation_email_queue ADD PRIMARY KEY (subscriber_id, list_id);
+
+-- Drafts get their list when they are published
+ALTER TABLE newsletter_issues ADD COLUMN list_id uuid NULL REFERENCES lists(list_id);
+UPDATE newsletter_issues SET list_id = '3f0c6a4e-2b7d-4d8e-9a51-6c2e8f1b7d34' WHERE status <> 'draft';
@@ -1,5 +1,6 @@
 use config::Config;
 use config::ConfigError;
+use rand::Rng;
 use secrecy::ExposeSecret;
 use secrecy::Secret;
 use serde::Deserialize;
@@ -9,16 +10,18 @@ use sqlx::postgres::PgSslMode;
 use sqlx::ConnectOptions;
 use std::convert::TryFrom;
 use std::env;
+use std::sync::Arc;
 use std::time::Duration;
 
 use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::email_client::{EmailSender, MailboxEmailClient, PostmarkEmailClient, SmtpEmailClient};
 
 #[derive(Deserialize, Clone)]
 pub struct Settings {
     pub application: ApplicationSettings,
     pub database: DatabaseSettings,
     pub email_client: EmailClientSettings,
+    pub worker: WorkerSettings,
     pub redis_uri: Secret<String>,
 }
 
@@ -40,26 +43,72 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    pub suppression_secret: Secret<String>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailBackend {
+    #[default]
+    Postmark,
+    Smtp,
+    Mailbox,
 }
 
 #[derive(Deserialize, Clone)]
 pub struct EmailClientSettings {
+    #[serde(default)]
+    pub backend: EmailBackend,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    pub smtp: Option<SmtpSettings>,
+    pub mailbox_directory: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    pub require_tls: bool,
 }
 
 impl EmailClientSettings {
-    pub fn client(&self) -> EmailClient {
+    pub fn client(&self) -> Arc<dyn EmailSender> {
         let sender_email = self.sender().expect("Invalid sender email address");
         let timeout = self.timeout();
-        EmailClient::new(
-            self.base_url.clone(),
-            sender_email,
-            self.authorization_token.clone(),
-            timeout,
-        )
+        match self.backend {
+            EmailBackend::Postmark => Arc::new(PostmarkEmailClient::new(
+                self.base_url.clone(),
+                sender_email,
+                self.authorization_token.clone(),
+                timeout,
+            )),
+            EmailBackend::Smtp => {
+                let smtp = self
+                    .smtp
+                    .as_ref()
+                    .expect("Missing `smtp` settings for the smtp email backend");
+                Arc::new(
+                    SmtpEmailClient::new(smtp, sender_email, timeout)
+                        .expect("Failed to build the SMTP email client"),
+                )
+            }
+            EmailBackend::Mailbox => {
+                let directory = self
+                    .mailbox_directory
+                    .as_ref()
+                    .expect("Missing `mailbox_directory` for the mailbox email backend");
+                Arc::new(
+                    MailboxEmailClient::new(directory, sender_email)
+                        .expect("Failed to create the mailbox directory"),
+                )
+            }
+        }
     }
 
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
@@ -71,6 +120,34 @@ impl EmailClientSettings {
     }
 }
 
+/// Retry policy of the newsletter delivery worker.
+#[derive(Deserialize, Clone)]
+pub struct WorkerSettings {
+    /// Failed deliveries are retried until they reach this many attempts,
+    /// then they are moved to `issue_delivery_failures`
+    pub max_attempts: i16,
+    /// Delay before the first retry, doubled on every following attempt
+    pub backoff_base_seconds: u64,
+    /// Upper bound for the delay between two attempts
+    pub backoff_max_seconds: u64,
+}
+
+impl WorkerSettings {
+    /// Delay before the next attempt of a delivery that already failed `n_failed_attempts` times.
+    /// The delay grows exponentially and half of it is randomised ("equal jitter"),
+    /// so that deliveries which failed together do not all retry at the same time.
+    pub fn backoff(&self, n_failed_attempts: i16) -> Duration {
+        let exponent = n_failed_attempts.saturating_sub(1).clamp(0, 31) as u32;
+        let delay = self
+            .backoff_base_seconds
+            .saturating_mul(2u64.pow(exponent))
+            .min(self.backoff_max_seconds)
+            .saturating_mul(1000);
+        let jitter = rand::thread_rng().gen_range(0..=delay / 2);
+        Duration::from_millis(delay - delay / 2 + jitter)
+    }
+}
+
 impl DatabaseSettings {
     pub fn without_db(&self) -> PgConnectOptions {
         let ssl_mode = if self.require_ssl {
@@ -165,6 +242,7 @@ impl TryFrom<Config> for Settings {
         if config.get::<ApplicationSettings>("application").is_err()
             || config.get::<DatabaseSettings>("database").is_err()
             || config.get::<EmailClientSettings>("email_client").is_err()
+            || config.get::<WorkerSettings>("worker").is_err()
             || config.get::<String>("redis_uri").is_err()
         {
             return Err(ConfigError::Message(String::from("Not enough field")));
@@ -173,7 +251,40 @@ impl TryFrom<Config> for Settings {
             application: config.get("application").unwrap(),
             database: config.get("database").unwrap(),
             email_client: config.get("email_client").unwrap(),
+            worker: config.get("worker").unwrap(),
             redis_uri: Secret::new(config.get("redis_uri").unwrap()),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::configurations::WorkerSettings;
+
+    fn settings() -> WorkerSettings {
+        WorkerSettings {
+            max_attempts: 10,
+            backoff_base_seconds: 60,
+            backoff_max_seconds: 3600,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_with_every_failed_attempt() {
+        let settings = settings();
+        for (n_failed_attempts, delay) in [(1, 60), (2, 120), (3, 240), (4, 480)] {
+            let backoff = settings.backoff(n_failed_attempts);
+            assert!(backoff >= Duration::from_secs(delay / 2));
+            assert!(backoff <= Duration::from_secs(delay));
+        }
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let backoff = settings().backoff(i16::MAX);
+        assert!(backoff >= Duration::from_secs(1800));
+        assert!(backoff <= Duration::from_secs(3600));
+    }
+}
@@ -0,0 +1,54 @@
+/// The URL-friendly name of a published issue, used in its web archive link,
+/// e.g. `/issues/our-march-update`.
+#[derive(Debug)]
+pub struct IssueSlug(String);
+
+impl IssueSlug {
+    /// Lowercase ASCII letters and digits, every other run of characters
+    /// becoming a single dash.
+    pub fn from_title(title: &str) -> Self {
+        let mut slug = String::new();
+        for c in title.chars() {
+            if c.is_ascii_alphanumeric() {
+                slug.push(c.to_ascii_lowercase());
+            } else if !slug.is_empty() && !slug.ends_with('-') {
+                slug.push('-');
+            }
+        }
+        let slug = slug.trim_end_matches('-');
+        if slug.is_empty() {
+            Self("issue".into())
+        } else {
+            Self(slug.into())
+        }
+    }
+}
+
+impl AsRef<str> for IssueSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::issue_slug::IssueSlug;
+
+    #[test]
+    fn titles_are_lowercased_and_dashed() {
+        let slug = IssueSlug::from_title("Our March Update");
+        assert_eq!(slug.as_ref(), "our-march-update");
+    }
+
+    #[test]
+    fn punctuation_is_collapsed_into_a_single_dash() {
+        let slug = IssueSlug::from_title("  Hello, world!! (part 2)  ");
+        assert_eq!(slug.as_ref(), "hello-world-part-2");
+    }
+
+    #[test]
+    fn titles_without_ascii_letters_get_a_generic_slug() {
+        let slug = IssueSlug::from_title("¡¿?!");
+        assert_eq!(slug.as_ref(), "issue");
+    }
+}
@@ -0,0 +1,156 @@
+/// The content of a newsletter issue, with placeholders that are filled in
+/// for every recipient, e.g. `Hello {{ username }}!`.
+#[derive(Debug)]
+pub struct IssueTemplate(Vec<Segment>);
+
+#[derive(Debug)]
+enum Segment {
+    Text(String),
+    Placeholder(Placeholder),
+}
+
+#[derive(Debug)]
+enum Placeholder {
+    Username,
+    UnsubscribeUrl,
+    WebViewUrl,
+}
+
+/// The values of the placeholders for a given recipient.
+pub struct TemplateContext<'a> {
+    pub username: &'a str,
+    pub unsubscribe_url: &'a str,
+    pub web_view_url: &'a str,
+}
+
+/// Values are escaped when rendering the HTML version of an issue.
+#[derive(Clone, Copy)]
+pub enum TemplateFormat {
+    Html,
+    Text,
+}
+
+impl IssueTemplate {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_owned()));
+            }
+            let after = &rest[start + 2..];
+            let end = after
+                .find("}}")
+                .ok_or_else(|| "A placeholder is missing its closing }}.".to_string())?;
+            let placeholder = match after[..end].trim() {
+                "username" => Placeholder::Username,
+                "unsubscribe_url" => Placeholder::UnsubscribeUrl,
+                "web_view_url" => Placeholder::WebViewUrl,
+                name => return Err(format!("{{{{ {} }}}} is not a known placeholder.", name)),
+            };
+            segments.push(Segment::Placeholder(placeholder));
+            rest = &after[end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_owned()));
+        }
+        Ok(Self(segments))
+    }
+
+    /// Content that is used as is, without looking for placeholders.
+    pub fn verbatim(s: &str) -> Self {
+        Self(vec![Segment::Text(s.to_owned())])
+    }
+
+    pub fn render(&self, context: &TemplateContext, format: TemplateFormat) -> String {
+        let mut rendered = String::new();
+        for segment in &self.0 {
+            let value = match segment {
+                Segment::Text(text) => {
+                    rendered.push_str(text);
+                    continue;
+                }
+                Segment::Placeholder(Placeholder::Username) => context.username,
+                Segment::Placeholder(Placeholder::UnsubscribeUrl) => context.unsubscribe_url,
+                Segment::Placeholder(Placeholder::WebViewUrl) => context.web_view_url,
+            };
+            match format {
+                TemplateFormat::Html => rendered.push_str(&htmlescape::encode_minimal(value)),
+                TemplateFormat::Text => rendered.push_str(value),
+            }
+        }
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use crate::domain::issue_template::{IssueTemplate, TemplateContext, TemplateFormat};
+
+    fn context() -> TemplateContext<'static> {
+        TemplateContext {
+            username: "Ursula & co",
+            unsubscribe_url: "https://example.com/unsubscribe?token=abc",
+            web_view_url: "https://example.com/issues/hello",
+        }
+    }
+
+    #[test]
+    fn content_without_placeholders_is_left_untouched() {
+        let template = assert_ok!(IssueTemplate::parse("<p>Hello {world}!</p>"));
+        assert_eq!(
+            template.render(&context(), TemplateFormat::Html),
+            "<p>Hello {world}!</p>"
+        );
+    }
+
+    #[test]
+    fn placeholders_are_replaced_with_the_recipient_values() {
+        let template = assert_ok!(IssueTemplate::parse(
+            "Hello {{username}}, leave at {{ unsubscribe_url }}"
+        ));
+        assert_eq!(
+            template.render(&context(), TemplateFormat::Text),
+            "Hello Ursula & co, leave at https://example.com/unsubscribe?token=abc"
+        );
+    }
+
+    #[test]
+    fn the_web_view_url_can_be_inserted() {
+        let template = assert_ok!(IssueTemplate::parse("Read it online: {{ web_view_url }}"));
+        assert_eq!(
+            template.render(&context(), TemplateFormat::Text),
+            "Read it online: https://example.com/issues/hello"
+        );
+    }
+
+    #[test]
+    fn values_are_escaped_in_html() {
+        let template = assert_ok!(IssueTemplate::parse("<p>Hello {{ username }}</p>"));
+        assert_eq!(
+            template.render(&context(), TemplateFormat::Html),
+            "<p>Hello Ursula &amp; co</p>"
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders_are_rejected() {
+        assert_err!(IssueTemplate::parse("Hello {{ password }}"));
+    }
+
+    #[test]
+    fn unclosed_placeholders_are_rejected() {
+        assert_err!(IssueTemplate::parse("Hello {{ username"));
+    }
+
+    #[test]
+    fn verbatim_content_is_not_rendered() {
+        let template = IssueTemplate::verbatim("Hello {{ password }}");
+        assert_eq!(
+            template.render(&context(), TemplateFormat::Text),
+            "Hello {{ password }}"
+        );
+    }
+}
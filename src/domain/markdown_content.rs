@@ -0,0 +1,192 @@
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag, TagEnd};
+use uuid::Uuid;
+
+/// The source of a newsletter issue written in Markdown.
+/// Both the HTML and the plain text versions of the issue are derived from it.
+#[derive(Debug)]
+pub struct MarkdownContent(String);
+
+// Email clients ignore stylesheets, every element is styled inline
+const WRAPPER_STYLE: &str = "max-width: 600px; margin: 0 auto; \
+    font-family: Helvetica, Arial, sans-serif; font-size: 16px; line-height: 1.5; color: #222222;";
+const INLINE_STYLES: [(&str, &str); 10] = [
+    ("<h1>", r#"<h1 style="font-size: 28px; margin: 0 0 16px;">"#),
+    (
+        "<h2>",
+        r#"<h2 style="font-size: 22px; margin: 24px 0 12px;">"#,
+    ),
+    (
+        "<h3>",
+        r#"<h3 style="font-size: 18px; margin: 20px 0 8px;">"#,
+    ),
+    ("<p>", r#"<p style="margin: 0 0 16px;">"#),
+    (
+        "<ul>",
+        r#"<ul style="margin: 0 0 16px; padding-left: 24px;">"#,
+    ),
+    (
+        "<ol>",
+        r#"<ol style="margin: 0 0 16px; padding-left: 24px;">"#,
+    ),
+    (
+        "<blockquote>",
+        r#"<blockquote style="margin: 0 0 16px; padding-left: 12px; border-left: 4px solid #dddddd; color: #555555;">"#,
+    ),
+    (
+        "<pre>",
+        r#"<pre style="margin: 0 0 16px; padding: 12px; background: #f4f4f4; overflow-x: auto;">"#,
+    ),
+    ("<a ", r#"<a style="color: #1a73e8;" "#),
+    ("<img ", r#"<img style="max-width: 100%;" "#),
+];
+
+impl MarkdownContent {
+    pub fn parse(s: String) -> Result<Self, String> {
+        if s.trim().is_empty() {
+            Err("The Markdown content is empty.".into())
+        } else {
+            Ok(Self(s))
+        }
+    }
+
+    /// Sanitized HTML, styled inline and wrapped in a centered layout.
+    pub fn to_html(&self) -> String {
+        // Placeholders used as link targets, e.g. `[Leave]({{unsubscribe_url}})`,
+        // would be percent-encoded along with the rest of the URL: their braces are
+        // swapped for markers that nobody can write, and put back once sanitized
+        let marker = Uuid::new_v4().simple().to_string();
+        let (open, close) = (format!("open{}", marker), format!("close{}", marker));
+        let source = self.0.replace("{{", &open).replace("}}", &close);
+        let mut unsafe_html = String::new();
+        pulldown_cmark::html::push_html(&mut unsafe_html, Parser::new(&source));
+        // Raw HTML is allowed in Markdown: scripts, event handlers, styles... are dropped
+        let mut html = ammonia::clean(&unsafe_html);
+        for (tag, styled_tag) in INLINE_STYLES {
+            html = html.replace(tag, styled_tag);
+        }
+        let html = html.replace(&open, "{{").replace(&close, "}}");
+        format!(r#"<div style="{}">{}</div>"#, WRAPPER_STYLE, html)
+    }
+
+    /// A readable plain text version: markup is dropped and links are spelled out.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let mut link_targets = Vec::new();
+        // The next number of the enclosing ordered lists, `None` for bullet lists
+        let mut lists: Vec<Option<u64>> = Vec::new();
+        for event in Parser::new(&self.0) {
+            match event {
+                Event::Text(s) | Event::Code(s) => text.push_str(&s),
+                Event::SoftBreak => text.push(' '),
+                Event::HardBreak => text.push('\n'),
+                Event::Rule => text.push_str("----------\n\n"),
+                Event::Start(Tag::Heading {
+                    level: HeadingLevel::H1,
+                    ..
+                }) => text.push_str("# "),
+                Event::Start(Tag::Heading { .. }) => text.push_str("## "),
+                Event::Start(Tag::List(first_number)) => lists.push(first_number),
+                Event::Start(Tag::Item) => {
+                    text.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                    match lists.last_mut() {
+                        Some(Some(number)) => {
+                            text.push_str(&format!("{}. ", number));
+                            *number += 1;
+                        }
+                        _ => text.push_str("- "),
+                    }
+                }
+                Event::Start(Tag::BlockQuote) => text.push_str("> "),
+                Event::Start(Tag::Link { dest_url, .. })
+                | Event::Start(Tag::Image { dest_url, .. }) => link_targets.push(dest_url),
+                Event::End(TagEnd::Link) | Event::End(TagEnd::Image) => {
+                    if let Some(dest_url) = link_targets.pop() {
+                        text.push_str(&format!(" ({})", dest_url));
+                    }
+                }
+                Event::End(TagEnd::Paragraph) | Event::End(TagEnd::Heading(_)) => {
+                    // Paragraphs of list items are not separated by blank lines
+                    text.push_str(if lists.is_empty() { "\n\n" } else { "\n" });
+                }
+                Event::End(TagEnd::Item) if !text.ends_with('\n') => text.push('\n'),
+                Event::End(TagEnd::List(_)) => {
+                    lists.pop();
+                    if lists.is_empty() {
+                        text.push('\n');
+                    }
+                }
+                Event::End(TagEnd::CodeBlock) => text.push('\n'),
+                // Raw HTML is left out of the plain text version
+                _ => {}
+            }
+        }
+        text.trim_end().to_owned()
+    }
+}
+
+impl AsRef<str> for MarkdownContent {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::assert_err;
+
+    use crate::domain::markdown_content::MarkdownContent;
+
+    fn markdown(s: &str) -> MarkdownContent {
+        MarkdownContent::parse(s.to_string()).unwrap()
+    }
+
+    #[test]
+    fn empty_content_is_rejected() {
+        assert_err!(MarkdownContent::parse(" \n".to_string()));
+    }
+
+    #[test]
+    fn html_is_styled_inline_and_wrapped_in_a_layout() {
+        let html = markdown("# Title\n\nSome *text*.").to_html();
+        assert!(html.starts_with(r#"<div style="max-width: 600px;"#));
+        assert!(html.contains(r#"<h1 style="font-size: 28px; margin: 0 0 16px;">Title</h1>"#));
+        assert!(html.contains(r#"<p style="margin: 0 0 16px;">Some <em>text</em>.</p>"#));
+    }
+
+    #[test]
+    fn dangerous_html_is_removed() {
+        let html =
+            markdown("Hello <script>alert(1)</script><img src=x onerror=alert(1)>").to_html();
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("onerror"));
+    }
+
+    #[test]
+    fn placeholders_survive_in_link_targets() {
+        let html = markdown("[Leave]({{unsubscribe_url}}), {{ username }}").to_html();
+        assert!(html.contains(r#"href="{{unsubscribe_url}}""#));
+        assert!(html.contains("{{ username }}"));
+    }
+
+    #[test]
+    fn encoded_braces_written_by_the_author_are_left_alone() {
+        let html = markdown("[Search](https://example.com/?q=%7B%7Bx%7D%7D) %7B%7B").to_html();
+        assert!(html.contains(r#"href="https://example.com/?q=%7B%7Bx%7D%7D""#));
+        assert!(html.contains("%7B%7B"));
+        assert!(!html.contains("{{"));
+    }
+
+    #[test]
+    fn the_text_version_drops_the_markup_and_spells_out_links() {
+        let text = markdown(
+            "# Title\n\nHello **there**, read [the post](https://example.com).\n\n\
+            - first\n- second\n\n1. one\n2. two\n\nThe end.",
+        )
+        .to_text();
+        assert_eq!(
+            text,
+            "# Title\n\nHello there, read the post (https://example.com).\n\n\
+            - first\n- second\n\n1. one\n2. two\n\nThe end."
+        );
+    }
+}
@@ -1,7 +1,15 @@
+mod issue_slug;
+mod issue_template;
+mod markdown_content;
 mod new_subscriber;
 mod subscriber_email;
+mod subscriber_import;
 mod subscriber_username;
 
+pub use issue_slug::IssueSlug;
+pub use issue_template::{IssueTemplate, TemplateContext, TemplateFormat};
+pub use markdown_content::MarkdownContent;
 pub use new_subscriber::NewSubscriber;
 pub use subscriber_email::SubscriberEmail;
+pub use subscriber_import::SubscriberImport;
 pub use subscriber_username::SubscriberUsername;
@@ -0,0 +1,199 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::domain::{SubscriberEmail, SubscriberUsername};
+
+/// A subscriber read from an imported list.
+#[derive(Debug)]
+pub struct ImportedSubscriber {
+    /// The line of the CSV file, to report problems found later on
+    pub line: u64,
+    pub email: SubscriberEmail,
+    pub username: SubscriberUsername,
+    /// The columns other than `email` and `username`, empty values left out
+    pub custom_fields: BTreeMap<String, String>,
+}
+
+/// A line of an imported list that cannot be imported.
+#[derive(Debug, PartialEq)]
+pub struct ImportError {
+    pub line: u64,
+    pub message: String,
+}
+
+/// The outcome of reading a list of subscribers, see `SubscriberImport::parse`.
+#[derive(Debug, Default)]
+pub struct SubscriberImport {
+    pub subscribers: Vec<ImportedSubscriber>,
+    pub errors: Vec<ImportError>,
+}
+
+impl SubscriberImport {
+    /// Read a CSV list whose first line names the columns. `email` and `username`
+    /// are required, the other columns are kept as custom fields.
+    /// Every line is checked, so that all the problems can be fixed at once.
+    pub fn parse(csv: &str) -> Self {
+        let mut import = Self::default();
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(csv.as_bytes());
+        let headers = match reader.headers() {
+            Ok(headers) => headers.clone(),
+            Err(e) => {
+                import.error(1, e.to_string());
+                return import;
+            }
+        };
+        let column = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+        let (email_column, username_column) = match (column("email"), column("username")) {
+            (Some(email), Some(username)) => (email, username),
+            _ => {
+                import.error(
+                    1,
+                    "The first line must name the email and username columns.",
+                );
+                return import;
+            }
+        };
+
+        // Addresses are compared regardless of their case to catch duplicates
+        let mut seen = HashSet::new();
+        for record in reader.records() {
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    let line = e.position().map_or(0, |p| p.line());
+                    let message = match e.kind() {
+                        csv::ErrorKind::UnequalLengths {
+                            expected_len, len, ..
+                        } => format!("Expected {} fields, found {}.", expected_len, len),
+                        _ => e.to_string(),
+                    };
+                    import.error(line, message);
+                    continue;
+                }
+            };
+            let line = record.position().map_or(0, |p| p.line());
+            let email = match SubscriberEmail::parse(record[email_column].to_string()) {
+                Ok(email) => email,
+                Err(e) => {
+                    import.error(line, e);
+                    continue;
+                }
+            };
+            let username = match SubscriberUsername::parse(record[username_column].to_string()) {
+                Ok(username) => username,
+                Err(e) => {
+                    import.error(line, e);
+                    continue;
+                }
+            };
+            if !seen.insert(email.as_ref().to_lowercase()) {
+                import.error(line, format!("{} is listed more than once.", email));
+                continue;
+            }
+            let custom_fields = headers
+                .iter()
+                .zip(record.iter())
+                .enumerate()
+                .filter(|(i, (_, value))| {
+                    *i != email_column && *i != username_column && !value.is_empty()
+                })
+                .map(|(_, (name, value))| (name.to_string(), value.to_string()))
+                .collect();
+            import.subscribers.push(ImportedSubscriber {
+                line,
+                email,
+                username,
+                custom_fields,
+            });
+        }
+        import
+    }
+
+    /// Reject the line of an already known subscriber.
+    pub fn reject(&mut self, line: u64, message: impl Into<String>) {
+        self.subscribers.retain(|s| s.line != line);
+        self.error(line, message);
+        self.errors.sort_by_key(|e| e.line);
+    }
+
+    fn error(&mut self, line: u64, message: impl Into<String>) {
+        self.errors.push(ImportError {
+            line,
+            message: message.into(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::subscriber_import::{ImportError, SubscriberImport};
+
+    #[test]
+    fn every_valid_line_is_imported_with_its_custom_fields() {
+        let import = SubscriberImport::parse(
+            "email,username,company\n\
+            ursula@example.com,Ursula Le Guin,Earthsea Ltd\n\
+             pat@example.com , Pat ,\n",
+        );
+        assert!(import.errors.is_empty());
+        assert_eq!(import.subscribers.len(), 2);
+        let ursula = &import.subscribers[0];
+        assert_eq!(ursula.line, 2);
+        assert_eq!(ursula.email.as_ref(), "ursula@example.com");
+        assert_eq!(ursula.username.as_ref(), "Ursula Le Guin");
+        assert_eq!(ursula.custom_fields["company"], "Earthsea Ltd");
+        let pat = &import.subscribers[1];
+        assert_eq!(pat.email.as_ref(), "pat@example.com");
+        assert_eq!(pat.username.as_ref(), "Pat");
+        assert!(pat.custom_fields.is_empty());
+    }
+
+    #[test]
+    fn the_columns_can_come_in_any_order() {
+        let import = SubscriberImport::parse("Username,Email\nUrsula,ursula@example.com\n");
+        assert!(import.errors.is_empty());
+        assert_eq!(import.subscribers[0].email.as_ref(), "ursula@example.com");
+    }
+
+    #[test]
+    fn the_email_and_username_columns_are_required() {
+        let import = SubscriberImport::parse("email,name\nursula@example.com,Ursula\n");
+        assert!(import.subscribers.is_empty());
+        assert_eq!(import.errors.len(), 1);
+        assert_eq!(import.errors[0].line, 1);
+    }
+
+    #[test]
+    fn every_invalid_line_is_reported() {
+        let import = SubscriberImport::parse(
+            "email,username\n\
+            not-an-email,Ursula\n\
+            pat@example.com,Pat\n\
+            sam@example.com,<script>\n\
+            lee@example.com\n\
+            PAT@example.com,Pat again\n",
+        );
+        assert_eq!(import.subscribers.len(), 1);
+        let lines: Vec<u64> = import.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![2, 4, 5, 6]);
+        assert_eq!(
+            import.errors[2],
+            ImportError {
+                line: 5,
+                message: "Expected 2 fields, found 1.".into()
+            }
+        );
+    }
+
+    #[test]
+    fn rejected_lines_are_reported_in_order() {
+        let mut import = SubscriberImport::parse(
+            "email,username\nnot-an-email,Ursula\npat@example.com,Pat\nsam@example.com,Sam\n",
+        );
+        import.reject(3, "pat@example.com is already subscribed.");
+        assert_eq!(import.subscribers.len(), 1);
+        let lines: Vec<u64> = import.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![2, 3]);
+    }
+}
@@ -0,0 +1,96 @@
+use std::path::Path;
+
+use anyhow::Context;
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+
+use super::{build_message, EmailHeader, EmailSender};
+use crate::domain::SubscriberEmail;
+
+/// Writes every email as an `.eml` file into a local directory instead of sending it.
+/// Handy for local development: open the files with any mail client.
+pub struct MailboxEmailClient {
+    transport: AsyncFileTransport<Tokio1Executor>,
+    sender: SubscriberEmail,
+}
+
+impl MailboxEmailClient {
+    pub fn new(directory: impl AsRef<Path>, sender: SubscriberEmail) -> std::io::Result<Self> {
+        std::fs::create_dir_all(directory.as_ref())?;
+        Ok(Self {
+            transport: AsyncFileTransport::new(directory),
+            sender,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for MailboxEmailClient {
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<(), anyhow::Error> {
+        let message = build_message(
+            &self.sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            headers,
+        )?;
+        let id = self
+            .transport
+            .send(message)
+            .await
+            .context("Failed to write the email to the mailbox directory")?;
+        tracing::info!(email_id = %id, "Email written to the mailbox directory");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::assert_ok;
+    use fake::{faker::internet::en::SafeEmail, Fake};
+    use uuid::Uuid;
+
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{EmailSender, MailboxEmailClient},
+    };
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_writes_an_eml_file_into_the_directory() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let email_client = MailboxEmailClient::new(&directory, email()).unwrap();
+        let recipient = email();
+
+        let outcome = email_client
+            .send_email(
+                &recipient,
+                "Newsletter title",
+                "<p>Newsletter body as HTML</p>",
+                "Newsletter body as plain text",
+            )
+            .await;
+
+        assert_ok!(outcome);
+        let files: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        let content = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(content.contains("Subject: Newsletter title"));
+        assert!(content.contains(&format!("To: {}", recipient.as_ref())));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,138 @@
+mod mailbox;
+mod postmark;
+mod smtp;
+
+use anyhow::Context;
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::Message;
+use serde::Serialize;
+
+pub use mailbox::MailboxEmailClient;
+pub use postmark::{PostmarkEmailClient, MAX_BATCH_SIZE};
+pub use smtp::SmtpEmailClient;
+
+use crate::domain::SubscriberEmail;
+
+/// Everything that sends emails (confirmation emails, newsletter issues, ...)
+/// goes through this trait, so that the delivery backend can be picked from the
+/// configuration.
+#[async_trait::async_trait]
+pub trait EmailSender: Send + Sync {
+    /// Same as `send_email`, but also sets the given custom headers on the message.
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<(), anyhow::Error>;
+
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.send_email_with_headers(recipient, subject, html_content, text_content, &[])
+            .await
+    }
+
+    /// Send several emails at once.
+    /// The outcome of every email is reported individually, in the same order as `emails`,
+    /// along with the id the provider gave to the message, if any.
+    /// Backends without a batch API send them one by one.
+    async fn send_batch(
+        &self,
+        emails: &[OutgoingEmail],
+    ) -> Vec<Result<Option<String>, anyhow::Error>> {
+        let mut outcomes = Vec::with_capacity(emails.len());
+        for email in emails {
+            let outcome = self
+                .send_email_with_headers(
+                    &email.recipient,
+                    &email.subject,
+                    &email.html_content,
+                    &email.text_content,
+                    &email.headers,
+                )
+                .await;
+            outcomes.push(outcome.map(|_| None));
+        }
+        outcomes
+    }
+}
+
+/// The provider refused the email for good (e.g. the recipient is inactive or
+/// the address does not exist): retrying the delivery would not help.
+/// Backends return it wrapped in an `anyhow::Error`, see `is_permanent_failure`.
+#[derive(thiserror::Error, Debug)]
+#[error("The email was permanently rejected: {0}")]
+pub struct PermanentFailure(pub String);
+
+/// Whether a delivery error is worth retrying or not.
+pub fn is_permanent_failure(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.is::<PermanentFailure>())
+}
+
+/// A single email of a batch, see `EmailSender::send_batch`.
+pub struct OutgoingEmail {
+    pub recipient: SubscriberEmail,
+    pub subject: String,
+    pub html_content: String,
+    pub text_content: String,
+    pub headers: Vec<EmailHeader>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader {
+    name: String,
+    value: String,
+}
+
+impl EmailHeader {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
+
+/// Build a MIME message with both an HTML and a plain text alternative.
+/// Shared by the backends that speak SMTP / RFC 5322 rather than an HTTP API.
+fn build_message(
+    sender: &SubscriberEmail,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+    headers: &[EmailHeader],
+) -> Result<Message, anyhow::Error> {
+    let mut builder = Message::builder()
+        .from(
+            sender
+                .as_ref()
+                .parse::<Mailbox>()
+                .context("Failed to parse the sender address")?,
+        )
+        .to(recipient
+            .as_ref()
+            .parse::<Mailbox>()
+            .context("Failed to parse the recipient address")?)
+        .subject(subject);
+    for header in headers {
+        let name = HeaderName::new_from_ascii(header.name.clone())
+            .with_context(|| format!("Invalid header name {}", header.name))?;
+        builder = builder.raw_header(HeaderValue::new(name, header.value.clone()));
+    }
+    builder
+        .multipart(MultiPart::alternative_plain_html(
+            text_content.to_owned(),
+            html_content.to_owned(),
+        ))
+        .context("Failed to build the email message")
+}
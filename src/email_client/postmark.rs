@@ -0,0 +1,625 @@
+use std::time::Duration;
+
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use super::{EmailHeader, EmailSender, OutgoingEmail, PermanentFailure};
+use crate::domain::SubscriberEmail;
+
+/// Postmark rejects batches with more than 500 messages.
+pub const MAX_BATCH_SIZE: usize = 500;
+
+/// Postmark API error codes that reject the email itself rather than the request,
+/// see https://postmarkapp.com/developer/api/overview#error-codes
+/// 300: Invalid email request, 406: Inactive recipient
+const PERMANENT_ERROR_CODES: [i64; 2] = [300, 406];
+
+/// Delivers emails through Postmark's `/email` and `/email/batch` HTTP APIs.
+pub struct PostmarkEmailClient {
+    base_url: String,
+    http_client: reqwest::Client,
+    sender: SubscriberEmail,
+    authorization_token: Secret<String>,
+}
+
+impl PostmarkEmailClient {
+    pub fn new(
+        base_url: String,
+        sender: SubscriberEmail,
+        authorization_token: Secret<String>,
+        timout: Duration,
+    ) -> Self {
+        Self {
+            base_url,
+            http_client: Client::builder()
+                // Remember to always set the timeout value for every request to prevent
+                // socker / performance degradation
+                .timeout(timout)
+                .build()
+                .unwrap(),
+            sender,
+            authorization_token,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for PostmarkEmailClient {
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<(), anyhow::Error> {
+        let url = format!("{}/email", self.base_url);
+        let body = SendEmailRequest {
+            from: self.sender.as_ref(),
+            to: recipient.as_ref(),
+            subject,
+            html_body: html_content,
+            text_body: text_content,
+            headers,
+        };
+        let response = self
+            .http_client
+            .post(url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&body)
+            .send()
+            .await?; // Since send always return Ok(()) when it receives a valid response from server
+
+        // Postmark answers 422 with an error code when it refuses the email.
+        // Only the codes about the recipient are permanent: a bad server token (401),
+        // an unconfirmed sender signature or a rate limit would reject every email
+        // until someone fixes it, so those are retried like any other failure.
+        let status = response.status();
+        if status == reqwest::StatusCode::UNPROCESSABLE_ENTITY {
+            let message = response.text().await.unwrap_or_default();
+            let error = format!("Postmark answered {}: {}", status, message);
+            return match serde_json::from_str::<ErrorResponse>(&message) {
+                Ok(body) if PERMANENT_ERROR_CODES.contains(&body.error_code) => {
+                    Err(PermanentFailure(error).into())
+                }
+                _ => Err(anyhow::anyhow!(error)),
+            };
+        }
+        response.error_for_status()?; // We have to use this method to receive Err when the server responses with code 500
+        Ok(())
+    }
+
+    async fn send_batch(
+        &self,
+        emails: &[OutgoingEmail],
+    ) -> Vec<Result<Option<String>, anyhow::Error>> {
+        let mut outcomes = Vec::with_capacity(emails.len());
+        for chunk in emails.chunks(MAX_BATCH_SIZE) {
+            match self.send_batch_request(chunk).await {
+                Ok(chunk_outcomes) => outcomes.extend(chunk_outcomes),
+                // The whole request failed: none of the emails in the chunk went out
+                Err(e) => {
+                    let message = format!("{:#}", e);
+                    outcomes.extend(chunk.iter().map(|_| Err(anyhow::anyhow!(message.clone()))));
+                }
+            }
+        }
+        outcomes
+    }
+}
+
+impl PostmarkEmailClient {
+    async fn send_batch_request(
+        &self,
+        emails: &[OutgoingEmail],
+    ) -> Result<Vec<Result<Option<String>, anyhow::Error>>, anyhow::Error> {
+        let url = format!("{}/email/batch", self.base_url);
+        let body: Vec<SendEmailRequest> = emails
+            .iter()
+            .map(|email| SendEmailRequest {
+                from: self.sender.as_ref(),
+                to: email.recipient.as_ref(),
+                subject: &email.subject,
+                html_body: &email.html_content,
+                text_body: &email.text_content,
+                headers: &email.headers,
+            })
+            .collect();
+        // Postmark answers 200 as long as the batch itself is valid,
+        // every message gets its own entry (and error code) in the response
+        let responses: Vec<BatchResponseEntry> = self
+            .http_client
+            .post(url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if responses.len() != emails.len() {
+            anyhow::bail!(
+                "Postmark returned {} results for a batch of {} emails",
+                responses.len(),
+                emails.len()
+            );
+        }
+        Ok(responses
+            .into_iter()
+            .map(|response| match response.error_code {
+                0 => Ok(response.message_id),
+                code if PERMANENT_ERROR_CODES.contains(&code) => Err(PermanentFailure(format!(
+                    "Postmark rejected the email (error code {}): {}",
+                    code, response.message
+                ))
+                .into()),
+                code => Err(anyhow::anyhow!(
+                    "Postmark rejected the email (error code {}): {}",
+                    code,
+                    response.message
+                )),
+            })
+            .collect())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    // Postmark expects custom headers as a list of `{"Name": ..., "Value": ...}` objects
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader],
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ErrorResponse {
+    error_code: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BatchResponseEntry {
+    error_code: i64,
+    message: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use claims::{assert_err, assert_ok};
+    use fake::{
+        faker::{
+            internet::en::SafeEmail,
+            lorem::en::{Paragraph, Sentence},
+        },
+        Fake, Faker,
+    };
+    use secrecy::Secret;
+    use wiremock::{
+        matchers::{any, header, header_exists, method, path},
+        Match, Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{
+            is_permanent_failure, EmailHeader, EmailSender, OutgoingEmail, PostmarkEmailClient,
+        },
+    };
+
+    /// Generate a random email subject
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+    /// Generate a random email content
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+    /// Generate a random subscriber email
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+    /// Get a test instance of `PostmarkEmailClient`.
+    fn email_client(base_url: String) -> PostmarkEmailClient {
+        PostmarkEmailClient::new(
+            base_url,
+            email(),
+            Secret::new(Faker.fake()),
+            Duration::from_millis(200),
+        )
+    }
+
+    /// Generate a random email to be sent as part of a batch
+    fn outgoing_email() -> OutgoingEmail {
+        OutgoingEmail {
+            recipient: email(),
+            subject: subject(),
+            html_content: content(),
+            text_content: content(),
+            headers: vec![],
+        }
+    }
+
+    struct SendEmailRequestMatcher;
+
+    impl Match for SendEmailRequestMatcher {
+        fn matches(&self, request: &wiremock::Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailRequestMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+    }
+
+    struct CustomHeadersMatcher;
+
+    impl Match for CustomHeadersMatcher {
+        fn matches(&self, request: &wiremock::Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                body["Headers"]
+                    == serde_json::json!([
+                        {"Name": "List-Unsubscribe", "Value": "<https://example.com/unsubscribe>"},
+                        {"Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click"}
+                    ])
+            } else {
+                false
+            }
+        }
+    }
+
+    struct NoCustomHeadersMatcher;
+
+    impl Match for NoCustomHeadersMatcher {
+        fn matches(&self, request: &wiremock::Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                body.get("Headers").is_none()
+            } else {
+                false
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_with_headers_sends_the_custom_headers() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailRequestMatcher)
+            .and(CustomHeadersMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let headers = [
+            EmailHeader::new("List-Unsubscribe", "<https://example.com/unsubscribe>"),
+            EmailHeader::new("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+        ];
+        let _ = email_client
+            .send_email_with_headers(&email(), &subject(), &content(), &content(), &headers)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_send_an_empty_headers_list() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(NoCustomHeadersMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let response = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(response);
+    }
+
+    #[tokio::test]
+    async fn send_email_err_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let response = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(response);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_permanently_if_the_server_returns_422() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "ErrorCode": 406,
+                "Message": "You tried to send to a recipient that has been marked as inactive."
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let response = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert!(is_permanent_failure(&response.unwrap_err()));
+    }
+
+    #[tokio::test]
+    async fn send_email_failure_is_transient_if_the_server_token_is_rejected() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(401).set_body_json(serde_json::json!({
+                "ErrorCode": 10,
+                "Message": "The Server Token you provided in the X-Postmark-Server-Token request header was invalid."
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let response = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert!(!is_permanent_failure(&response.unwrap_err()));
+    }
+
+    #[tokio::test]
+    async fn send_email_failure_is_transient_if_postmark_rejects_the_account() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "ErrorCode": 400,
+                "Message": "The 'From' address you supplied is not a Sender Signature on your account."
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let response = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert!(!is_permanent_failure(&response.unwrap_err()));
+    }
+
+    #[tokio::test]
+    async fn send_email_failure_is_transient_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let response = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert!(!is_permanent_failure(&response.unwrap_err()));
+    }
+
+    #[tokio::test]
+    async fn send_email_takes_too_long_to_response() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(3)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let response = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(response);
+    }
+
+    struct BatchRequestMatcher(usize);
+
+    impl Match for BatchRequestMatcher {
+        fn matches(&self, request: &wiremock::Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            match result {
+                Ok(serde_json::Value::Array(messages)) => {
+                    messages.len() == self.0
+                        && messages.iter().all(|body| {
+                            body.get("From").is_some()
+                                && body.get("To").is_some()
+                                && body.get("Subject").is_some()
+                                && body.get("HtmlBody").is_some()
+                                && body.get("TextBody").is_some()
+                        })
+                }
+                _ => false,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn send_batch_sends_all_emails_in_a_single_request() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .and(BatchRequestMatcher(3))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {"ErrorCode": 0, "Message": "OK", "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817"},
+                {"ErrorCode": 0, "Message": "OK", "MessageID": "e2ecbbfc-fe12-463d-b933-9fe22915106d"},
+                {"ErrorCode": 0, "Message": "OK", "MessageID": "0b4e6e6b-2a17-4a21-b4f6-e5a2d7fb1a8c"}
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcomes = email_client
+            .send_batch(&[outgoing_email(), outgoing_email(), outgoing_email()])
+            .await;
+
+        assert_eq!(outcomes.len(), 3);
+        assert_eq!(
+            outcomes[0].as_ref().unwrap().as_deref(),
+            Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+        );
+        assert!(outcomes.iter().all(|outcome| outcome.is_ok()));
+    }
+
+    #[tokio::test]
+    async fn send_batch_reports_the_outcome_of_each_email() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {"ErrorCode": 0, "Message": "OK"},
+                {"ErrorCode": 406, "Message": "You tried to send to an inactive recipient."}
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcomes = email_client
+            .send_batch(&[outgoing_email(), outgoing_email()])
+            .await;
+
+        assert_ok!(&outcomes[0]);
+        assert!(is_permanent_failure(outcomes[1].as_ref().unwrap_err()));
+    }
+
+    #[tokio::test]
+    async fn send_batch_failures_are_transient_unless_postmark_rejects_the_email() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {"ErrorCode": 100, "Message": "Maintenance"}
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcomes = email_client.send_batch(&[outgoing_email()]).await;
+
+        assert!(!is_permanent_failure(outcomes[0].as_ref().unwrap_err()));
+    }
+
+    #[tokio::test]
+    async fn send_batch_fails_every_email_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcomes = email_client
+            .send_batch(&[outgoing_email(), outgoing_email()])
+            .await;
+
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes.iter().all(|outcome| outcome.is_err()));
+    }
+}
@@ -0,0 +1,183 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use secrecy::ExposeSecret;
+
+use super::{build_message, EmailHeader, EmailSender, PermanentFailure};
+use crate::configurations::SmtpSettings;
+use crate::domain::SubscriberEmail;
+
+/// Delivers emails to an SMTP relay.
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: SubscriberEmail,
+}
+
+impl SmtpEmailClient {
+    pub fn new(
+        settings: &SmtpSettings,
+        sender: SubscriberEmail,
+        timeout: Duration,
+    ) -> Result<Self, anyhow::Error> {
+        let mut builder = if settings.require_tls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)
+                .context("Failed to set up a TLS connection to the SMTP relay")?
+        } else {
+            // Plain text connections are only meant for local SMTP sinks (e.g. MailHog)
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+        };
+        builder = builder.port(settings.port).timeout(Some(timeout));
+        if let (Some(username), Some(password)) = (&settings.username, &settings.password) {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                password.expose_secret().clone(),
+            ));
+        }
+        Ok(Self {
+            transport: builder.build(),
+            sender,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for SmtpEmailClient {
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<(), anyhow::Error> {
+        let message = build_message(
+            &self.sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            headers,
+        )?;
+        match self.transport.send(message).await {
+            Ok(_) => Ok(()),
+            // 5xx replies: the relay refused this email for good
+            Err(e) if e.is_permanent() => Err(PermanentFailure(e.to_string()).into()),
+            Err(e) => Err(e).context("The SMTP relay did not accept the email"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use claims::assert_ok;
+    use fake::{faker::internet::en::SafeEmail, Fake};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    use crate::{
+        configurations::SmtpSettings,
+        domain::SubscriberEmail,
+        email_client::{is_permanent_failure, EmailHeader, EmailSender, SmtpEmailClient},
+    };
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(port: u16) -> SmtpEmailClient {
+        let settings = SmtpSettings {
+            host: "127.0.0.1".into(),
+            port,
+            username: None,
+            password: None,
+            require_tls: false,
+        };
+        SmtpEmailClient::new(&settings, email(), Duration::from_secs(2)).unwrap()
+    }
+
+    /// A bare-bones SMTP sink accepting a single message.
+    /// It answers `rcpt_reply` to `RCPT TO` and returns the raw DATA it received.
+    async fn spawn_smtp_sink(rcpt_reply: &'static str) -> (u16, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut data = String::new();
+            let mut in_data = false;
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        writer.write_all(b"250 OK\r\n").await.unwrap();
+                    } else {
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    continue;
+                }
+                let reply = match line.get(..4).unwrap_or_default().to_uppercase().as_str() {
+                    "EHLO" | "HELO" => "250 localhost\r\n",
+                    "RCPT" => rcpt_reply,
+                    "DATA" => {
+                        in_data = true;
+                        "354 End data with <CR><LF>.<CR><LF>\r\n"
+                    }
+                    "QUIT" => {
+                        writer.write_all(b"221 Bye\r\n").await.unwrap();
+                        break;
+                    }
+                    _ => "250 OK\r\n",
+                };
+                writer.write_all(reply.as_bytes()).await.unwrap();
+            }
+            data
+        });
+        (port, handle)
+    }
+
+    #[tokio::test]
+    async fn send_email_delivers_the_message_to_the_smtp_relay() {
+        let (port, sink) = spawn_smtp_sink("250 OK\r\n").await;
+        let email_client = email_client(port);
+
+        let outcome = email_client
+            .send_email_with_headers(
+                &email(),
+                "Newsletter title",
+                "<p>Newsletter body as HTML</p>",
+                "Newsletter body as plain text",
+                &[EmailHeader::new(
+                    "List-Unsubscribe",
+                    "<https://example.com/unsubscribe>",
+                )],
+            )
+            .await;
+
+        assert_ok!(outcome);
+        let data = sink.await.unwrap();
+        assert!(data.contains("Subject: Newsletter title"));
+        assert!(data.contains("List-Unsubscribe: <https://example.com/unsubscribe>"));
+        assert!(data.contains("Newsletter body as plain text"));
+        assert!(data.contains("<p>Newsletter body as HTML</p>"));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_permanently_if_the_relay_rejects_the_recipient() {
+        let (port, _sink) = spawn_smtp_sink("550 No such user\r\n").await;
+        let email_client = email_client(port);
+
+        let outcome = email_client
+            .send_email(&email(), "Subject", "<p>Body</p>", "Body")
+            .await;
+
+        assert!(is_permanent_failure(&outcome.unwrap_err()));
+    }
+}
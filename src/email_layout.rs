@@ -0,0 +1,173 @@
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// The branding wrapped around the emails we send, managed from the admin area.
+pub struct EmailLayout {
+    pub header_html: String,
+    pub footer_html: String,
+    pub css: String,
+    pub company_address: String,
+}
+
+impl EmailLayout {
+    pub fn wrap_html(&self, body: &str) -> String {
+        format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<style>{css}</style>
+</head>
+<body>
+<div class="email-header">{header_html}</div>
+<div class="email-body">{body}</div>
+<div class="email-footer">{footer_html}<p class="email-address">{company_address}</p></div>
+</body>
+</html>"#,
+            css = self.css,
+            header_html = self.header_html,
+            footer_html = self.footer_html,
+            company_address =
+                htmlescape::encode_minimal(&self.company_address).replace('\n', "<br>"),
+        )
+    }
+
+    pub fn wrap_text(&self, body: &str) -> String {
+        format!("{}\n\n--\n{}", body, self.company_address)
+    }
+}
+
+/// Wrap both versions of an email in the layout, if any.
+pub fn apply_layout(
+    layout: Option<&EmailLayout>,
+    html_content: String,
+    text_content: String,
+) -> (String, String) {
+    match layout {
+        Some(layout) => (
+            layout.wrap_html(&html_content),
+            layout.wrap_text(&text_content),
+        ),
+        None => (html_content, text_content),
+    }
+}
+
+/// Mailbox providers show the beginning of an email next to its subject.
+/// The preheader replaces it: it comes first in the body, before the layout
+/// header, and is hidden once the email is opened.
+pub fn with_preheader(html_content: String, preheader: &str) -> String {
+    let preheader = format!(
+        r#"<div style="display: none; max-height: 0; overflow: hidden;">{}</div>"#,
+        htmlescape::encode_minimal(preheader)
+    );
+    match html_content.find("<body>") {
+        Some(start) => {
+            let end = start + "<body>".len();
+            format!(
+                "{}{}{}",
+                &html_content[..end],
+                preheader,
+                &html_content[end..]
+            )
+        }
+        None => format!("{}{}", preheader, html_content),
+    }
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_email_layout(
+    pool: &PgPool,
+    email_template_id: Uuid,
+) -> Result<Option<EmailLayout>, anyhow::Error> {
+    let layout = sqlx::query_as!(
+        EmailLayout,
+        r#"
+        SELECT header_html, footer_html, css, company_address
+        FROM email_templates
+        WHERE email_template_id = $1
+    "#,
+        email_template_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the email layout")?;
+    Ok(layout)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_default_email_layout(pool: &PgPool) -> Result<Option<EmailLayout>, anyhow::Error> {
+    let layout = sqlx::query_as!(
+        EmailLayout,
+        r#"
+        SELECT header_html, footer_html, css, company_address
+        FROM email_templates
+        WHERE is_default
+    "#
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the default email layout")?;
+    Ok(layout)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_default_email_layout_id(pool: &PgPool) -> Result<Option<Uuid>, anyhow::Error> {
+    let record = sqlx::query!("SELECT email_template_id FROM email_templates WHERE is_default")
+        .fetch_optional(pool)
+        .await
+        .context("Failed to retrieve the default email layout")?;
+    Ok(record.map(|r| r.email_template_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::email_layout::{apply_layout, with_preheader, EmailLayout};
+
+    fn layout() -> EmailLayout {
+        EmailLayout {
+            header_html: "<h1>ACME news</h1>".into(),
+            footer_html: "<p>Thanks for reading!</p>".into(),
+            css: "body { color: #222222; }".into(),
+            company_address: "ACME Corp.\n1 Main Street, Springfield".into(),
+        }
+    }
+
+    #[test]
+    fn the_html_version_is_wrapped_with_the_branding_and_the_company_address() {
+        let (html, _) = apply_layout(Some(&layout()), "<p>Body</p>".into(), "Body".into());
+        assert!(html.contains("<style>body { color: #222222; }</style>"));
+        assert!(html.contains(r#"<div class="email-header"><h1>ACME news</h1></div>"#));
+        assert!(html.contains(r#"<div class="email-body"><p>Body</p></div>"#));
+        assert!(html.contains("ACME Corp.<br>1 Main Street, Springfield"));
+    }
+
+    #[test]
+    fn the_text_version_ends_with_the_company_address() {
+        let (_, text) = apply_layout(Some(&layout()), "<p>Body</p>".into(), "Body".into());
+        assert_eq!(text, "Body\n\n--\nACME Corp.\n1 Main Street, Springfield");
+    }
+
+    #[test]
+    fn the_preheader_comes_before_the_layout_header() {
+        let (html, _) = apply_layout(Some(&layout()), "<p>Body</p>".into(), "Body".into());
+        let html = with_preheader(html, "In this issue: A & B");
+        let preheader = html.find("In this issue: A &amp; B").unwrap();
+        assert!(html.find("<body>").unwrap() < preheader);
+        assert!(preheader < html.find("ACME news").unwrap());
+    }
+
+    #[test]
+    fn the_preheader_is_prepended_to_content_without_layout() {
+        let html = with_preheader("<p>Body</p>".into(), "In this issue");
+        assert!(html.starts_with(r#"<div style="display: none;"#));
+        assert!(html.ends_with("In this issue</div><p>Body</p>"));
+    }
+
+    #[test]
+    fn emails_without_layout_are_left_untouched() {
+        let (html, text) = apply_layout(None, "<p>Body</p>".into(), "Body".into());
+        assert_eq!(html, "<p>Body</p>");
+        assert_eq!(text, "Body");
+    }
+}
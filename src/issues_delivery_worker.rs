@@ -1,26 +1,54 @@
-use std::time::Duration;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use sqlx::{PgPool, Postgres, Transaction};
-use tracing::{field::display, Span};
+use tracing::Span;
 use uuid::Uuid;
 
 use crate::{
-    configurations::Settings, domain::SubscriberEmail, email_client::EmailClient,
+    configurations::{Settings, WorkerSettings},
+    domain::{IssueTemplate, SubscriberEmail, TemplateContext, TemplateFormat},
+    email_client::{is_permanent_failure, EmailHeader, EmailSender, OutgoingEmail, MAX_BATCH_SIZE},
+    email_layout::{
+        apply_layout, get_default_email_layout, get_email_layout, with_preheader, EmailLayout,
+    },
+    routes::{
+        generate_subscription_token, list_unsubscribe_link, preferences_link,
+        send_confirmation_email, store_token, web_view_link,
+    },
     startup::get_connection_pool,
 };
 
+// Confirmation emails are sent one by one, they should not hold up the issues for long
+const CONFIRMATION_EMAILS_PER_RUN: usize = 20;
+
 pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration);
 
     let email_client = configuration.email_client.client();
 
-    worker_loop(connection_pool, email_client).await
+    worker_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+        configuration.worker,
+    )
+    .await
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
+async fn worker_loop(
+    pool: PgPool,
+    email_client: Arc<dyn EmailSender>,
+    base_url: String,
+    settings: WorkerSettings,
+) -> Result<(), anyhow::Error> {
     loop {
-        match try_execute_task(&pool, &email_client).await {
+        match try_execute_task(&pool, email_client.as_ref(), &base_url, &settings).await {
             Ok(ExecutionOutcome::EmptyQueue) => {
                 tokio::time::sleep(Duration::from_secs(10)).await;
             }
@@ -37,129 +65,554 @@ pub enum ExecutionOutcome {
     EmptyQueue,
 }
 
-#[tracing::instrument(
-    skip_all,
-    fields(
-        newsletter_issue_id=tracing::field::Empty,
-        subscriber_email=tracing::field::Empty
-    ),
-    err
-)]
+#[tracing::instrument(skip_all, fields(n_tasks = tracing::field::Empty), err)]
 pub async fn try_execute_task(
     pool: &PgPool,
-    email_client: &EmailClient,
+    email_client: &dyn EmailSender,
+    base_url: &str,
+    settings: &WorkerSettings,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    if let Some((mut transaction, newsletter_issue_id, subscriber_email)) =
-        dequeue_task(pool).await?
-    {
-        Span::current()
-            .record("newsletter_issue_id", &display(newsletter_issue_id))
-            .record("subscriber_email", &display(&subscriber_email));
-
-        let newsletter_issue = get_issue(pool, &newsletter_issue_id).await?;
-
-        match SubscriberEmail::parse(subscriber_email) {
-            Ok(subscriber) => {
-                if let Err(_) = email_client
-                    .send_email(
-                        &subscriber,
-                        &newsletter_issue.title,
-                        &newsletter_issue.html_content,
-                        &newsletter_issue.text_content,
-                    )
-                    .await
-                    .context(format!(
-                        "Failed to send newsletter issue to {}",
-                        subscriber.as_ref()
-                    ))
-                {
-                    update_task_retry(&mut transaction, &newsletter_issue_id, subscriber.as_ref())
-                        .await
-                        .context("Failed to update task retries")?;
-                }
-                delete_task(&mut transaction, &newsletter_issue_id, subscriber.as_ref()).await?;
-                remove_non_complete_tasks(transaction)
-                    .await
-                    .context("Failed to remove tasks where n_retries > 10")?;
+    publish_due_issues(pool)
+        .await
+        .context("Failed to publish the scheduled issues")?;
+    mark_sent_issues(pool)
+        .await
+        .context("Failed to mark the sent issues")?;
+    let n_confirmation_emails =
+        send_queued_confirmation_emails(pool, email_client, base_url, settings).await;
+    let (mut transaction, tasks) = match dequeue_tasks(pool, MAX_BATCH_SIZE as i64).await? {
+        Some(claimed) => claimed,
+        None if n_confirmation_emails > 0 => return Ok(ExecutionOutcome::TaskCompleted),
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    Span::current().record("n_tasks", tasks.len());
+
+    let mut issues: HashMap<Uuid, NewsletterIssue> = HashMap::new();
+    let mut batch = Vec::with_capacity(tasks.len());
+    let mut batch_tasks = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        // The subscriber might have left the list or paused delivery after the issue was published
+        let recipient = match get_recipient(pool, &task).await? {
+            Some(recipient) => recipient,
+            None => {
+                tracing::info!(
+                    newsletter_issue_id = %task.newsletter_issue_id,
+                    subscriber_email = task.subscriber_email,
+                    "Skipping a subscriber who left the list of the issue or paused delivery"
+                );
+                record_delivery(&mut transaction, &task, DeliveryStatus::Skipped, None, None)
+                    .await?;
+                delete_task(&mut transaction, &task).await?;
+                continue;
             }
+        };
+        let subscriber = match SubscriberEmail::parse(task.subscriber_email.clone()) {
+            Ok(subscriber) => subscriber,
             Err(error) => {
                 tracing::warn!(
                     error.cause_chain = ?error,
                     error.message = %error,
                     "Skipping a confirmed subscriber. Their stored contact details are invalid",
                 );
+                let error = error.to_string();
+                record_delivery(
+                    &mut transaction,
+                    &task,
+                    DeliveryStatus::Skipped,
+                    None,
+                    Some(&error),
+                )
+                .await?;
+                delete_task(&mut transaction, &task).await?;
+                continue;
+            }
+        };
+        let newsletter_issue = match issues.entry(task.newsletter_issue_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                entry.insert(get_issue(pool, base_url, &task.newsletter_issue_id).await?)
+            }
+        };
+        batch.push(newsletter_email(
+            newsletter_issue,
+            subscriber,
+            &recipient.username,
+            &list_unsubscribe_link(base_url, &recipient.unsubscribe_token, recipient.list_id),
+            &preferences_link(base_url, &recipient.unsubscribe_token),
+        ));
+        batch_tasks.push(task);
+    }
+
+    let outcomes = email_client.send_batch(&batch).await;
+    // Only the emails that went out are removed from the queue.
+    // The others are retried later on until they run out of attempts,
+    // unless the provider refused them for good
+    for (task, outcome) in batch_tasks.iter().zip(outcomes) {
+        let error = match outcome {
+            Ok(message_id) => {
+                record_delivery(
+                    &mut transaction,
+                    task,
+                    DeliveryStatus::Sent,
+                    message_id.as_deref(),
+                    None,
+                )
+                .await?;
+                delete_task(&mut transaction, task).await?;
+                continue;
             }
+            Err(error) => error,
+        };
+        tracing::error!(
+            error.cause_chain = ?error,
+            error.message = %error,
+            newsletter_issue_id = %task.newsletter_issue_id,
+            subscriber_email = task.subscriber_email,
+            "Failed to deliver a newsletter issue",
+        );
+        let n_attempts = task.n_retries + 1;
+        if is_permanent_failure(&error) || n_attempts >= settings.max_attempts {
+            move_to_failures(&mut transaction, task, n_attempts, &format!("{:#}", error))
+                .await
+                .context("Failed to move an exhausted task to the failures")?;
+        } else {
+            update_task_retry(&mut transaction, task, settings.backoff(n_attempts))
+                .await
+                .context("Failed to update task retries")?;
         }
-        Ok(ExecutionOutcome::TaskCompleted)
+    }
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Render an issue for one of its recipients.
+pub fn newsletter_email(
+    newsletter_issue: &NewsletterIssue,
+    recipient: SubscriberEmail,
+    username: &str,
+    unsubscribe_link: &str,
+    preferences_link: &str,
+) -> OutgoingEmail {
+    let context = TemplateContext {
+        username,
+        unsubscribe_url: unsubscribe_link,
+        web_view_url: &newsletter_issue.web_view_url,
+    };
+    // Only issues in the public archive can be read in a browser
+    let (web_view_html, web_view_text) = if newsletter_issue.is_public {
+        (
+            format!(
+                "<p><a href=\"{}\">View this issue in your browser</a></p>",
+                newsletter_issue.web_view_url
+            ),
+            format!(
+                "View this issue in your browser: {}\n\n",
+                newsletter_issue.web_view_url
+            ),
+        )
     } else {
-        Ok(ExecutionOutcome::EmptyQueue)
+        (String::new(), String::new())
+    };
+    let (html_content, text_content) = apply_layout(
+        newsletter_issue.layout.as_ref(),
+        format!(
+            "{}{}<p><a href=\"{}\">Update your preferences</a> or \
+            <a href=\"{}\">unsubscribe</a> from this newsletter.</p>",
+            web_view_html,
+            newsletter_issue
+                .html_content
+                .render(&context, TemplateFormat::Html),
+            preferences_link,
+            htmlescape::encode_minimal(unsubscribe_link)
+        ),
+        format!(
+            "{}{}\n\nTo update your preferences, visit {}\nTo unsubscribe, visit {}",
+            web_view_text,
+            newsletter_issue
+                .text_content
+                .render(&context, TemplateFormat::Text),
+            preferences_link,
+            unsubscribe_link
+        ),
+    );
+    let html_content = match &newsletter_issue.subject_preview {
+        Some(subject_preview) => with_preheader(html_content, subject_preview),
+        None => html_content,
+    };
+    OutgoingEmail {
+        recipient,
+        subject: newsletter_issue.title.clone(),
+        html_content,
+        text_content,
+        // RFC 8058 one-click unsubscribe: mailbox providers POST
+        // `List-Unsubscribe=One-Click` to the URL on behalf of the subscriber
+        headers: vec![
+            EmailHeader::new("List-Unsubscribe", format!("<{}>", unsubscribe_link)),
+            EmailHeader::new("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+        ],
     }
 }
 
 type PgTransaction = Transaction<'static, Postgres>;
 
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    /// Number of failed attempts so far
+    n_retries: i16,
+    enqueued_at: DateTime<Utc>,
+}
+
+/// Claim up to `limit` tasks that are due, leaving the ones of paused and cancelled issues alone.
+/// The rows stay locked until the returned transaction ends, so that
+/// concurrent workers skip them.
 #[tracing::instrument(skip_all)]
-async fn dequeue_task(
+async fn dequeue_tasks(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+    limit: i64,
+) -> Result<Option<(PgTransaction, Vec<DeliveryTask>)>, anyhow::Error> {
     let mut transaction = pool.begin().await?;
 
-    match sqlx::query!(
+    let tasks = sqlx::query_as!(
+        DeliveryTask,
         r#"
-    SELECT newsletter_issue_id, subscriber_email
+    SELECT newsletter_issue_id, subscriber_email, n_retries, enqueued_at
     FROM issue_delivery_table
     WHERE execute_after <= now()
+        AND newsletter_issue_id NOT IN (
+            SELECT newsletter_issue_id FROM newsletter_issues
+            WHERE status IN ('paused', 'cancelled')
+        )
     FOR UPDATE
     SKIP LOCKED
-    LIMIT 1
-"#
+    LIMIT $1
+"#,
+        limit
     )
-    .fetch_optional(&mut *transaction)
-    .await?
-    {
-        Some(r) => Ok(Some((
-            transaction,
-            r.newsletter_issue_id,
-            r.subscriber_email,
-        ))),
-        None => Ok(None),
+    .fetch_all(&mut *transaction)
+    .await?;
+    if tasks.is_empty() {
+        return Ok(None);
     }
+    Ok(Some((transaction, tasks)))
 }
 
 #[tracing::instrument(skip_all)]
 async fn delete_task(
     transaction: &mut PgTransaction,
-    newsletter_issue_id: &Uuid,
-    subscriber_email: &str,
+    task: &DeliveryTask,
 ) -> Result<(), anyhow::Error> {
     sqlx::query!(
         r#"
         DELETE FROM issue_delivery_table
         WHERE newsletter_issue_id = $1 AND subscriber_email = $2
     "#,
-        newsletter_issue_id,
-        subscriber_email
+        task.newsletter_issue_id,
+        task.subscriber_email
     )
     .execute(&mut **transaction)
     .await?;
     Ok(())
 }
 
-struct NewsletterIssue {
-    title: String,
-    html_content: String,
-    text_content: String,
+pub struct NewsletterIssue {
+    pub title: String,
+    pub html_content: IssueTemplate,
+    pub text_content: IssueTemplate,
+    pub layout: Option<EmailLayout>,
+    /// Where the issue can be read in a browser
+    pub web_view_url: String,
+    /// Whether the issue is listed in the public archive
+    pub is_public: bool,
+    /// Shown next to the subject in the inbox
+    pub subject_preview: Option<String>,
+}
+
+impl NewsletterIssue {
+    /// Fails if either version of the content is not a valid template.
+    pub fn parse(title: String, html_content: &str, text_content: &str) -> Result<Self, String> {
+        let html_content = IssueTemplate::parse(html_content)
+            .map_err(|e| format!("The HTML content is invalid: {}", e))?;
+        let text_content = IssueTemplate::parse(text_content)
+            .map_err(|e| format!("The plain text content is invalid: {}", e))?;
+        Ok(Self {
+            title,
+            html_content,
+            text_content,
+            layout: None,
+            web_view_url: String::new(),
+            is_public: false,
+            subject_preview: None,
+        })
+    }
+}
+
+struct Recipient {
+    username: String,
+    unsubscribe_token: String,
+    list_id: Uuid,
+}
+
+/// Send the confirmation emails of imported subscribers, a few at a time.
+/// Their confirmation token is only created here, so that it expires
+/// a day after the email went out rather than after the import.
+/// Failures are logged rather than returned: they must not hold up the issues.
+#[tracing::instrument(skip_all, fields(n_emails = tracing::field::Empty))]
+async fn send_queued_confirmation_emails(
+    pool: &PgPool,
+    email_client: &dyn EmailSender,
+    base_url: &str,
+    settings: &WorkerSettings,
+) -> usize {
+    let layout = match get_default_email_layout(pool).await {
+        Ok(layout) => layout,
+        Err(error) => {
+            tracing::error!(
+                error.cause_chain = ?error,
+                error.message = %error,
+                "Failed to load the layout of the confirmation emails",
+            );
+            return 0;
+        }
+    };
+    let mut n_emails = 0;
+    while n_emails < CONFIRMATION_EMAILS_PER_RUN {
+        match send_queued_confirmation_email(
+            pool,
+            email_client,
+            layout.as_ref(),
+            base_url,
+            settings,
+        )
+        .await
+        {
+            Ok(ExecutionOutcome::TaskCompleted) => n_emails += 1,
+            Ok(ExecutionOutcome::EmptyQueue) => break,
+            // The task is left in the queue and tried again on the next run
+            Err(error) => {
+                tracing::error!(
+                    error.cause_chain = ?error,
+                    error.message = %error,
+                    "Failed to handle a queued confirmation email",
+                );
+                break;
+            }
+        }
+    }
+    Span::current().record("n_emails", n_emails);
+    n_emails
+}
+
+struct ConfirmationTask {
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    n_retries: i16,
+    email: String,
+    list_name: String,
+    /// Their membership of the list to confirm, if it is still there
+    list_status: Option<String>,
+}
+
+/// Claim a single queued confirmation email and settle it in the same transaction.
+/// The row stays locked until then, so that concurrent workers skip it.
+#[tracing::instrument(skip_all)]
+async fn send_queued_confirmation_email(
+    pool: &PgPool,
+    email_client: &dyn EmailSender,
+    layout: Option<&EmailLayout>,
+    base_url: &str,
+    settings: &WorkerSettings,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let task = sqlx::query_as!(
+        ConfirmationTask,
+        r#"
+        SELECT q.subscriber_id, q.list_id, q.n_retries, s.email, l.name AS list_name,
+            m.status AS "list_status?"
+        FROM confirmation_email_queue q
+        JOIN subscriptions s ON s.id = q.subscriber_id
+        JOIN lists l ON l.list_id = q.list_id
+        LEFT JOIN list_memberships m
+            ON m.subscriber_id = q.subscriber_id AND m.list_id = q.list_id
+        WHERE q.execute_after <= now()
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+    "#
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let task = match task {
+        Some(task) => task,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+
+    // The subscriber might have left the list, or been unsubscribed by an admin,
+    // in the meantime
+    if task.list_status.as_deref() != Some("pending_confirmation") {
+        delete_confirmation_task(&mut transaction, &task).await?;
+        transaction.commit().await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+    let recipient = match SubscriberEmail::parse(task.email.clone()) {
+        Ok(recipient) => recipient,
+        Err(error) => {
+            tracing::warn!(
+                subscriber_id = %task.subscriber_id,
+                error,
+                "Skipping a confirmation email. The stored address is invalid",
+            );
+            delete_confirmation_task(&mut transaction, &task).await?;
+            transaction.commit().await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+
+    match send_confirmation(pool, email_client, layout, base_url, &task, &recipient).await {
+        Ok(()) => delete_confirmation_task(&mut transaction, &task).await?,
+        Err(error) => {
+            // An unused token is harmless, it expires on its own
+            let n_attempts = task.n_retries + 1;
+            if is_permanent_failure(&error) || n_attempts >= settings.max_attempts {
+                // Admins can still send it again from the subscriber page
+                tracing::error!(
+                    error.cause_chain = ?error,
+                    error.message = %error,
+                    subscriber_id = %task.subscriber_id,
+                    "Giving up on a confirmation email",
+                );
+                delete_confirmation_task(&mut transaction, &task).await?;
+            } else {
+                tracing::error!(
+                    error.cause_chain = ?error,
+                    error.message = %error,
+                    subscriber_id = %task.subscriber_id,
+                    "Failed to send a confirmation email",
+                );
+                sqlx::query!(
+                    r#"
+                    UPDATE confirmation_email_queue
+                    SET n_retries = n_retries + 1,
+                        execute_after = now() + make_interval(secs => $3)
+                    WHERE subscriber_id = $1 AND list_id = $2
+                "#,
+                    task.subscriber_id,
+                    task.list_id,
+                    settings.backoff(n_attempts).as_secs_f64()
+                )
+                .execute(&mut *transaction)
+                .await
+                .context("Failed to update the confirmation email retries")?;
+            }
+        }
+    }
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// The token is committed on its own before the email goes out, so that the
+/// link works whatever happens to the queued task afterwards.
+async fn send_confirmation(
+    pool: &PgPool,
+    email_client: &dyn EmailSender,
+    layout: Option<&EmailLayout>,
+    base_url: &str,
+    task: &ConfirmationTask,
+    recipient: &SubscriberEmail,
+) -> Result<(), anyhow::Error> {
+    let subscription_token = generate_subscription_token();
+    let mut transaction = pool.begin().await?;
+    store_token(
+        &mut transaction,
+        task.subscriber_id,
+        task.list_id,
+        &subscription_token,
+    )
+    .await?;
+    transaction.commit().await?;
+    send_confirmation_email(
+        email_client,
+        layout,
+        recipient,
+        &task.list_name,
+        base_url,
+        &subscription_token,
+    )
+    .await
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_confirmation_task(
+    transaction: &mut PgTransaction,
+    task: &ConfirmationTask,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM confirmation_email_queue WHERE subscriber_id = $1 AND list_id = $2",
+        task.subscriber_id,
+        task.list_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// Scheduled issues start going out once due, to the confirmed members of their list
+/// at that time: whoever confirmed since the issue was scheduled receives it too.
+/// Concurrent workers cannot enqueue an issue twice, the second one to update it
+/// does not find it `scheduled` anymore.
+#[tracing::instrument(skip_all)]
+async fn publish_due_issues(pool: &PgPool) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        WITH due AS (
+            UPDATE newsletter_issues
+            SET status = 'sending', updated_at = now()
+            WHERE status = 'scheduled' AND scheduled_for <= now()
+            RETURNING newsletter_issue_id, list_id
+        )
+        INSERT INTO issue_delivery_table (newsletter_issue_id, subscriber_email)
+        SELECT due.newsletter_issue_id, s.email
+        FROM due
+        JOIN list_memberships m ON m.list_id = due.list_id AND m.status = 'confirmed'
+        JOIN subscriptions s ON s.id = m.subscriber_id AND s.status = 'confirmed'
+        ON CONFLICT DO NOTHING
+    "#
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// An issue is sent once none of its deliveries is left in the queue.
+/// This is done on every run rather than when deleting a task, since concurrent
+/// workers cannot see each other's deletions before they commit.
+#[tracing::instrument(skip_all)]
+async fn mark_sent_issues(pool: &PgPool) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues i
+        SET status = 'sent', updated_at = now()
+        WHERE status = 'sending' AND NOT EXISTS (
+            SELECT 1 FROM issue_delivery_table q
+            WHERE q.newsletter_issue_id = i.newsletter_issue_id
+        )
+    "#
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
 }
 
 #[tracing::instrument(skip_all)]
 async fn get_issue(
     pool: &PgPool,
+    base_url: &str,
     newsletter_issue_id: &Uuid,
 ) -> Result<NewsletterIssue, anyhow::Error> {
-    let newsletter_issue = sqlx::query_as!(
-        NewsletterIssue,
+    let record = sqlx::query!(
         r#"
-            SELECT title, html_content, text_content
+            SELECT title, html_content, text_content, email_template_id, slug, is_public,
+                subject_preview
             FROM newsletter_issues
             WHERE newsletter_issue_id = $1
         "#,
@@ -167,26 +620,83 @@ async fn get_issue(
     )
     .fetch_one(pool)
     .await?;
+    // Templates are validated when publishing, but issues published before
+    // placeholders were supported are sent as they are
+    let mut newsletter_issue = NewsletterIssue::parse(
+        record.title.clone(),
+        &record.html_content,
+        &record.text_content,
+    )
+    .unwrap_or_else(|error| {
+        tracing::warn!(
+            %newsletter_issue_id,
+            error,
+            "Sending a newsletter issue without rendering its placeholders",
+        );
+        NewsletterIssue {
+            title: record.title,
+            html_content: IssueTemplate::verbatim(&record.html_content),
+            text_content: IssueTemplate::verbatim(&record.text_content),
+            layout: None,
+            web_view_url: String::new(),
+            is_public: false,
+            subject_preview: None,
+        }
+    });
+    newsletter_issue.subject_preview = record.subject_preview;
+    // Only issues in the public archive can be read in a browser, the
+    // `{{ web_view_url }}` placeholder of the others stays empty
+    if let (Some(slug), true) = (&record.slug, record.is_public) {
+        newsletter_issue.web_view_url = web_view_link(base_url, slug);
+        newsletter_issue.is_public = true;
+    }
+    if let Some(email_template_id) = record.email_template_id {
+        newsletter_issue.layout = get_email_layout(pool, email_template_id).await?;
+    }
     Ok(newsletter_issue)
 }
 
+#[tracing::instrument(skip_all)]
+async fn get_recipient(
+    pool: &PgPool,
+    task: &DeliveryTask,
+) -> Result<Option<Recipient>, anyhow::Error> {
+    let recipient = sqlx::query_as!(
+        Recipient,
+        r#"
+            SELECT s.username, s.unsubscribe_token, m.list_id
+            FROM subscriptions s
+            JOIN newsletter_issues i ON i.newsletter_issue_id = $2
+            JOIN list_memberships m ON m.subscriber_id = s.id AND m.list_id = i.list_id
+            WHERE s.email = $1
+                AND s.status = 'confirmed'
+                AND m.status = 'confirmed'
+                AND (s.paused_until IS NULL OR s.paused_until <= now())
+        "#,
+        task.subscriber_email,
+        task.newsletter_issue_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(recipient)
+}
+
 #[tracing::instrument(skip_all)]
 async fn update_task_retry(
     transaction: &mut PgTransaction,
-    newsletter_issue_id: &Uuid,
-    subscriber_email: &str,
+    task: &DeliveryTask,
+    backoff: Duration,
 ) -> Result<(), anyhow::Error> {
-    // When the attempt to send email fails, we update
-    // n_retries and execute_after to 1 minutes later
     sqlx::query!(
         r#"
         UPDATE issue_delivery_table
         SET n_retries = n_retries + 1,
-            execute_after = execute_after + (1 * interval '1 minute')
+            execute_after = now() + make_interval(secs => $3)
         WHERE newsletter_issue_id = $1 AND subscriber_email = $2
     "#,
-        newsletter_issue_id,
-        subscriber_email
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        backoff.as_secs_f64()
     )
     .execute(&mut **transaction)
     .await
@@ -194,16 +704,96 @@ async fn update_task_retry(
     Ok(())
 }
 
-async fn remove_non_complete_tasks(mut transaction: PgTransaction) -> Result<(), anyhow::Error> {
-    // Remove all the tasks whose n_retries > 10
+/// Keep a record of the deliveries that ran out of attempts, so that admins
+/// can look into them and requeue them.
+#[tracing::instrument(skip_all)]
+async fn move_to_failures(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
+    n_attempts: i16,
+    last_error: &str,
+) -> Result<(), anyhow::Error> {
     sqlx::query!(
         r#"
-        DELETE FROM issue_delivery_table
-        WHERE n_retries > 10
-    "#
+        INSERT INTO issue_delivery_failures (
+            newsletter_issue_id,
+            subscriber_email,
+            n_attempts,
+            last_error,
+            enqueued_at,
+            failed_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET n_attempts = EXCLUDED.n_attempts,
+            last_error = EXCLUDED.last_error,
+            enqueued_at = EXCLUDED.enqueued_at,
+            failed_at = EXCLUDED.failed_at
+    "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        n_attempts,
+        last_error,
+        task.enqueued_at
     )
-    .execute(&mut *transaction)
+    .execute(&mut **transaction)
     .await?;
-    transaction.commit().await?;
+    record_delivery(
+        transaction,
+        task,
+        DeliveryStatus::Failed,
+        None,
+        Some(last_error),
+    )
+    .await?;
+    delete_task(transaction, task).await
+}
+
+enum DeliveryStatus {
+    Sent,
+    Failed,
+    Skipped,
+}
+
+impl DeliveryStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sent => "sent",
+            Self::Failed => "failed",
+            Self::Skipped => "skipped",
+        }
+    }
+}
+
+/// Append the final outcome of a delivery to the history of its issue.
+#[tracing::instrument(skip_all)]
+async fn record_delivery(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
+    status: DeliveryStatus,
+    provider_message_id: Option<&str>,
+    error: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_deliveries (
+            newsletter_issue_id,
+            subscriber_email,
+            status,
+            provider_message_id,
+            error,
+            recorded_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+    "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        status.as_str(),
+        provider_message_id,
+        error
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to record the delivery")?;
     Ok(())
 }
@@ -2,8 +2,10 @@ pub mod authentication;
 pub mod configurations;
 pub mod domain;
 pub mod email_client;
+pub mod email_layout;
 pub mod idempotency;
 pub mod issues_delivery_worker;
+pub mod mailing_lists;
 pub mod routes;
 pub mod session_state;
 pub mod startup;
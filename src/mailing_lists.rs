@@ -0,0 +1,103 @@
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// One of the newsletters people can subscribe to, managed from the admin area.
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub name: String,
+    pub description: String,
+    pub is_default: bool,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_mailing_lists(pool: &PgPool) -> Result<Vec<MailingList>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        MailingList,
+        r#"
+        SELECT list_id, name, description, is_default
+        FROM lists
+        ORDER BY name
+    "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the mailing lists")?;
+    Ok(lists)
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_mailing_list(
+    pool: &PgPool,
+    list_id: Uuid,
+) -> Result<Option<MailingList>, anyhow::Error> {
+    let list = sqlx::query_as!(
+        MailingList,
+        r#"
+        SELECT list_id, name, description, is_default
+        FROM lists
+        WHERE list_id = $1
+    "#,
+        list_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the mailing list")?;
+    Ok(list)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_default_mailing_list(pool: &PgPool) -> Result<Option<MailingList>, anyhow::Error> {
+    let list = sqlx::query_as!(
+        MailingList,
+        r#"
+        SELECT list_id, name, description, is_default
+        FROM lists
+        WHERE is_default
+    "#
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the default mailing list")?;
+    Ok(list)
+}
+
+/// The list picked in a form: the default one when the field is missing or empty.
+/// `None` if there is no such list.
+pub async fn selected_mailing_list(
+    pool: &PgPool,
+    choice: Option<&str>,
+) -> Result<Option<MailingList>, anyhow::Error> {
+    match choice.map(str::trim) {
+        None | Some("") => get_default_mailing_list(pool).await,
+        Some(choice) => match Uuid::parse_str(choice) {
+            Ok(list_id) => get_mailing_list(pool, list_id).await,
+            Err(_) => Ok(None),
+        },
+    }
+}
+
+/// A `<select>` to pick one of the lists, the default one comes selected.
+pub fn mailing_list_select(lists: &[MailingList], selected: Option<Uuid>) -> String {
+    let mut options_html = String::new();
+    for list in lists {
+        let is_selected = match selected {
+            Some(selected) => selected == list.list_id,
+            None => list.is_default,
+        };
+        writeln!(
+            options_html,
+            r#"<option value="{id}"{selected}>{name}</option>"#,
+            id = list.list_id,
+            selected = if is_selected { " selected" } else { "" },
+            name = htmlescape::encode_minimal(&list.name),
+        )
+        .unwrap();
+    }
+    format!(
+        r#"<label>List:<br>
+            <select name="list_id">{options_html}</select>
+        </label>"#
+    )
+}
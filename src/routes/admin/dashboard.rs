@@ -26,7 +26,13 @@ pub async fn admin_dashboard(
                 <p>Welcome {username}!</p>
                 <p>Available actions:</p>
                 <ol>
+                <li><a href="/admin/newsletters">Send a newsletter issue</a></li>
+                <li><a href="/admin/drafts">Drafts</a></li>
+                <li><a href="/admin/subscribers">Subscribers</a></li>
+                <li><a href="/admin/lists">Mailing lists</a></li>
+                <li><a href="/admin/templates">Email layouts</a></li>
                 <li><a href="/admin/password">Change password</a></li>
+                <li><a href="/admin/deliveries/failures">Failed deliveries</a></li>
                 <li>
                 <form name="logoutForm" action="/admin/logout" method="post">
                 <input type="submit" value="Logout">
@@ -0,0 +1,110 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::utils::{e500, flash_messages_html};
+
+struct DeliveryFailure {
+    newsletter_issue_id: Uuid,
+    title: String,
+    subscriber_email: String,
+    n_attempts: i16,
+    last_error: String,
+    enqueued_at: DateTime<Utc>,
+    failed_at: DateTime<Utc>,
+}
+
+pub async fn delivery_failures(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let msg_html = flash_messages_html(&flash_messages);
+    let failures = get_delivery_failures(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for failure in &failures {
+        let subscriber_email = htmlescape::encode_minimal(&failure.subscriber_email);
+        writeln!(
+            rows_html,
+            r#"<tr>
+    <td>{title}</td>
+    <td>{subscriber_email}</td>
+    <td>{n_attempts}</td>
+    <td>{last_error}</td>
+    <td>{enqueued_at}</td>
+    <td>{failed_at}</td>
+    <td>
+        <form action="/admin/deliveries/failures/requeue" method="post">
+            <input hidden type="text" name="newsletter_issue_id" value="{newsletter_issue_id}">
+            <input hidden type="text" name="subscriber_email" value="{subscriber_email}">
+            <button type="submit">Requeue</button>
+        </form>
+    </td>
+</tr>"#,
+            title = htmlescape::encode_minimal(&failure.title),
+            n_attempts = failure.n_attempts,
+            last_error = htmlescape::encode_minimal(&failure.last_error),
+            enqueued_at = failure.enqueued_at.to_rfc3339(),
+            failed_at = failure.failed_at.to_rfc3339(),
+            newsletter_issue_id = failure.newsletter_issue_id,
+        )
+        .unwrap();
+    }
+    let content_html = if failures.is_empty() {
+        "<p>No failed deliveries.</p>".to_string()
+    } else {
+        format!(
+            r#"<table>
+<tr>
+    <th>Issue</th>
+    <th>Subscriber</th>
+    <th>Attempts</th>
+    <th>Last error</th>
+    <th>Enqueued at</th>
+    <th>Failed at</th>
+    <th></th>
+</tr>
+{rows_html}</table>"#
+        )
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Failed deliveries</title>
+</head>
+<body>
+    {msg_html}
+    <p>Newsletter deliveries that ran out of attempts:</p>
+    {content_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_delivery_failures(pool: &PgPool) -> Result<Vec<DeliveryFailure>, anyhow::Error> {
+    let failures = sqlx::query_as!(
+        DeliveryFailure,
+        r#"
+        SELECT f.newsletter_issue_id, i.title, f.subscriber_email, f.n_attempts,
+            f.last_error, f.enqueued_at, f.failed_at
+        FROM issue_delivery_failures f
+        JOIN newsletter_issues i ON i.newsletter_issue_id = f.newsletter_issue_id
+        ORDER BY f.failed_at DESC
+    "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the delivery failures")?;
+    Ok(failures)
+}
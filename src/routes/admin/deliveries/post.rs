@@ -0,0 +1,120 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::utils::{e500, redirect};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+}
+
+#[tracing::instrument(
+    name = "Requeue a failed delivery",
+    skip(form, pool),
+    fields(
+        newsletter_issue_id=%form.newsletter_issue_id,
+        subscriber_email=%form.subscriber_email
+    )
+)]
+pub async fn requeue_delivery_failure(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let outcome = requeue(&pool, form.newsletter_issue_id, &form.subscriber_email)
+        .await
+        .map_err(e500)?;
+    match outcome {
+        RequeueOutcome::Requeued => FlashMessage::info(format!(
+            "The delivery to {} has been requeued.",
+            form.subscriber_email
+        ))
+        .send(),
+        RequeueOutcome::IssueCancelled => {
+            FlashMessage::error("This newsletter issue has been cancelled.").send()
+        }
+        RequeueOutcome::NotFound => {
+            FlashMessage::error("This delivery is not in the failures anymore.").send()
+        }
+    }
+    Ok(redirect("/admin/deliveries/failures"))
+}
+
+enum RequeueOutcome {
+    Requeued,
+    IssueCancelled,
+    NotFound,
+}
+
+/// Move a failed delivery back to the queue, with a fresh set of attempts.
+/// The deliveries of a cancelled issue stay in the failures.
+#[tracing::instrument(skip(pool))]
+async fn requeue(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    subscriber_email: &str,
+) -> Result<RequeueOutcome, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    // The issue is locked, so that it cannot be cancelled until the delivery is back in the queue
+    let issue = sqlx::query!(
+        "SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1 FOR UPDATE",
+        newsletter_issue_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to lock the newsletter issue")?;
+    match issue {
+        Some(issue) if issue.status == "cancelled" => return Ok(RequeueOutcome::IssueCancelled),
+        Some(_) => {}
+        None => return Ok(RequeueOutcome::NotFound),
+    }
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_failures
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+    "#,
+        newsletter_issue_id,
+        subscriber_email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the delivery failure")?;
+    if deleted.rows_affected() == 0 {
+        return Ok(RequeueOutcome::NotFound);
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_table (newsletter_issue_id, subscriber_email)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+    "#,
+        newsletter_issue_id,
+        subscriber_email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to enqueue the delivery task")?;
+    // The issue is going out again
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'sending', updated_at = now()
+        WHERE newsletter_issue_id = $1 AND status = 'sent'
+    "#,
+        newsletter_issue_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to update the status of the newsletter issue")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to requeue a delivery")?;
+    Ok(RequeueOutcome::Requeued)
+}
@@ -0,0 +1,241 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use super::{get_draft, Draft};
+use crate::mailing_lists::{get_mailing_lists, mailing_list_select};
+use crate::routes::admin::templates::email_template_select;
+use crate::utils::{e500, flash_messages_html};
+
+pub async fn newsletter_drafts(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let msg_html = flash_messages_html(&flash_messages);
+    let drafts = get_drafts(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for draft in &drafts {
+        writeln!(
+            rows_html,
+            r#"<tr>
+    <td><a href="/admin/drafts/{id}">{title}</a></td>
+    <td>Last edited {updated_at}</td>
+    <td><a href="/admin/drafts/{id}/preview">Preview</a></td>
+    <td>
+        <form action="/admin/drafts/{id}/delete" method="post">
+            <button type="submit">Delete</button>
+        </form>
+    </td>
+</tr>"#,
+            id = draft.newsletter_issue_id,
+            title = htmlescape::encode_minimal(&draft.title),
+            updated_at = draft.updated_at.format("%Y-%m-%d %H:%M UTC"),
+        )
+        .unwrap();
+    }
+    let content_html = if drafts.is_empty() {
+        "<p>No drafts.</p>".to_string()
+    } else {
+        format!("<table>\n{rows_html}</table>")
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Drafts</title>
+</head>
+<body>
+    {msg_html}
+    {content_html}
+    <p><a href="/admin/newsletters">Write a new issue</a></p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+pub async fn edit_newsletter_draft(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let msg_html = flash_messages_html(&flash_messages);
+    let draft = match get_draft(&pool, *draft_id).await.map_err(e500)? {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let layout_html = email_template_select(&pool, draft.email_template_id)
+        .await
+        .map_err(e500)?;
+    let lists = get_mailing_lists(&pool).await.map_err(e500)?;
+    let list_html = mailing_list_select(&lists, None);
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Edit draft</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/drafts/{id}" method="post">
+        <label>Title:<br>
+            <input
+                type="text"
+                placeholder="Enter the issue title"
+                name="title"
+                value="{title}"
+            >
+        </label>
+        <br>
+        <label>Subject preview (shown next to the subject in the inbox):<br>
+            <input
+                type="text"
+                placeholder="Enter a short summary"
+                name="subject_preview"
+                value="{subject_preview}"
+            >
+        </label>
+        <br>
+        <label>Plain text content:<br>
+            <textarea
+                placeholder="Enter the content in plain text"
+                name="text_content"
+                rows="20"
+                cols="50"
+            >{text_content}</textarea>
+        </label>
+        <br>
+        <label>HTML content:<br>
+            <textarea
+                placeholder="Enter the content in HTML format"
+                name="html_content"
+                rows="20"
+                cols="50"
+            >{html_content}</textarea>
+        </label>
+        <br>
+        <label>Markdown content (replaces the plain text and HTML content):<br>
+            <textarea
+                placeholder="Enter the content in Markdown"
+                name="markdown_content"
+                rows="20"
+                cols="50"
+            >{markdown_content}</textarea>
+        </label>
+        <br>
+        {layout_html}
+        <br>
+        <label>
+            <input type="checkbox" name="public"{public_checked}>
+            Show in the public archive
+        </label>
+        <br>
+        <button type="submit">Save</button>
+    </form>
+    <p><a href="/admin/drafts/{id}/preview">Preview</a></p>
+    <form action="/admin/drafts/{id}/test" method="post">
+        <label>Send a test email to (separate addresses with commas):<br>
+            <input type="text" name="recipients">
+        </label>
+        <button type="submit">Send test email</button>
+    </form>
+    <form action="/admin/newsletters" method="post">
+        {list_html}
+        <br>
+        <label>Send at (UTC, leave empty to send right away):<br>
+            <input type="datetime-local" name="scheduled_for">
+        </label>
+        <input hidden type="text" name="draft_id" value="{id}">
+        <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+        <button type="submit">Publish</button>
+    </form>
+    <form action="/admin/drafts/{id}/delete" method="post">
+        <button type="submit">Delete</button>
+    </form>
+    <p><a href="/admin/drafts">&lt;- Back</a></p>
+</body>
+</html>"#,
+            id = draft.newsletter_issue_id,
+            title = htmlescape::encode_minimal(&draft.title),
+            subject_preview =
+                htmlescape::encode_minimal(draft.subject_preview.as_deref().unwrap_or_default()),
+            text_content = htmlescape::encode_minimal(&draft.text_content),
+            html_content = htmlescape::encode_minimal(&draft.html_content),
+            markdown_content =
+                htmlescape::encode_minimal(draft.markdown_content.as_deref().unwrap_or_default()),
+            public_checked = if draft.is_public { " checked" } else { "" },
+        )))
+}
+
+pub async fn preview_newsletter_draft(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = match get_draft(&pool, *draft_id).await.map_err(e500)? {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    // The HTML version is rendered in a sandboxed frame, so that its styles
+    // and scripts cannot leak into the admin page
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Preview draft</title>
+</head>
+<body>
+    <h1>{title}</h1>
+    <div style="display: flex; gap: 1em;">
+        <section style="flex: 1;">
+            <h2>HTML</h2>
+            <iframe sandbox srcdoc="{html_content}" style="width: 100%; height: 40em;"></iframe>
+        </section>
+        <section style="flex: 1;">
+            <h2>Plain text</h2>
+            <pre>{text_content}</pre>
+        </section>
+    </div>
+    <p><a href="/admin/drafts/{id}">&lt;- Back</a></p>
+</body>
+</html>"#,
+            id = draft.newsletter_issue_id,
+            title = htmlescape::encode_minimal(&draft.title),
+            html_content = htmlescape::encode_minimal(&draft.html_content),
+            text_content = htmlescape::encode_minimal(&draft.text_content),
+        )))
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_drafts(pool: &PgPool) -> Result<Vec<Draft>, anyhow::Error> {
+    let drafts = sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT newsletter_issue_id, title, text_content, html_content, markdown_content,
+            email_template_id, is_public, subject_preview, updated_at
+        FROM newsletter_issues
+        WHERE status = 'draft'
+        ORDER BY updated_at DESC
+    "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the drafts")?;
+    Ok(drafts)
+}
@@ -0,0 +1,42 @@
+mod get;
+mod post;
+
+pub use get::{edit_newsletter_draft, newsletter_drafts, preview_newsletter_draft};
+pub use post::{
+    create_newsletter_draft, delete_newsletter_draft, send_test_email, update_newsletter_draft,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+struct Draft {
+    newsletter_issue_id: Uuid,
+    title: String,
+    text_content: String,
+    html_content: String,
+    markdown_content: Option<String>,
+    email_template_id: Option<Uuid>,
+    is_public: bool,
+    subject_preview: Option<String>,
+    updated_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_draft(pool: &PgPool, draft_id: Uuid) -> Result<Option<Draft>, anyhow::Error> {
+    let draft = sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT newsletter_issue_id, title, text_content, html_content, markdown_content,
+            email_template_id, is_public, subject_preview, updated_at
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+    "#,
+        draft_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the draft")?;
+    Ok(draft)
+}
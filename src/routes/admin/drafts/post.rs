@@ -0,0 +1,310 @@
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::get_draft;
+use crate::authentication::UserId;
+use crate::domain::{MarkdownContent, SubscriberEmail};
+use crate::email_client::EmailSender;
+use crate::email_layout::get_email_layout;
+use crate::issues_delivery_worker::{newsletter_email, NewsletterIssue};
+use crate::routes::admin::templates::selected_email_template;
+use crate::routes::{preferences_link, unsubscribe_link};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e500, redirect};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    title: String,
+    // Shown next to the subject in the inbox
+    #[serde(default)]
+    subject_preview: String,
+    text_content: String,
+    html_content: String,
+    #[serde(default)]
+    markdown_content: String,
+    // Missing to use the default layout, empty for no layout
+    email_template_id: Option<String>,
+    // Checkboxes are only sent when ticked
+    public: Option<String>,
+}
+
+struct DraftContent {
+    text_content: String,
+    html_content: String,
+    markdown_content: Option<String>,
+}
+
+impl FormData {
+    fn subject_preview(&self) -> Option<&str> {
+        Some(self.subject_preview.trim()).filter(|s| !s.is_empty())
+    }
+
+    /// The plain text and HTML versions are derived from the Markdown content, if any.
+    fn content(&self) -> DraftContent {
+        match MarkdownContent::parse(self.markdown_content.clone()) {
+            Ok(markdown_content) => DraftContent {
+                text_content: markdown_content.to_text(),
+                html_content: markdown_content.to_html(),
+                markdown_content: Some(self.markdown_content.clone()),
+            },
+            Err(_) => DraftContent {
+                text_content: self.text_content.clone(),
+                html_content: self.html_content.clone(),
+                markdown_content: None,
+            },
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Save a newsletter draft",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn create_newsletter_draft(
+    form: web::Form<FormData>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = Uuid::new_v4();
+    let content = form.content();
+    let email_template_id =
+        selected_email_template(&pool, form.email_template_id.as_deref()).await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, title, text_content, html_content, markdown_content,
+            email_template_id, is_public, subject_preview, author_user_id, status
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, 'draft')
+    "#,
+        draft_id,
+        form.title,
+        content.text_content,
+        content.html_content,
+        content.markdown_content,
+        email_template_id,
+        form.public.is_some(),
+        form.subject_preview(),
+        **user_id,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to insert the draft")
+    .map_err(e500)?;
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(redirect(&format!("/admin/drafts/{}", draft_id)))
+}
+
+#[tracing::instrument(name = "Update a newsletter draft", skip(form, pool))]
+pub async fn update_newsletter_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let content = form.content();
+    let email_template_id =
+        selected_email_template(&pool, form.email_template_id.as_deref()).await?;
+    // Published issues are not drafts anymore and cannot be edited
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET title = $2, text_content = $3, html_content = $4, markdown_content = $5,
+            email_template_id = $6, is_public = $7, subject_preview = $8, updated_at = now()
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+    "#,
+        draft_id,
+        form.title,
+        content.text_content,
+        content.html_content,
+        content.markdown_content,
+        email_template_id,
+        form.public.is_some(),
+        form.subject_preview(),
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the draft")
+    .map_err(e500)?;
+    if updated.rows_affected() == 0 {
+        FlashMessage::error("This draft has already been published.").send();
+        return Ok(redirect("/admin/drafts"));
+    }
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(redirect(&format!("/admin/drafts/{}", draft_id)))
+}
+
+#[tracing::instrument(name = "Delete a newsletter draft", skip(pool))]
+pub async fn delete_newsletter_draft(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+    "#,
+        draft_id.into_inner()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to delete the draft")
+    .map_err(e500)?;
+    if deleted.rows_affected() == 0 {
+        FlashMessage::error("This draft has already been published.").send();
+    } else {
+        FlashMessage::info("The draft has been deleted.").send();
+    }
+    Ok(redirect("/admin/drafts"))
+}
+
+const TEST_USERNAME: &str = "Test subscriber";
+
+#[derive(serde::Deserialize)]
+pub struct TestEmailFormData {
+    // Separated by commas or whitespace
+    recipients: String,
+}
+
+#[tracing::instrument(
+    name = "Send a test email of a draft",
+    skip(form, pool, email_client, base_url)
+)]
+pub async fn send_test_email(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<TestEmailFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailSender>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let draft_page = format!("/admin/drafts/{}", draft_id);
+    let recipients = match parse_recipients(&form.recipients) {
+        Ok(recipients) => recipients,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(redirect(&draft_page));
+        }
+    };
+    let draft = match get_draft(&pool, draft_id).await.map_err(e500)? {
+        Some(draft) => draft,
+        None => {
+            FlashMessage::error("This draft has already been published.").send();
+            return Ok(redirect("/admin/drafts"));
+        }
+    };
+    let mut issue =
+        match NewsletterIssue::parse(draft.title, &draft.html_content, &draft.text_content) {
+            Ok(issue) => issue,
+            Err(e) => {
+                FlashMessage::error(e).send();
+                return Ok(redirect(&draft_page));
+            }
+        };
+    if let Some(email_template_id) = draft.email_template_id {
+        issue.layout = get_email_layout(&pool, email_template_id)
+            .await
+            .map_err(e500)?;
+    }
+    // Drafts have no web version yet, the preview stands in for it
+    issue.web_view_url = format!("{}/admin/drafts/{}/preview", base_url.0, draft_id);
+    issue.is_public = draft.is_public;
+    issue.subject_preview = draft.subject_preview;
+    // Test recipients are not subscribers, so the placeholders get sample values.
+    // The unsubscribe and preferences links are only there to make the email look
+    // like the real one: their token does not match any subscriber.
+    let unsubscribe_link = unsubscribe_link(&base_url.0, "test");
+    let preferences_link = preferences_link(&base_url.0, "test");
+    let emails: Vec<_> = recipients
+        .into_iter()
+        .map(|recipient| {
+            newsletter_email(
+                &issue,
+                recipient,
+                TEST_USERNAME,
+                &unsubscribe_link,
+                &preferences_link,
+            )
+        })
+        .collect();
+    let outcomes = email_client.send_batch(&emails).await;
+
+    let mut sent = Vec::new();
+    let mut failed = Vec::new();
+    for (email, outcome) in emails.iter().zip(outcomes) {
+        match outcome {
+            Ok(_) => sent.push(email.recipient.as_ref()),
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    recipient = email.recipient.as_ref(),
+                    "Failed to send a test email",
+                );
+                failed.push(email.recipient.as_ref());
+            }
+        }
+    }
+    if !sent.is_empty() {
+        FlashMessage::info(format!(
+            "The test email has been sent to {}.",
+            sent.join(", ")
+        ))
+        .send();
+    }
+    if !failed.is_empty() {
+        FlashMessage::error(format!(
+            "The test email could not be sent to {}.",
+            failed.join(", ")
+        ))
+        .send();
+    }
+    Ok(redirect(&draft_page))
+}
+
+fn parse_recipients(input: &str) -> Result<Vec<SubscriberEmail>, String> {
+    let recipients = input
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|recipient| !recipient.is_empty())
+        .map(|recipient| {
+            SubscriberEmail::parse(recipient.to_owned())
+                .map_err(|_| format!("{} is not a valid email address.", recipient))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if recipients.is_empty() {
+        return Err("Please enter at least one email address.".into());
+    }
+    Ok(recipients)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_recipients;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn recipients_can_be_separated_by_commas_or_whitespace() {
+        let recipients = assert_ok!(parse_recipients(
+            "ursula@domain.com, le_guin@domain.com\nhank@domain.com"
+        ));
+        let recipients: Vec<&str> = recipients.iter().map(|r| r.as_ref()).collect();
+        assert_eq!(
+            recipients,
+            ["ursula@domain.com", "le_guin@domain.com", "hank@domain.com"]
+        );
+    }
+
+    #[test]
+    fn an_empty_list_of_recipients_is_rejected() {
+        assert_err!(parse_recipients(" , "));
+    }
+
+    #[test]
+    fn an_invalid_recipient_is_rejected() {
+        assert_err!(parse_recipients("ursula@domain.com, ursuladomain.com"));
+    }
+}
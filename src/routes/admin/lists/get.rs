@@ -0,0 +1,160 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::mailing_lists::{get_mailing_list, MailingList};
+use crate::utils::{e500, flash_messages_html};
+
+struct ListSummary {
+    list_id: Uuid,
+    name: String,
+    is_default: bool,
+    n_confirmed: i64,
+    n_pending: i64,
+}
+
+pub async fn mailing_lists(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let msg_html = flash_messages_html(&flash_messages);
+    let lists = get_list_summaries(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for list in &lists {
+        writeln!(
+            rows_html,
+            r#"<tr>
+    <td><a href="/admin/lists/{id}">{name}</a>{default}</td>
+    <td>{n_confirmed}</td>
+    <td>{n_pending}</td>
+</tr>"#,
+            id = list.list_id,
+            name = htmlescape::encode_minimal(&list.name),
+            default = if list.is_default { " (default)" } else { "" },
+            n_confirmed = list.n_confirmed,
+            n_pending = list.n_pending,
+        )
+        .unwrap();
+    }
+    let content_html = if lists.is_empty() {
+        "<p>No lists.</p>".to_string()
+    } else {
+        format!(
+            r#"<table>
+<tr>
+    <th>List</th>
+    <th>Confirmed members</th>
+    <th>Pending members</th>
+</tr>
+{rows_html}</table>"#
+        )
+    };
+    let form_html = list_form_html("/admin/lists", None);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Mailing lists</title>
+</head>
+<body>
+    {msg_html}
+    {content_html}
+    <h2>New list</h2>
+    {form_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+pub async fn edit_mailing_list(
+    list_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let msg_html = flash_messages_html(&flash_messages);
+    let list = match get_mailing_list(&pool, *list_id).await.map_err(e500)? {
+        Some(list) => list,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let form_html = list_form_html(&format!("/admin/lists/{}", list_id), Some(&list));
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Edit list</title>
+</head>
+<body>
+    {msg_html}
+    {form_html}
+    <p><a href="/admin/lists">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+/// The form to create a list, or to edit `list`.
+fn list_form_html(action: &str, list: Option<&MailingList>) -> String {
+    let value =
+        |f: fn(&MailingList) -> &str| htmlescape::encode_minimal(list.map(f).unwrap_or_default());
+    format!(
+        r#"<form action="{action}" method="post">
+        <label>Name:<br>
+            <input type="text" name="name" value="{name}">
+        </label>
+        <br>
+        <label>Description (shown to the subscribers):<br>
+            <textarea name="description" rows="3" cols="50">{description}</textarea>
+        </label>
+        <br>
+        <label>
+            <input type="checkbox" name="is_default"{checked}>
+            Use for the subscriptions and issues that do not pick a list
+        </label>
+        <br>
+        <button type="submit">Save</button>
+    </form>"#,
+        name = value(|l| &l.name),
+        description = value(|l| &l.description),
+        checked = if list.is_some_and(|l| l.is_default) {
+            " checked"
+        } else {
+            ""
+        },
+    )
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_list_summaries(pool: &PgPool) -> Result<Vec<ListSummary>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        ListSummary,
+        r#"
+        SELECT
+            l.list_id,
+            l.name,
+            l.is_default,
+            COUNT(m.subscriber_id) FILTER (WHERE m.status = 'confirmed') AS "n_confirmed!",
+            COUNT(m.subscriber_id) FILTER (WHERE m.status = 'pending_confirmation') AS "n_pending!"
+        FROM lists l
+        LEFT JOIN list_memberships m ON m.list_id = l.list_id
+        GROUP BY l.list_id
+        ORDER BY l.name
+    "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the mailing lists")?;
+    Ok(lists)
+}
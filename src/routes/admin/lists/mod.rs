@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{edit_mailing_list, mailing_lists};
+pub use post::{create_mailing_list, update_mailing_list};
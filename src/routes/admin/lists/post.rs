@@ -0,0 +1,154 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::utils::{e500, redirect};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    #[serde(default)]
+    description: String,
+    // Checkboxes are only sent when ticked
+    is_default: Option<String>,
+}
+
+enum SaveOutcome {
+    Saved,
+    NameTaken,
+    NotFound,
+    // Subscribing without picking a list needs a default one
+    DefaultRequired,
+}
+
+#[tracing::instrument(name = "Create a mailing list", skip(form, pool), fields(name = %form.name))]
+pub async fn create_mailing_list(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if form.name.trim().is_empty() {
+        FlashMessage::error("The list needs a name.").send();
+        return Ok(redirect("/admin/lists"));
+    }
+    let list_id = Uuid::new_v4();
+    match save(&pool, list_id, &form, true).await.map_err(e500)? {
+        SaveOutcome::Saved => {
+            FlashMessage::info("The list has been saved.").send();
+            Ok(redirect(&format!("/admin/lists/{}", list_id)))
+        }
+        _ => {
+            FlashMessage::error(format!("A list named {} already exists.", form.name)).send();
+            Ok(redirect("/admin/lists"))
+        }
+    }
+}
+
+#[tracing::instrument(name = "Update a mailing list", skip(form, pool), fields(name = %form.name))]
+pub async fn update_mailing_list(
+    list_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let list_id = list_id.into_inner();
+    let list_page = format!("/admin/lists/{}", list_id);
+    if form.name.trim().is_empty() {
+        FlashMessage::error("The list needs a name.").send();
+        return Ok(redirect(&list_page));
+    }
+    match save(&pool, list_id, &form, false).await.map_err(e500)? {
+        SaveOutcome::Saved => FlashMessage::info("The list has been saved.").send(),
+        SaveOutcome::NameTaken => {
+            FlashMessage::error(format!("A list named {} already exists.", form.name)).send()
+        }
+        SaveOutcome::DefaultRequired => {
+            FlashMessage::error("Make another list the default one first.").send()
+        }
+        SaveOutcome::NotFound => {
+            FlashMessage::error("This list does not exist anymore.").send();
+            return Ok(redirect("/admin/lists"));
+        }
+    }
+    Ok(redirect(&list_page))
+}
+
+#[tracing::instrument(skip(pool, form))]
+async fn save(
+    pool: &PgPool,
+    list_id: Uuid,
+    form: &FormData,
+    create: bool,
+) -> Result<SaveOutcome, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let is_default = form.is_default.is_some();
+    // There is a single default list, and there is always one
+    if is_default {
+        sqlx::query!(
+            "UPDATE lists SET is_default = false WHERE is_default AND list_id <> $1",
+            list_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to unset the previous default list")?;
+    } else if !create {
+        let was_default = sqlx::query!(
+            "SELECT is_default FROM lists WHERE list_id = $1 FOR UPDATE",
+            list_id
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to retrieve the mailing list")?
+        .is_some_and(|r| r.is_default);
+        if was_default {
+            return Ok(SaveOutcome::DefaultRequired);
+        }
+    }
+    let outcome = if create {
+        sqlx::query!(
+            r#"
+            INSERT INTO lists (list_id, name, description, is_default)
+            VALUES ($1, $2, $3, $4)
+        "#,
+            list_id,
+            form.name.trim(),
+            form.description.trim(),
+            is_default,
+        )
+        .execute(&mut *transaction)
+        .await
+    } else {
+        sqlx::query!(
+            r#"
+            UPDATE lists
+            SET name = $2, description = $3, is_default = $4
+            WHERE list_id = $1
+        "#,
+            list_id,
+            form.name.trim(),
+            form.description.trim(),
+            is_default,
+        )
+        .execute(&mut *transaction)
+        .await
+    };
+    match outcome {
+        Ok(result) if result.rows_affected() == 0 => return Ok(SaveOutcome::NotFound),
+        Ok(_) => {}
+        Err(e)
+            if e.as_database_error()
+                .is_some_and(|e| e.is_unique_violation()) =>
+        {
+            return Ok(SaveOutcome::NameTaken)
+        }
+        Err(e) => return Err(e).context("Failed to save the mailing list"),
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to save a mailing list")?;
+    Ok(SaveOutcome::Saved)
+}
@@ -1,9 +1,19 @@
 mod dashboard;
+mod deliveries;
+mod drafts;
+mod lists;
 mod logout;
 mod newsletter;
 mod password;
+mod subscribers;
+mod templates;
 
 pub use dashboard::admin_dashboard;
+pub use deliveries::*;
+pub use drafts::*;
+pub use lists::*;
 pub use logout::logout;
 pub use newsletter::*;
 pub use password::{change_password, change_password_form};
+pub use subscribers::*;
+pub use templates::*;
@@ -0,0 +1,130 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::utils::{e500, redirect};
+
+#[tracing::instrument(name = "Pause a newsletter issue", skip(pool))]
+pub async fn pause_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    if update_status(&pool, newsletter_issue_id, "sending", "paused")
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The newsletter issue has been paused.").send();
+    } else {
+        FlashMessage::error("This newsletter issue is not being sent.").send();
+    }
+    Ok(redirect(&format!(
+        "/admin/newsletters/{}",
+        newsletter_issue_id
+    )))
+}
+
+#[tracing::instrument(name = "Resume a newsletter issue", skip(pool))]
+pub async fn resume_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    if update_status(&pool, newsletter_issue_id, "paused", "sending")
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The newsletter issue has been resumed.").send();
+    } else {
+        FlashMessage::error("This newsletter issue is not paused.").send();
+    }
+    Ok(redirect(&format!(
+        "/admin/newsletters/{}",
+        newsletter_issue_id
+    )))
+}
+
+#[tracing::instrument(name = "Cancel a newsletter issue", skip(pool))]
+pub async fn cancel_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    if cancel(&pool, newsletter_issue_id).await.map_err(e500)? {
+        FlashMessage::info("The newsletter issue has been cancelled.").send();
+    } else {
+        FlashMessage::error("This newsletter issue can no longer be cancelled.").send();
+    }
+    Ok(redirect(&format!(
+        "/admin/newsletters/{}",
+        newsletter_issue_id
+    )))
+}
+
+/// Returns `false` if the issue was not in the `from` status.
+#[tracing::instrument(skip(pool))]
+async fn update_status(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    from: &str,
+    to: &str,
+) -> Result<bool, anyhow::Error> {
+    // The worker does not dequeue the deliveries of paused issues.
+    // The ones it is already sending when the issue gets paused still go out.
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = $3, updated_at = now()
+        WHERE newsletter_issue_id = $1 AND status = $2
+    "#,
+        newsletter_issue_id,
+        from,
+        to
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the status of the newsletter issue")?;
+    Ok(updated.rows_affected() == 1)
+}
+
+/// Drop the deliveries of an issue that has not completely gone out yet.
+/// Returns `false` if the issue is already sent (or cancelled).
+#[tracing::instrument(skip(pool))]
+async fn cancel(pool: &PgPool, newsletter_issue_id: Uuid) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'cancelled', updated_at = now()
+        WHERE newsletter_issue_id = $1 AND status IN ('scheduled', 'sending', 'paused')
+    "#,
+        newsletter_issue_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to cancel the newsletter issue")?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+    // Waits for the deliveries a worker is currently sending, if any
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_table
+        WHERE newsletter_issue_id = $1
+    "#,
+        newsletter_issue_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the delivery tasks")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to cancel a newsletter issue")?;
+    Ok(true)
+}
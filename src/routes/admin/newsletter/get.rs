@@ -1,15 +1,39 @@
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
 use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::email_layout::get_default_email_layout_id;
+use crate::mailing_lists::{get_mailing_lists, mailing_list_select};
+use crate::routes::admin::templates::email_template_select;
+use crate::utils::{e500, flash_messages_html};
 
 pub async fn publish_newsletter_form(
+    pool: web::Data<PgPool>,
     flash_messages: IncomingFlashMessages,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let mut msg_html = String::new();
-    for m in flash_messages.iter() {
-        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    let msg_html = flash_messages_html(&flash_messages);
+    let mut issues_html = String::new();
+    for issue in get_recent_issues(&pool).await.map_err(e500)? {
+        writeln!(
+            issues_html,
+            r#"<li><a href="/admin/newsletters/{}">{}</a> ({})</li>"#,
+            issue.newsletter_issue_id,
+            htmlescape::encode_minimal(&issue.title),
+            issue.published_at.format("%Y-%m-%d %H:%M UTC")
+        )
+        .unwrap();
     }
+    let default_layout = get_default_email_layout_id(&pool).await.map_err(e500)?;
+    let layout_html = email_template_select(&pool, default_layout)
+        .await
+        .map_err(e500)?;
+    let lists = get_mailing_lists(&pool).await.map_err(e500)?;
+    let list_html = mailing_list_select(&lists, None);
     let idempotency_key = uuid::Uuid::new_v4().to_string();
 
     Ok(HttpResponse::Ok()
@@ -32,6 +56,14 @@ pub async fn publish_newsletter_form(
             >
         </label>
         <br>
+        <label>Subject preview (shown next to the subject in the inbox):<br>
+            <input
+                type="text"
+                placeholder="Enter a short summary"
+                name="subject_preview"
+            >
+        </label>
+        <br>
         <label>Plain text content:<br>
             <textarea
                 placeholder="Enter the content in plain text"
@@ -50,11 +82,65 @@ pub async fn publish_newsletter_form(
             ></textarea>
         </label>
         <br>
+        <label>Markdown content (replaces the plain text and HTML content):<br>
+            <textarea
+                placeholder="Enter the content in Markdown"
+                name="markdown_content"
+                rows="20"
+                cols="50"
+            ></textarea>
+        </label>
+        <br>
+        <p>Placeholders: <code>{{{{ username }}}}</code>, <code>{{{{ unsubscribe_url }}}}</code>, <code>{{{{ web_view_url }}}}</code></p>
+        {list_html}
+        <br>
+        {layout_html}
+        <br>
+        <label>
+            <input type="checkbox" name="public" checked>
+            Show in the public archive
+        </label>
+        <br>
+        <label>Send at (UTC, leave empty to send right away):<br>
+            <input type="datetime-local" name="scheduled_for">
+        </label>
+        <br>
         <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
         <button type="submit">Publish</button>
+        <button type="submit" formaction="/admin/drafts">Save as draft</button>
     </form>
+    <p>To send yourself a test email first, save the issue as a draft.</p>
+    <p><a href="/admin/drafts">Drafts</a></p>
+    <p>Recent issues:</p>
+    <ul>
+    {issues_html}
+    </ul>
     <p><a href="/admin/dashboard">&lt;- Back</a></p>
 </body>
 </html>"#,
         )))
 }
+
+struct RecentIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_recent_issues(pool: &PgPool) -> Result<Vec<RecentIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        RecentIssue,
+        r#"
+        SELECT newsletter_issue_id, title, published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE status <> 'draft'
+        ORDER BY published_at DESC
+        LIMIT 10
+    "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the recent newsletter issues")?;
+    Ok(issues)
+}
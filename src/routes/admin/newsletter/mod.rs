@@ -1,5 +1,13 @@
+mod broadcast;
 mod get;
 mod post;
+mod schedule;
+mod status;
+mod visibility;
 
+pub use broadcast::{cancel_newsletter_issue, pause_newsletter_issue, resume_newsletter_issue};
 pub use get::publish_newsletter_form;
 pub use post::publish_newsletter;
+pub use schedule::reschedule_newsletter_issue;
+pub use status::newsletter_issue_status;
+pub use visibility::update_newsletter_issue_visibility;
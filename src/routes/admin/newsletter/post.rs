@@ -1,19 +1,40 @@
 use crate::authentication::UserId;
+use crate::domain::{IssueSlug, MarkdownContent};
 use crate::idempotency::{save_response, try_processing, IdempotencyKey};
+use crate::issues_delivery_worker::NewsletterIssue;
+use crate::mailing_lists::selected_mailing_list;
+use crate::routes::admin::templates::selected_email_template;
 use crate::utils::{e400, e500, redirect};
 use actix_web::web::ReqData;
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
-use sqlx::{PgPool, Postgres, Transaction};
+use chrono::{DateTime, Utc};
+use sqlx::{Acquire, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
+use super::schedule::parse_send_time;
+
 #[derive(serde::Deserialize)]
 pub struct FormData {
-    title: String,
-    text_content: String,
-    html_content: String,
+    // The content comes from the draft when publishing one
+    draft_id: Option<Uuid>,
+    title: Option<String>,
+    // Shown next to the subject in the inbox
+    subject_preview: Option<String>,
+    text_content: Option<String>,
+    html_content: Option<String>,
+    // Replaces both the HTML and the plain text content when filled in
+    markdown_content: Option<String>,
+    // Missing to use the default layout, empty for no layout
+    email_template_id: Option<String>,
+    // Checkboxes are only sent when ticked
+    public: Option<String>,
+    // Missing to send the issue to the default list
+    list_id: Option<String>,
     idempotency_key: String,
+    // Empty to send the issue right away
+    scheduled_for: Option<String>,
 }
 
 #[tracing::instrument(
@@ -28,12 +49,76 @@ pub async fn publish_newsletter(
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     let FormData {
+        draft_id,
         title,
+        subject_preview,
         text_content,
         html_content,
+        markdown_content,
+        email_template_id,
+        public,
+        list_id,
         idempotency_key,
+        scheduled_for,
     } = form.0;
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let markdown_content = match markdown_content {
+        Some(s) if !s.trim().is_empty() => Some(MarkdownContent::parse(s).map_err(e400)?),
+        _ => None,
+    };
+    let content = match (
+        draft_id,
+        title,
+        markdown_content,
+        text_content,
+        html_content,
+    ) {
+        (Some(draft_id), ..) => IssueContent::Draft(draft_id),
+        (None, Some(title), Some(markdown_content), _, _) => IssueContent::Form {
+            title,
+            text_content: markdown_content.to_text(),
+            html_content: markdown_content.to_html(),
+            markdown_content: Some(markdown_content.as_ref().to_owned()),
+        },
+        (None, Some(title), None, Some(text_content), Some(html_content)) => IssueContent::Form {
+            title,
+            text_content,
+            html_content,
+            markdown_content: None,
+        },
+        _ => return Err(e400("The title and the content of the issue are missing.")),
+    };
+    // Reject unknown placeholders before anything goes out
+    if let IssueContent::Form {
+        title,
+        text_content,
+        html_content,
+        ..
+    } = &content
+    {
+        if let Err(e) = NewsletterIssue::parse(title.clone(), html_content, text_content) {
+            FlashMessage::error(e).send();
+            return Ok(redirect("/admin/newsletters"));
+        }
+    }
+    let scheduled_for = match parse_send_time(scheduled_for.as_deref()) {
+        Ok(scheduled_for) => scheduled_for,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(redirect("/admin/newsletters"));
+        }
+    };
+    let list = match selected_mailing_list(&pool, list_id.as_deref())
+        .await
+        .map_err(e500)?
+    {
+        Some(list) => list,
+        None => {
+            FlashMessage::error("Choose the list the issue is sent to.").send();
+            return Ok(redirect("/admin/newsletters"));
+        }
+    };
+    let email_template_id = selected_email_template(&pool, email_template_id.as_deref()).await?;
     // try_processing will first insert into the idemptency table the value of user_id,
     // idempotency_key without the response data to handle concurrent requests
     // it then returns the transaction
@@ -47,21 +132,75 @@ pub async fn publish_newsletter(
         // the first request below to return back to the client
         // ensure idempotent operations for concurrent requests
         crate::idempotency::NextAction::ReturnedHttpResponse(response) => {
-            success_message().send();
+            success_message(scheduled_for).send();
             return Ok(response);
         }
     };
     // To ensure fault tolerance, we have to use the forward recovery - active recovery in which
     // we limit the scope of our POST /admin/newsletter to asynchronously send issues to all emails in the background
     // instead of performing all the sending before responsing back to the users.
-    let issue_id =
-        insert_into_newsletter_issue(&mut transaction, &title, &html_content, &text_content)
-            .await
-            .map_err(e500)?;
-    enqueue_delivery_task(&mut transaction, issue_id)
+    let (issue_id, title) = match content {
+        IssueContent::Form {
+            title,
+            text_content,
+            html_content,
+            markdown_content,
+        } => {
+            let new_issue = NewIssue {
+                title: &title,
+                html_content: &html_content,
+                text_content: &text_content,
+                markdown_content: markdown_content.as_deref(),
+                email_template_id,
+                is_public: public.is_some(),
+                subject_preview: subject_preview
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty()),
+                author_user_id: *user_id,
+                list_id: list.list_id,
+            };
+            let issue_id =
+                insert_into_newsletter_issue(&mut transaction, &new_issue, scheduled_for)
+                    .await
+                    .map_err(e500)?;
+            (issue_id, title)
+        }
+        IssueContent::Draft(draft_id) => {
+            // Dropping the transaction releases the idempotency key
+            // and leaves the draft untouched
+            let draft = match publish_draft(&mut transaction, draft_id, list.list_id, scheduled_for)
+                .await
+                .map_err(e500)?
+            {
+                Some(draft) => draft,
+                None => {
+                    FlashMessage::error("This draft has already been published.").send();
+                    return Ok(redirect("/admin/newsletters"));
+                }
+            };
+            if let Err(e) = NewsletterIssue::parse(
+                draft.title.clone(),
+                &draft.html_content,
+                &draft.text_content,
+            ) {
+                FlashMessage::error(e).send();
+                return Ok(redirect(&format!("/admin/drafts/{}", draft_id)));
+            }
+            (draft_id, draft.title)
+        }
+    };
+    assign_slug(&mut transaction, issue_id, &title)
         .await
         .map_err(e500)?;
-    success_message().send();
+    // Scheduled issues are sent to whoever is on the list once they are due,
+    // the worker enqueues their deliveries then
+    if scheduled_for.is_none() {
+        enqueue_delivery_task(&mut transaction, issue_id, list.list_id)
+            .await
+            .map_err(e500)?;
+    }
+    success_message(scheduled_for).send();
     let response = redirect("/admin/newsletters");
     // Here, we continue to use the transaction to update the response data in the table
     // and commit the transaction
@@ -71,45 +210,195 @@ pub async fn publish_newsletter(
     Ok(response)
 }
 
-fn success_message() -> FlashMessage {
-    FlashMessage::info("The newsletter issue has been accepted - emails will go out shortly.")
+enum IssueContent {
+    Form {
+        title: String,
+        text_content: String,
+        html_content: String,
+        markdown_content: Option<String>,
+    },
+    Draft(Uuid),
+}
+
+fn success_message(scheduled_for: Option<DateTime<Utc>>) -> FlashMessage {
+    match scheduled_for {
+        Some(scheduled_for) => FlashMessage::info(format!(
+            "The newsletter issue has been scheduled for {}.",
+            scheduled_for.format("%Y-%m-%d %H:%M UTC")
+        )),
+        None => FlashMessage::info(
+            "The newsletter issue has been accepted - emails will go out shortly.",
+        ),
+    }
+}
+
+struct NewIssue<'a> {
+    title: &'a str,
+    html_content: &'a str,
+    text_content: &'a str,
+    markdown_content: Option<&'a str>,
+    email_template_id: Option<Uuid>,
+    is_public: bool,
+    subject_preview: Option<&'a str>,
+    author_user_id: Uuid,
+    list_id: Uuid,
 }
 
 #[tracing::instrument(skip_all)]
 async fn insert_into_newsletter_issue(
     transaction: &mut Transaction<'static, Postgres>,
-    title: &str,
-    html_content: &str,
-    text_content: &str,
+    new_issue: &NewIssue<'_>,
+    scheduled_for: Option<DateTime<Utc>>,
 ) -> Result<Uuid, anyhow::Error> {
     let newsletter_issue_id = Uuid::new_v4();
+    let status = publication_status(scheduled_for);
     sqlx::query!(
         r#"
-        INSERT INTO newsletter_issues (newsletter_issue_id, title, html_content, text_content, published_at)
-        VALUES ($1, $2, $3, $4, now())
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, title, html_content, text_content, markdown_content,
+            email_template_id, is_public, subject_preview, author_user_id, list_id,
+            published_at, status, scheduled_for
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, COALESCE($12, now()), $11, $12)
     "#,
         newsletter_issue_id,
-        title,
-        html_content,
-        text_content,
-    ).execute(&mut **transaction)
-    .await.context("Failed to insert into newsletter_issue")?;
+        new_issue.title,
+        new_issue.html_content,
+        new_issue.text_content,
+        new_issue.markdown_content,
+        new_issue.email_template_id,
+        new_issue.is_public,
+        new_issue.subject_preview,
+        new_issue.author_user_id,
+        new_issue.list_id,
+        status,
+        scheduled_for,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to insert into newsletter_issue")?;
     Ok(newsletter_issue_id)
 }
 
+/// Published issues get the address of their web version from their title.
+/// When another issue already uses it, the start of the issue id is appended.
+#[tracing::instrument(skip(transaction))]
+async fn assign_slug(
+    transaction: &mut Transaction<'static, Postgres>,
+    newsletter_issue_id: Uuid,
+    title: &str,
+) -> Result<(), anyhow::Error> {
+    let slug = IssueSlug::from_title(title);
+    // Two issues with the same title can be published at the same time: the unique
+    // constraint has the final word. The savepoint keeps the transaction usable
+    // when it rejects the title alone.
+    let mut savepoint = Acquire::begin(&mut **transaction)
+        .await
+        .context("Failed to create a savepoint")?;
+    match set_slug(&mut savepoint, newsletter_issue_id, slug.as_ref()).await {
+        Ok(()) => savepoint
+            .commit()
+            .await
+            .context("Failed to release the savepoint")?,
+        Err(e)
+            if e.as_database_error()
+                .is_some_and(|e| e.is_unique_violation()) =>
+        {
+            savepoint
+                .rollback()
+                .await
+                .context("Failed to roll back to the savepoint")?;
+            let suffix = &newsletter_issue_id.simple().to_string()[..8];
+            set_slug(
+                transaction,
+                newsletter_issue_id,
+                &format!("{}-{}", slug.as_ref(), suffix),
+            )
+            .await
+            .context("Failed to assign a slug to the newsletter issue")?;
+        }
+        Err(e) => return Err(e).context("Failed to assign a slug to the newsletter issue"),
+    }
+    Ok(())
+}
+
+async fn set_slug(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    slug: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE newsletter_issues SET slug = $2 WHERE newsletter_issue_id = $1",
+        newsletter_issue_id,
+        slug,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// A scheduled issue starts going out once its send time is reached.
+fn publication_status(scheduled_for: Option<DateTime<Utc>>) -> &'static str {
+    match scheduled_for {
+        Some(_) => "scheduled",
+        None => "sending",
+    }
+}
+
+struct PublishedDraft {
+    title: String,
+    html_content: String,
+    text_content: String,
+}
+
+/// Returns `None` if there is no draft with this id (anymore).
+#[tracing::instrument(skip(transaction))]
+async fn publish_draft(
+    transaction: &mut Transaction<'static, Postgres>,
+    draft_id: Uuid,
+    list_id: Uuid,
+    scheduled_for: Option<DateTime<Utc>>,
+) -> Result<Option<PublishedDraft>, anyhow::Error> {
+    let draft = sqlx::query_as!(
+        PublishedDraft,
+        r#"
+        UPDATE newsletter_issues
+        SET status = $2,
+            published_at = COALESCE($3, now()),
+            updated_at = now(),
+            scheduled_for = $3,
+            list_id = $4
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+        RETURNING title, html_content, text_content
+    "#,
+        draft_id,
+        publication_status(scheduled_for),
+        scheduled_for,
+        list_id,
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to publish the draft")?;
+    Ok(draft)
+}
+
 #[tracing::instrument(skip_all)]
 async fn enqueue_delivery_task(
     transaction: &mut Transaction<'static, Postgres>,
     newsletter_issue_id: Uuid,
+    list_id: Uuid,
 ) -> Result<(), anyhow::Error> {
+    // Only the confirmed members of the list of the issue receive it
     sqlx::query!(
         r#"
         INSERT INTO issue_delivery_table (newsletter_issue_id, subscriber_email)
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
+        SELECT $1, s.email
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        WHERE s.status = 'confirmed' AND m.list_id = $2 AND m.status = 'confirmed'
     "#,
-        newsletter_issue_id
+        newsletter_issue_id,
+        list_id
     )
     .execute(&mut **transaction)
     .await?;
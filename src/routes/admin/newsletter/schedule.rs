@@ -0,0 +1,125 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::utils::{e500, redirect};
+
+/// Parse the send time picked in a `datetime-local` input, interpreted as UTC.
+/// An empty value means "send right away".
+pub(super) fn parse_send_time(input: Option<&str>) -> Result<Option<DateTime<Utc>>, String> {
+    let input = match input.map(str::trim) {
+        None | Some("") => return Ok(None),
+        Some(input) => input,
+    };
+    let send_time = NaiveDateTime::parse_from_str(input, "%Y-%m-%dT%H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(input, "%Y-%m-%dT%H:%M:%S"))
+        .map_err(|_| format!("{} is not a valid send time.", input))?
+        .and_utc();
+    if send_time <= Utc::now() {
+        return Err("The send time must be in the future.".into());
+    }
+    Ok(Some(send_time))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RescheduleFormData {
+    scheduled_for: String,
+}
+
+#[tracing::instrument(name = "Reschedule a newsletter issue", skip(form, pool))]
+pub async fn reschedule_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<RescheduleFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let issue_page = format!("/admin/newsletters/{}", newsletter_issue_id);
+    let scheduled_for = match parse_send_time(Some(&form.scheduled_for)) {
+        Ok(Some(scheduled_for)) => scheduled_for,
+        Ok(None) => {
+            FlashMessage::error("Please pick a new send time.").send();
+            return Ok(redirect(&issue_page));
+        }
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(redirect(&issue_page));
+        }
+    };
+    if reschedule(&pool, newsletter_issue_id, scheduled_for)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info(format!(
+            "The newsletter issue has been rescheduled for {}.",
+            scheduled_for.format("%Y-%m-%d %H:%M UTC")
+        ))
+        .send();
+    } else {
+        FlashMessage::error("This newsletter issue can no longer be rescheduled.").send();
+    }
+    Ok(redirect(&issue_page))
+}
+
+/// Move the send time of an issue that has not gone out yet.
+/// Returns `false` if the issue is not scheduled anymore.
+#[tracing::instrument(skip(pool))]
+async fn reschedule(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    scheduled_for: DateTime<Utc>,
+) -> Result<bool, anyhow::Error> {
+    // The deliveries of an issue are only enqueued once it is due,
+    // so an issue still scheduled in the future has not been sent to anyone
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET scheduled_for = $2, published_at = $2, updated_at = now()
+        WHERE newsletter_issue_id = $1 AND status = 'scheduled' AND scheduled_for > now()
+    "#,
+        newsletter_issue_id,
+        scheduled_for
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the schedule of the newsletter issue")?;
+    Ok(updated.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use claims::{assert_err, assert_none};
+
+    use super::parse_send_time;
+
+    #[test]
+    fn an_empty_send_time_means_right_away() {
+        assert_none!(parse_send_time(None).unwrap());
+        assert_none!(parse_send_time(Some("")).unwrap());
+    }
+
+    #[test]
+    fn a_future_send_time_is_accepted() {
+        let send_time = (Utc::now() + Duration::days(1)).format("%Y-%m-%dT%H:%M");
+        let parsed = parse_send_time(Some(&send_time.to_string()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            parsed.format("%Y-%m-%dT%H:%M").to_string(),
+            send_time.to_string()
+        );
+    }
+
+    #[test]
+    fn a_past_send_time_is_rejected() {
+        assert_err!(parse_send_time(Some("2020-01-01T10:00")));
+    }
+
+    #[test]
+    fn a_malformed_send_time_is_rejected() {
+        assert_err!(parse_send_time(Some("next tuesday")));
+    }
+}
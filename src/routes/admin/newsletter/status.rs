@@ -0,0 +1,235 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::utils::{e500, flash_messages_html};
+
+struct IssueProgress {
+    title: String,
+    slug: Option<String>,
+    is_public: bool,
+    author: Option<String>,
+    published_at: DateTime<Utc>,
+    status: String,
+    scheduled_for: Option<DateTime<Utc>>,
+    sent: i64,
+    skipped: i64,
+    pending: i64,
+    retrying: i64,
+    failed: i64,
+}
+
+impl IssueProgress {
+    fn total_recipients(&self) -> i64 {
+        self.sent + self.skipped + self.pending + self.retrying + self.failed
+    }
+
+    /// Only issues that are `sending` can be paused
+    fn is_in_progress(&self) -> bool {
+        self.status == "sending" && self.pending + self.retrying > 0
+    }
+
+    /// Due scheduled issues are enqueued on the next run of the worker
+    fn is_starting(&self) -> bool {
+        self.status == "scheduled" && !self.is_upcoming()
+    }
+
+    /// Paused and cancelled issues went out to some of the recipients only
+    fn is_interrupted(&self) -> bool {
+        matches!(self.status.as_str(), "paused" | "cancelled")
+    }
+
+    /// Scheduled issues can be changed until their send time
+    fn is_upcoming(&self) -> bool {
+        self.status == "scheduled" && self.scheduled_for.is_some_and(|t| t > Utc::now())
+    }
+
+    fn state(&self) -> String {
+        match self.status.as_str() {
+            "cancelled" => "Cancelled".into(),
+            "paused" => "Paused".into(),
+            _ if self.is_upcoming() => match self.scheduled_for {
+                Some(t) => format!("Scheduled for {}", t.format("%Y-%m-%d %H:%M UTC")),
+                None => "Scheduled".into(),
+            },
+            _ if self.is_starting() => "Starting".into(),
+            _ if self.is_in_progress() => "Sending".into(),
+            _ => "Done".into(),
+        }
+    }
+}
+
+pub async fn newsletter_issue_status(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let msg_html = flash_messages_html(&flash_messages);
+    let progress = match get_issue_progress(&pool, *newsletter_issue_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(progress) => progress,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    // Keep reloading the page while the worker is still going through the queue
+    let refresh_html = if progress.is_in_progress() || progress.is_starting() {
+        r#"<meta http-equiv="refresh" content="5">"#
+    } else {
+        ""
+    };
+    let id = *newsletter_issue_id;
+    let cancel_html = format!(
+        r#"<form action="/admin/newsletters/{id}/cancel" method="post">
+        <button type="submit">Cancel</button>
+    </form>"#
+    );
+    let actions_html = if progress.is_upcoming() {
+        format!(
+            r#"<form action="/admin/newsletters/{id}/schedule" method="post">
+        <label>Send at (UTC):
+            <input type="datetime-local" name="scheduled_for">
+        </label>
+        <button type="submit">Reschedule</button>
+    </form>
+    {cancel_html}"#
+        )
+    } else if progress.is_in_progress() {
+        format!(
+            r#"<form action="/admin/newsletters/{id}/pause" method="post">
+        <button type="submit">Pause</button>
+    </form>
+    {cancel_html}"#
+        )
+    } else if progress.is_starting() {
+        cancel_html
+    } else if progress.status == "paused" {
+        format!(
+            r#"<form action="/admin/newsletters/{id}/resume" method="post">
+        <button type="submit">Resume</button>
+    </form>
+    {cancel_html}"#
+        )
+    } else {
+        String::new()
+    };
+    let visibility_html = match (&progress.slug, progress.is_public) {
+        (Some(slug), true) => format!(
+            r#"<p>In the <a href="/issues/{slug}">public archive</a>.</p>
+    <form action="/admin/newsletters/{id}/visibility" method="post">
+        <input hidden type="text" name="public" value="false">
+        <button type="submit">Remove from the public archive</button>
+    </form>"#
+        ),
+        _ => format!(
+            r#"<p>Not in the public archive.</p>
+    <form action="/admin/newsletters/{id}/visibility" method="post">
+        <input hidden type="text" name="public" value="true">
+        <button type="submit">Add to the public archive</button>
+    </form>"#
+        ),
+    };
+    // Issues written before authors were recorded have none
+    let author_html = match &progress.author {
+        Some(author) => format!("<p>Written by {}</p>", htmlescape::encode_minimal(author)),
+        None => String::new(),
+    };
+    let interrupted_html = if progress.is_interrupted() {
+        format!(
+            "<p>{} recipient(s) had already received it.</p>",
+            progress.sent
+        )
+    } else {
+        String::new()
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    {refresh_html}
+    <title>Newsletter issue</title>
+</head>
+<body>
+    {msg_html}
+    <h1>{title}</h1>
+    <p>Published at {published_at} - {state}</p>
+    {author_html}
+    {interrupted_html}
+    {actions_html}
+    {visibility_html}
+    <table>
+        <tr><th>Total recipients</th><td>{total}</td></tr>
+        <tr><th>Sent</th><td>{sent}</td></tr>
+        <tr><th>Pending</th><td>{pending}</td></tr>
+        <tr><th>Retrying</th><td>{retrying}</td></tr>
+        <tr><th>Failed</th><td>{failed}</td></tr>
+        <tr><th>Skipped</th><td>{skipped}</td></tr>
+    </table>
+    <p><a href="/admin/deliveries/failures">Failed deliveries</a></p>
+    <p><a href="/admin/newsletters">&lt;- Back</a></p>
+</body>
+</html>"#,
+            title = htmlescape::encode_minimal(&progress.title),
+            published_at = progress.published_at.format("%Y-%m-%d %H:%M UTC"),
+            state = progress.state(),
+            total = progress.total_recipients(),
+            sent = progress.sent,
+            pending = progress.pending,
+            retrying = progress.retrying,
+            failed = progress.failed,
+            skipped = progress.skipped,
+        )))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_issue_progress(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<IssueProgress>, anyhow::Error> {
+    // Deliveries still in the queue are either pending (never attempted)
+    // or retrying, the others have a final outcome in the history
+    let progress = sqlx::query_as!(
+        IssueProgress,
+        r#"
+        SELECT
+            i.title,
+            i.slug,
+            i.is_public,
+            u.username AS "author?",
+            i.published_at AS "published_at!",
+            i.status,
+            i.scheduled_for,
+            (SELECT COUNT(DISTINCT d.subscriber_email) FROM newsletter_issue_deliveries d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id AND d.status = 'sent')
+                AS "sent!",
+            (SELECT COUNT(DISTINCT d.subscriber_email) FROM newsletter_issue_deliveries d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id AND d.status = 'skipped')
+                AS "skipped!",
+            (SELECT COUNT(DISTINCT q.subscriber_email) FROM issue_delivery_table q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id AND q.n_retries = 0)
+                AS "pending!",
+            (SELECT COUNT(DISTINCT q.subscriber_email) FROM issue_delivery_table q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id AND q.n_retries > 0)
+                AS "retrying!",
+            (SELECT COUNT(DISTINCT f.subscriber_email) FROM issue_delivery_failures f
+                WHERE f.newsletter_issue_id = i.newsletter_issue_id)
+                AS "failed!"
+        FROM newsletter_issues i
+        LEFT JOIN users u ON u.user_id = i.author_user_id
+        WHERE i.newsletter_issue_id = $1 AND i.status <> 'draft'
+    "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the progress of the newsletter issue")?;
+    Ok(progress)
+}
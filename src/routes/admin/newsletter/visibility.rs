@@ -0,0 +1,47 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::utils::{e500, redirect};
+
+#[derive(serde::Deserialize)]
+pub struct VisibilityFormData {
+    public: bool,
+}
+
+#[tracing::instrument(name = "Change the visibility of a newsletter issue", skip(form, pool))]
+pub async fn update_newsletter_issue_visibility(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<VisibilityFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    // Drafts get their visibility from the draft form
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET is_public = $2, updated_at = now()
+        WHERE newsletter_issue_id = $1 AND status <> 'draft'
+    "#,
+        newsletter_issue_id,
+        form.public
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the visibility of the newsletter issue")
+    .map_err(e500)?;
+    if updated.rows_affected() == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    if form.public {
+        FlashMessage::info("The newsletter issue is now in the public archive.").send();
+    } else {
+        FlashMessage::info("The newsletter issue has been removed from the public archive.").send();
+    }
+    Ok(redirect(&format!(
+        "/admin/newsletters/{}",
+        newsletter_issue_id
+    )))
+}
@@ -1,14 +1,12 @@
 use actix_web::{http::header::ContentType, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
-use core::fmt::Write;
+
+use crate::utils::flash_messages_html;
 
 pub async fn change_password_form(
     flash_messages: IncomingFlashMessages,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let mut msg_html = String::new();
-    for m in flash_messages.iter() {
-        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
-    }
+    let msg_html = flash_messages_html(&flash_messages);
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -0,0 +1,319 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::Bytes;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use futures_util::TryStreamExt;
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use super::status_filter;
+use crate::utils::e400;
+
+// Chunks are sent once they reach this size, rather than one per subscriber
+const CHUNK_SIZE: usize = 64 * 1024;
+// How many chunks can wait for a slow client before we stop reading the database
+const PENDING_CHUNKS: usize = 4;
+
+const CSV_COLUMNS: [&str; 9] = [
+    "id",
+    "email",
+    "username",
+    "status",
+    "subscribed_at",
+    "confirmed_at",
+    "unsubscribed_at",
+    "consent_source",
+    "lists",
+];
+
+#[derive(serde::Deserialize)]
+pub struct ExportQuery {
+    // `csv` (the default) or `ndjson`
+    format: Option<String>,
+    status: Option<String>,
+    // Subscription dates, as YYYY-MM-DD, both included
+    from: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+#[derive(Debug)]
+struct ExportFilters {
+    status: Option<String>,
+    subscribed_from: Option<DateTime<Utc>>,
+    subscribed_before: Option<DateTime<Utc>>,
+}
+
+struct ExportedSubscriber {
+    id: Uuid,
+    email: String,
+    username: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    confirmed_at: Option<DateTime<Utc>>,
+    unsubscribed_at: Option<DateTime<Utc>>,
+    consent_source: Option<String>,
+    // The names of the lists they confirmed
+    lists: Vec<String>,
+    custom_fields: serde_json::Value,
+}
+
+impl ExportedSubscriber {
+    fn custom_field(&self, name: &str) -> &str {
+        self.custom_fields
+            .get(name)
+            .and_then(|value| value.as_str())
+            .unwrap_or_default()
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "email": self.email,
+            "username": self.username,
+            "status": self.status,
+            "subscribed_at": self.subscribed_at.to_rfc3339(),
+            "confirmed_at": self.confirmed_at.map(|date| date.to_rfc3339()),
+            "unsubscribed_at": self.unsubscribed_at.map(|date| date.to_rfc3339()),
+            "consent_source": self.consent_source,
+            "lists": self.lists,
+            "custom_fields": self.custom_fields,
+        })
+    }
+}
+
+/// Stream the subscribers as CSV or as one JSON object per line.
+/// The list can be long: the rows are written out as they come from the database.
+#[tracing::instrument(name = "Export the subscribers", skip(query, pool))]
+pub async fn export_subscribers(
+    query: web::Query<ExportQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let format = match query.format.as_deref() {
+        None | Some("") | Some("csv") => ExportFormat::Csv,
+        Some("ndjson") => ExportFormat::Ndjson,
+        Some(_) => return Err(e400("Subscribers can be exported as csv or ndjson.")),
+    };
+    let filters = ExportFilters {
+        status: status_filter(query.status.as_deref())?.map(String::from),
+        subscribed_from: parse_date(query.from.as_deref())?,
+        subscribed_before: parse_date(query.to.as_deref())?
+            .map(|to| to + chrono::Duration::days(1)),
+    };
+
+    // The database is read from its own task, which waits whenever the client
+    // is slower than the database and stops as soon as the client goes away
+    let (sender, receiver) = mpsc::channel(PENDING_CHUNKS);
+    let pool = pool.into_inner();
+    tokio::spawn(
+        async move {
+            if let Err(e) = write_subscribers(&pool, &filters, format, &sender).await {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to export the subscribers",
+                );
+                // The client gets a truncated response rather than a partial list that looks whole
+                let _ = sender.send(Err(e)).await;
+            }
+        }
+        .instrument(tracing::Span::current()),
+    );
+    let body = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+
+    let (content_type, extension) = match format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "csv"),
+        ExportFormat::Ndjson => ("application/x-ndjson", "ndjson"),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "subscribers-{}.{}",
+                Utc::now().format("%Y-%m-%d"),
+                extension
+            ))],
+        })
+        .streaming(body))
+}
+
+fn parse_date(date: Option<&str>) -> Result<Option<DateTime<Utc>>, actix_web::Error> {
+    match date {
+        None | Some("") => Ok(None),
+        Some(date) => {
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| e400(format!("{} is not a valid date.", date)))?;
+            Ok(Some(
+                Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap()),
+            ))
+        }
+    }
+}
+
+#[tracing::instrument(skip(pool, sender))]
+async fn write_subscribers(
+    pool: &PgPool,
+    filters: &ExportFilters,
+    format: ExportFormat,
+    sender: &mpsc::Sender<Result<Bytes, anyhow::Error>>,
+) -> Result<(), anyhow::Error> {
+    // The custom fields are looked up first, in the same snapshot as the subscribers
+    let mut transaction = pool.begin().await?;
+    sqlx::query!("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+        .execute(&mut *transaction)
+        .await?;
+    let custom_fields = match format {
+        ExportFormat::Csv => sqlx::query!(
+            r#"
+            SELECT DISTINCT jsonb_object_keys(custom_fields) AS "name!"
+            FROM subscriptions
+            WHERE ($1::text IS NULL OR status = $1)
+                AND ($2::timestamptz IS NULL OR subscribed_at >= $2)
+                AND ($3::timestamptz IS NULL OR subscribed_at < $3)
+            ORDER BY 1
+        "#,
+            filters.status,
+            filters.subscribed_from,
+            filters.subscribed_before
+        )
+        .fetch_all(&mut *transaction)
+        .await
+        .context("Failed to retrieve the custom fields")?
+        .into_iter()
+        .map(|r| r.name)
+        .collect(),
+        ExportFormat::Ndjson => Vec::new(),
+    };
+
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    if let ExportFormat::Csv = format {
+        let custom_headers: Vec<String> = custom_fields
+            .iter()
+            .map(|name| custom_field_header(name))
+            .collect();
+        write_csv_record(
+            &mut chunk,
+            CSV_COLUMNS
+                .iter()
+                .copied()
+                .chain(custom_headers.iter().map(String::as_str)),
+        )?;
+    }
+    let mut subscribers = sqlx::query_as!(
+        ExportedSubscriber,
+        r#"
+        SELECT id, email, username, status, subscribed_at, confirmed_at, unsubscribed_at,
+            consent_source, custom_fields,
+            ARRAY(
+                SELECT l.name
+                FROM list_memberships m
+                JOIN lists l ON l.list_id = m.list_id
+                WHERE m.subscriber_id = subscriptions.id AND m.status = 'confirmed'
+                ORDER BY l.name
+            ) AS "lists!"
+        FROM subscriptions
+        WHERE ($1::text IS NULL OR status = $1)
+            AND ($2::timestamptz IS NULL OR subscribed_at >= $2)
+            AND ($3::timestamptz IS NULL OR subscribed_at < $3)
+        ORDER BY subscribed_at, id
+    "#,
+        filters.status,
+        filters.subscribed_from,
+        filters.subscribed_before
+    )
+    .fetch(&mut *transaction);
+    while let Some(subscriber) = subscribers
+        .try_next()
+        .await
+        .context("Failed to retrieve the subscribers")?
+    {
+        match format {
+            ExportFormat::Csv => {
+                let format_date = |date: Option<DateTime<Utc>>| {
+                    date.map(|date| date.to_rfc3339()).unwrap_or_default()
+                };
+                let id = subscriber.id.to_string();
+                let subscribed_at = subscriber.subscribed_at.to_rfc3339();
+                let confirmed_at = format_date(subscriber.confirmed_at);
+                let unsubscribed_at = format_date(subscriber.unsubscribed_at);
+                let lists = subscriber.lists.join("; ");
+                let columns = [
+                    id.as_str(),
+                    &subscriber.email,
+                    &subscriber.username,
+                    &subscriber.status,
+                    &subscribed_at,
+                    &confirmed_at,
+                    &unsubscribed_at,
+                    subscriber.consent_source.as_deref().unwrap_or_default(),
+                    &lists,
+                ];
+                write_csv_record(
+                    &mut chunk,
+                    columns.into_iter().chain(
+                        custom_fields
+                            .iter()
+                            .map(|name| subscriber.custom_field(name)),
+                    ),
+                )?;
+            }
+            ExportFormat::Ndjson => {
+                serde_json::to_writer(&mut chunk, &subscriber.to_json())?;
+                chunk.push(b'\n');
+            }
+        }
+        if chunk.len() >= CHUNK_SIZE {
+            let full_chunk = std::mem::replace(&mut chunk, Vec::with_capacity(CHUNK_SIZE));
+            if sender.send(Ok(full_chunk.into())).await.is_err() {
+                // The client went away, there is nobody left to export to
+                return Ok(());
+            }
+        }
+    }
+    if !chunk.is_empty() {
+        let _ = sender.send(Ok(chunk.into())).await;
+    }
+    Ok(())
+}
+
+fn write_csv_record<'a>(
+    chunk: &mut Vec<u8>,
+    record: impl IntoIterator<Item = &'a str>,
+) -> Result<(), anyhow::Error> {
+    let mut writer = csv::Writer::from_writer(chunk);
+    for field in record {
+        // Spreadsheets run the cells starting with one of these as formulas
+        if field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+            writer.write_field(format!("'{}", field))?;
+        } else {
+            writer.write_field(field)?;
+        }
+    }
+    writer.write_record(None::<&[u8]>)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Custom fields named like one of the fixed columns get a prefix,
+/// so that every column of the export has its own name.
+fn custom_field_header(name: &str) -> String {
+    if CSV_COLUMNS
+        .iter()
+        .any(|column| column.eq_ignore_ascii_case(name))
+    {
+        format!("custom_{}", name)
+    } else {
+        name.to_string()
+    }
+}
@@ -0,0 +1,480 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, SecondsFormat, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use super::{status_filter, status_label, STATUSES};
+use crate::utils::{e400, e500, flash_messages_html};
+
+const SUBSCRIBERS_PER_PAGE: i64 = 50;
+
+#[derive(serde::Deserialize)]
+pub struct SubscribersQuery {
+    status: Option<String>,
+    // Matched against both the email and the username
+    q: Option<String>,
+    // The last subscriber of the previous page, see `Cursor`
+    after: Option<String>,
+}
+
+/// Pages are delimited by the last subscriber shown rather than by an offset,
+/// so that new subscribers do not shift the following pages.
+struct Cursor {
+    subscribed_at: DateTime<Utc>,
+    id: Uuid,
+}
+
+impl Cursor {
+    fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("{} is not a valid page.", s);
+        let (subscribed_at, id) = s.split_once('_').ok_or_else(invalid)?;
+        Ok(Self {
+            subscribed_at: DateTime::parse_from_rfc3339(subscribed_at)
+                .map_err(|_| invalid())?
+                .with_timezone(&Utc),
+            id: Uuid::parse_str(id).map_err(|_| invalid())?,
+        })
+    }
+
+    fn encode(subscribed_at: DateTime<Utc>, id: Uuid) -> String {
+        format!(
+            "{}_{}",
+            subscribed_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+            id
+        )
+    }
+}
+
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    username: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn subscribers(
+    query: web::Query<SubscribersQuery>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let msg_html = flash_messages_html(&flash_messages);
+    let status = status_filter(query.status.as_deref())?;
+    let search = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty());
+    let after = match query.after.as_deref() {
+        None | Some("") => None,
+        Some(after) => Some(Cursor::parse(after).map_err(e400)?),
+    };
+
+    let mut subscribers = get_subscribers(&pool, status, search, after.as_ref())
+        .await
+        .map_err(e500)?;
+    // One more subscriber than needed is fetched to know whether there is a next page
+    let next_page = if subscribers.len() as i64 > SUBSCRIBERS_PER_PAGE {
+        subscribers.truncate(SUBSCRIBERS_PER_PAGE as usize);
+        subscribers
+            .last()
+            .map(|last| Cursor::encode(last.subscribed_at, last.id))
+    } else {
+        None
+    };
+
+    let mut rows_html = String::new();
+    for subscriber in &subscribers {
+        writeln!(
+            rows_html,
+            r#"<tr>
+    <td><a href="/admin/subscribers/{id}">{email}</a></td>
+    <td>{username}</td>
+    <td>{status}</td>
+    <td>{subscribed_at}</td>
+</tr>"#,
+            id = subscriber.id,
+            email = htmlescape::encode_minimal(&subscriber.email),
+            username = htmlescape::encode_minimal(&subscriber.username),
+            status = status_label(&subscriber.status),
+            subscribed_at = subscriber.subscribed_at.format("%Y-%m-%d %H:%M UTC"),
+        )
+        .unwrap();
+    }
+    let content_html = if subscribers.is_empty() {
+        "<p>No subscribers.</p>".to_string()
+    } else {
+        format!(
+            r#"<table>
+<tr>
+    <th>Email</th>
+    <th>Name</th>
+    <th>Status</th>
+    <th>Subscribed at</th>
+</tr>
+{rows_html}</table>"#
+        )
+    };
+    // The filters are carried over from page to page
+    let filters = format!(
+        "status={}&q={}",
+        status.unwrap_or_default(),
+        urlencoding::encode(search.unwrap_or_default())
+    );
+    let mut pages_html = String::new();
+    if after.is_some() {
+        write!(
+            pages_html,
+            r#"<a href="/admin/subscribers?{filters}">First page</a> "#
+        )
+        .unwrap();
+    }
+    if let Some(next_page) = next_page {
+        write!(
+            pages_html,
+            r#"<a href="/admin/subscribers?{filters}&after={}">Next page</a>"#,
+            urlencoding::encode(&next_page)
+        )
+        .unwrap();
+    }
+    let mut status_options_html = String::new();
+    for (value, label) in STATUSES {
+        writeln!(
+            status_options_html,
+            r#"<option value="{value}"{selected}>{label}</option>"#,
+            selected = if status == Some(value) {
+                " selected"
+            } else {
+                ""
+            },
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscribers</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/subscribers" method="get">
+        <label>Status:
+            <select name="status">
+                <option value="">All</option>
+                {status_options_html}
+            </select>
+        </label>
+        <label>Search:
+            <input type="text" name="q" placeholder="Email or name" value="{search}">
+        </label>
+        <button type="submit">Filter</button>
+    </form>
+    {content_html}
+    <p>{pages_html}</p>
+    <form action="/admin/subscribers/export" method="get">
+        <input hidden type="text" name="status" value="{status}">
+        <label>Subscribed from:
+            <input type="date" name="from">
+        </label>
+        <label>to:
+            <input type="date" name="to">
+        </label>
+        <label>Format:
+            <select name="format">
+                <option value="csv">CSV</option>
+                <option value="ndjson">JSON (one subscriber per line)</option>
+            </select>
+        </label>
+        <button type="submit">Export</button>
+    </form>
+    <p><a href="/admin/subscribers/import">Import subscribers</a></p>
+    <p><a href="/admin/subscribers/personal-data">Export or erase someone's personal data</a></p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            search = htmlescape::encode_minimal(search.unwrap_or_default()),
+            status = status.unwrap_or_default(),
+        )))
+}
+
+#[tracing::instrument(skip(pool, after))]
+async fn get_subscribers(
+    pool: &PgPool,
+    status: Option<&str>,
+    search: Option<&str>,
+    after: Option<&Cursor>,
+) -> Result<Vec<SubscriberRow>, anyhow::Error> {
+    // strpos rather than LIKE, so that % and _ in the search are not wildcards
+    let subscribers = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT id, email, username, status, subscribed_at
+        FROM subscriptions
+        WHERE ($1::text IS NULL OR status = $1)
+            AND ($2::text IS NULL
+                OR strpos(lower(email), lower($2)) > 0
+                OR strpos(lower(username), lower($2)) > 0)
+            AND ($3::timestamptz IS NULL OR (subscribed_at, id) < ($3, $4))
+        ORDER BY subscribed_at DESC, id DESC
+        LIMIT $5
+    "#,
+        status,
+        search,
+        after.map(|cursor| cursor.subscribed_at),
+        after.map(|cursor| cursor.id),
+        SUBSCRIBERS_PER_PAGE + 1
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscribers")?;
+    Ok(subscribers)
+}
+
+struct SubscriberDetails {
+    email: String,
+    username: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    confirmed_at: Option<DateTime<Utc>>,
+    unsubscribed_at: Option<DateTime<Utc>>,
+    paused_until: Option<DateTime<Utc>>,
+    consent_source: Option<String>,
+    custom_fields: serde_json::Value,
+}
+
+struct Membership {
+    name: String,
+    status: String,
+}
+
+struct Delivery {
+    title: String,
+    status: String,
+    error: Option<String>,
+    recorded_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn subscriber_details(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let msg_html = flash_messages_html(&flash_messages);
+    let subscriber = match get_subscriber_details(&pool, *subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let deliveries = get_deliveries(&pool, &subscriber.email)
+        .await
+        .map_err(e500)?;
+    let memberships = get_memberships(&pool, *subscriber_id).await.map_err(e500)?;
+    let lists = if memberships.is_empty() {
+        "-".to_string()
+    } else {
+        memberships
+            .iter()
+            .map(|m| match m.status.as_str() {
+                "confirmed" => htmlescape::encode_minimal(&m.name),
+                _ => format!("{} (pending)", htmlescape::encode_minimal(&m.name)),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut rows_html = String::new();
+    for delivery in &deliveries {
+        writeln!(
+            rows_html,
+            r#"<tr>
+    <td>{title}</td>
+    <td>{status}</td>
+    <td>{error}</td>
+    <td>{recorded_at}</td>
+</tr>"#,
+            title = htmlescape::encode_minimal(&delivery.title),
+            status = delivery.status,
+            error = htmlescape::encode_minimal(delivery.error.as_deref().unwrap_or_default()),
+            recorded_at = delivery.recorded_at.format("%Y-%m-%d %H:%M UTC"),
+        )
+        .unwrap();
+    }
+    let deliveries_html = if deliveries.is_empty() {
+        "<p>No issues delivered yet.</p>".to_string()
+    } else {
+        format!(
+            r#"<table>
+<tr>
+    <th>Issue</th>
+    <th>Outcome</th>
+    <th>Error</th>
+    <th>At</th>
+</tr>
+{rows_html}</table>"#
+        )
+    };
+    let id = *subscriber_id;
+    let mut actions_html = String::new();
+    if subscriber.status == "pending_confirmation" {
+        writeln!(
+            actions_html,
+            r#"<form action="/admin/subscribers/{id}/resend" method="post">
+        <button type="submit">Resend the confirmation email</button>
+    </form>"#
+        )
+        .unwrap();
+    }
+    if subscriber.status != "unsubscribed" {
+        writeln!(
+            actions_html,
+            r#"<form action="/admin/subscribers/{id}/unsubscribe" method="post">
+        <button type="submit">Unsubscribe</button>
+    </form>"#
+        )
+        .unwrap();
+    }
+    writeln!(
+        actions_html,
+        r#"<form action="/admin/subscribers/{id}/delete" method="post">
+        <button type="submit">Delete</button>
+    </form>"#
+    )
+    .unwrap();
+    // Imported lists can come with extra columns
+    let mut custom_fields_html = String::new();
+    if let Some(custom_fields) = subscriber.custom_fields.as_object() {
+        for (name, value) in custom_fields {
+            writeln!(
+                custom_fields_html,
+                "<tr><th>{}</th><td>{}</td></tr>",
+                htmlescape::encode_minimal(name),
+                htmlescape::encode_minimal(value.as_str().unwrap_or_default()),
+            )
+            .unwrap();
+        }
+    }
+    let format_date = |date: Option<DateTime<Utc>>| match date {
+        Some(date) => date.format("%Y-%m-%d %H:%M UTC").to_string(),
+        None => "-".to_string(),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscriber</title>
+</head>
+<body>
+    {msg_html}
+    <h1>{email}</h1>
+    <table>
+        <tr><th>Name</th><td>{username}</td></tr>
+        <tr><th>Status</th><td>{status}</td></tr>
+        <tr><th>Lists</th><td>{lists}</td></tr>
+        <tr><th>Subscribed at</th><td>{subscribed_at}</td></tr>
+        <tr><th>Confirmed at</th><td>{confirmed_at}</td></tr>
+        <tr><th>Unsubscribed at</th><td>{unsubscribed_at}</td></tr>
+        <tr><th>Paused until</th><td>{paused_until}</td></tr>
+        <tr><th>Consent source</th><td>{consent_source}</td></tr>
+        {custom_fields_html}
+    </table>
+    {actions_html}
+    <form action="/admin/subscribers/personal-data/export" method="post">
+        <input hidden type="text" name="email" value="{email}">
+        <button type="submit">Export their personal data</button>
+    </form>
+    <p><a href="/admin/subscribers/{id}/personal-data">Erase their personal data</a></p>
+    <h2>Delivery history</h2>
+    {deliveries_html}
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>"#,
+            email = htmlescape::encode_minimal(&subscriber.email),
+            username = htmlescape::encode_minimal(&subscriber.username),
+            status = status_label(&subscriber.status),
+            subscribed_at = format_date(Some(subscriber.subscribed_at)),
+            confirmed_at = format_date(subscriber.confirmed_at),
+            unsubscribed_at = format_date(subscriber.unsubscribed_at),
+            paused_until = format_date(subscriber.paused_until.filter(|until| *until > Utc::now())),
+            consent_source =
+                htmlescape::encode_minimal(subscriber.consent_source.as_deref().unwrap_or("-")),
+        )))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_subscriber_details(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberDetails>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        SubscriberDetails,
+        r#"
+        SELECT email, username, status, subscribed_at, confirmed_at, unsubscribed_at,
+            paused_until, consent_source, custom_fields
+        FROM subscriptions
+        WHERE id = $1
+    "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber")?;
+    Ok(subscriber)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_memberships(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<Membership>, anyhow::Error> {
+    let memberships = sqlx::query_as!(
+        Membership,
+        r#"
+        SELECT l.name, m.status
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        WHERE m.subscriber_id = $1
+        ORDER BY l.name
+    "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the lists of the subscriber")?;
+    Ok(memberships)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_deliveries(
+    pool: &PgPool,
+    subscriber_email: &str,
+) -> Result<Vec<Delivery>, anyhow::Error> {
+    let deliveries = sqlx::query_as!(
+        Delivery,
+        r#"
+        SELECT i.title, d.status, d.error, d.recorded_at
+        FROM newsletter_issue_deliveries d
+        JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+        WHERE d.subscriber_email = $1
+        ORDER BY d.recorded_at DESC
+        LIMIT 100
+    "#,
+        subscriber_email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the delivery history of the subscriber")?;
+    Ok(deliveries)
+}
@@ -0,0 +1,423 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use uuid::Uuid;
+
+use super::personal_data::email_hash;
+use crate::domain::SubscriberImport;
+use crate::mailing_lists::{get_mailing_lists, mailing_list_select, selected_mailing_list};
+use crate::routes::generate_subscription_token;
+use crate::startup::SuppressionKey;
+use crate::utils::{e400, e500, flash_messages_html, redirect};
+
+/// Lists are pasted in the import form, which is way bigger than the other ones.
+pub const SUBSCRIBER_IMPORT_MAX_SIZE: usize = 10 * 1024 * 1024;
+
+#[derive(serde::Deserialize)]
+pub struct ImportFormData {
+    csv: String,
+    // `confirmed` or `pending_confirmation`
+    status: String,
+    #[serde(default)]
+    consent_source: String,
+    // Missing to add them to the default list
+    list_id: Option<String>,
+    // `check` for a dry run, `import` to save the subscribers
+    mode: String,
+}
+
+pub async fn import_subscribers_form(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let msg_html = flash_messages_html(&flash_messages);
+    let lists = get_mailing_lists(&pool).await.map_err(e500)?;
+    Ok(import_page(
+        &msg_html,
+        "",
+        "pending_confirmation",
+        "",
+        &mailing_list_select(&lists, None),
+    ))
+}
+
+#[tracing::instrument(
+    name = "Import subscribers",
+    skip(form, pool, suppression_key),
+    fields(status = %form.status, mode = %form.mode)
+)]
+pub async fn import_subscribers(
+    form: web::Form<ImportFormData>,
+    pool: web::Data<PgPool>,
+    suppression_key: web::Data<SuppressionKey>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let dry_run = match form.mode.as_str() {
+        "check" => true,
+        "import" => false,
+        _ => return Err(e400("Unknown import mode.")),
+    };
+    if form.status != "confirmed" && form.status != "pending_confirmation" {
+        return Err(e400(
+            "Subscribers can only be imported as confirmed or pending.",
+        ));
+    }
+    let consent_source = Some(form.consent_source.trim()).filter(|s| !s.is_empty());
+    let list = selected_mailing_list(&pool, form.list_id.as_deref())
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e400("This list does not exist."))?;
+    let mut import = SubscriberImport::parse(&form.csv);
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let existing = reject_known_subscribers(
+        &mut transaction,
+        &mut import,
+        list.list_id,
+        &suppression_key.0,
+    )
+    .await
+    .map_err(e500)?;
+
+    let mut report_html = String::new();
+    if form.status == "confirmed" && consent_source.is_none() {
+        report_html.push_str(
+            "<p>Tell where the consent of confirmed subscribers comes from, \
+            they will not be asked for it.</p>\n",
+        );
+    }
+    if import.subscribers.is_empty() && import.errors.is_empty() {
+        report_html.push_str("<p>The list is empty.</p>\n");
+    }
+    if !import.errors.is_empty() {
+        report_html.push_str("<ul>\n");
+        for error in &import.errors {
+            writeln!(
+                report_html,
+                "<li>Line {}: {}</li>",
+                error.line,
+                htmlescape::encode_minimal(&error.message)
+            )
+            .unwrap();
+        }
+        report_html.push_str("</ul>\n");
+    }
+
+    // Nothing is imported until the whole list is valid, so that
+    // it can be fixed and imported again without duplicates
+    if dry_run || !report_html.is_empty() {
+        let summary = if report_html.is_empty() {
+            format!(
+                "<p>The list is valid: {} subscribers are ready to be imported.</p>",
+                import.subscribers.len()
+            )
+        } else if dry_run {
+            format!(
+                "<p>{} subscribers are ready to be imported, fix the following first:</p>\n{}",
+                import.subscribers.len(),
+                report_html
+            )
+        } else {
+            format!(
+                "<p>Nothing was imported, fix the following first:</p>\n{}",
+                report_html
+            )
+        };
+        let lists = get_mailing_lists(&pool).await.map_err(e500)?;
+        return Ok(import_page(
+            &summary,
+            &form.csv,
+            &form.status,
+            &form.consent_source,
+            &mailing_list_select(&lists, Some(list.list_id)),
+        ));
+    }
+
+    let n_imported = import.subscribers.len();
+    insert_subscribers(
+        &mut transaction,
+        &import,
+        &form.status,
+        consent_source,
+        list.list_id,
+        &existing,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the imported subscribers")
+        .map_err(e500)?;
+
+    let message = if form.status == "pending_confirmation" {
+        format!(
+            "{} subscribers have been imported, their confirmation emails are on their way.",
+            n_imported
+        )
+    } else {
+        format!("{} subscribers have been imported.", n_imported)
+    };
+    FlashMessage::info(message).send();
+    Ok(redirect("/admin/subscribers"))
+}
+
+fn import_page(
+    msg_html: &str,
+    csv: &str,
+    status: &str,
+    consent_source: &str,
+    list_html: &str,
+) -> HttpResponse {
+    let checked = |value: &str| if status == value { " checked" } else { "" };
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Import subscribers</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/subscribers/import" method="post">
+        <label>Subscribers, as CSV with an email and a username column.
+            The other columns are kept as custom fields:<br>
+            <textarea
+                placeholder="email,username&#10;ursula@example.com,Ursula"
+                name="csv"
+                rows="20"
+                cols="80"
+            >{csv}</textarea>
+        </label>
+        <br>
+        {list_html}
+        <br>
+        <label>
+            <input type="radio" name="status" value="pending_confirmation"{pending_checked}>
+            Send them a confirmation email
+        </label>
+        <br>
+        <label>
+            <input type="radio" name="status" value="confirmed"{confirmed_checked}>
+            Already confirmed
+        </label>
+        <br>
+        <label>Where their consent comes from (required for confirmed subscribers):<br>
+            <input
+                type="text"
+                placeholder="e.g. Signup form of our previous provider"
+                name="consent_source"
+                value="{consent_source}"
+            >
+        </label>
+        <br>
+        <button type="submit" name="mode" value="check">Check</button>
+        <button type="submit" name="mode" value="import">Import</button>
+    </form>
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>"#,
+            csv = htmlescape::encode_minimal(csv),
+            pending_checked = checked("pending_confirmation"),
+            confirmed_checked = checked("confirmed"),
+            consent_source = htmlescape::encode_minimal(consent_source),
+        ))
+}
+
+/// Subscribers already on the list, whatever their status, are left as they are.
+/// So are the people who unsubscribed or asked to be erased.
+/// Returns the other known subscribers, by lowercase address: they are added to the list.
+#[tracing::instrument(skip_all)]
+async fn reject_known_subscribers(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    import: &mut SubscriberImport,
+    list_id: Uuid,
+    suppression_key: &Secret<String>,
+) -> Result<HashMap<String, Uuid>, anyhow::Error> {
+    let emails: Vec<String> = import
+        .subscribers
+        .iter()
+        .map(|s| s.email.as_ref().to_lowercase())
+        .collect();
+    let known: HashMap<String, KnownSubscriber> = sqlx::query_as!(
+        KnownSubscriber,
+        r#"
+        SELECT lower(s.email) AS "email!", s.id, s.status,
+            EXISTS (
+                SELECT 1 FROM list_memberships m
+                WHERE m.subscriber_id = s.id AND m.list_id = $2
+            ) AS "on_list!"
+        FROM subscriptions s
+        WHERE lower(s.email) = ANY($1)
+    "#,
+        &emails,
+        list_id
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .context("Failed to look up the known subscribers")?
+    .into_iter()
+    .map(|s| (s.email.clone(), s))
+    .collect();
+    let hashes: Vec<String> = emails
+        .iter()
+        .map(|email| email_hash(suppression_key, email))
+        .collect();
+    let suppressed: HashSet<String> = sqlx::query!(
+        "SELECT email_hash FROM suppressed_emails WHERE email_hash = ANY($1)",
+        &hashes
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .context("Failed to look up the suppression list")?
+    .into_iter()
+    .map(|r| r.email_hash)
+    .collect();
+    let mut existing = HashMap::new();
+    let mut rejected: Vec<(u64, String)> = Vec::new();
+    for s in &import.subscribers {
+        let email = s.email.as_ref().to_lowercase();
+        match known.get(&email) {
+            Some(known) if known.on_list => {
+                rejected.push((s.line, format!("{} is already subscribed.", s.email)))
+            }
+            Some(known) if known.status == "unsubscribed" => {
+                rejected.push((s.line, format!("{} has unsubscribed.", s.email)))
+            }
+            Some(known) => {
+                existing.insert(email, known.id);
+            }
+            None if suppressed.contains(&email_hash(suppression_key, &email)) => rejected.push((
+                s.line,
+                format!("{} asked for their data to be erased.", s.email),
+            )),
+            None => {}
+        }
+    }
+    for (line, message) in rejected {
+        import.reject(line, message);
+    }
+    Ok(existing)
+}
+
+struct KnownSubscriber {
+    email: String,
+    id: Uuid,
+    status: String,
+    on_list: bool,
+}
+
+#[tracing::instrument(skip(transaction, import))]
+async fn insert_subscribers(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    import: &SubscriberImport,
+    status: &str,
+    consent_source: Option<&str>,
+    list_id: Uuid,
+    existing: &HashMap<String, Uuid>,
+) -> Result<(), anyhow::Error> {
+    let n_subscribers = import.subscribers.len();
+    let mut ids = Vec::with_capacity(n_subscribers);
+    let mut emails = Vec::with_capacity(n_subscribers);
+    let mut usernames = Vec::with_capacity(n_subscribers);
+    let mut unsubscribe_tokens = Vec::with_capacity(n_subscribers);
+    let mut custom_fields = Vec::with_capacity(n_subscribers);
+    // Known subscribers keep their details, they join the list
+    let mut member_ids = Vec::with_capacity(n_subscribers);
+    for subscriber in &import.subscribers {
+        if let Some(id) = existing.get(&subscriber.email.as_ref().to_lowercase()) {
+            member_ids.push(*id);
+            continue;
+        }
+        let id = Uuid::new_v4();
+        ids.push(id);
+        member_ids.push(id);
+        emails.push(subscriber.email.as_ref().to_string());
+        usernames.push(subscriber.username.as_ref().to_string());
+        unsubscribe_tokens.push(generate_subscription_token());
+        custom_fields.push(serde_json::to_value(&subscriber.custom_fields)?);
+    }
+    // One statement for the whole list rather than one per subscriber
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (
+            id, email, username, subscribed_at, status, confirmed_at, unsubscribe_token,
+            consent_source, custom_fields
+        )
+        SELECT id, email, username, now(), $6::text,
+            CASE WHEN $6::text = 'confirmed' THEN now() END,
+            unsubscribe_token, $7, custom_fields
+        FROM UNNEST($1::uuid[], $2::text[], $3::text[], $4::text[], $5::jsonb[])
+            AS imported(id, email, username, unsubscribe_token, custom_fields)
+    "#,
+        &ids,
+        &emails,
+        &usernames,
+        &unsubscribe_tokens,
+        &custom_fields,
+        status,
+        consent_source
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to insert the imported subscribers")?;
+    // Their membership has the status chosen for the import
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status, confirmed_at)
+        SELECT id, $2, $3::text, CASE WHEN $3::text = 'confirmed' THEN now() END
+        FROM UNNEST($1::uuid[]) AS imported(id)
+    "#,
+        &member_ids,
+        list_id,
+        status
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to add the imported subscribers to the list")?;
+    // Issues only go to confirmed subscriptions: known subscribers who never confirmed
+    // theirs would not get anything from the list they are imported into as confirmed
+    if status == "confirmed" {
+        let existing_ids: Vec<Uuid> = existing.values().copied().collect();
+        sqlx::query!(
+            r#"
+            UPDATE subscriptions
+            SET status = 'confirmed', confirmed_at = now(),
+                consent_source = COALESCE(consent_source, $2)
+            WHERE id = ANY($1) AND status = 'pending_confirmation'
+        "#,
+            &existing_ids,
+            consent_source
+        )
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to confirm the known subscribers")?;
+    }
+    // The confirmation emails are sent by the background worker,
+    // the tokens are created at the same time
+    if status == "pending_confirmation" {
+        sqlx::query!(
+            r#"
+            INSERT INTO confirmation_email_queue (subscriber_id, list_id)
+            SELECT id, $2 FROM UNNEST($1::uuid[]) AS imported(id)
+            ON CONFLICT (subscriber_id, list_id) DO NOTHING
+        "#,
+            &member_ids,
+            list_id
+        )
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to enqueue the confirmation emails")?;
+    }
+    Ok(())
+}
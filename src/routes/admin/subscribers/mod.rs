@@ -0,0 +1,38 @@
+mod export;
+mod get;
+mod import;
+mod personal_data;
+mod post;
+
+pub use export::export_subscribers;
+pub use get::{subscriber_details, subscribers};
+pub use import::{import_subscribers, import_subscribers_form, SUBSCRIBER_IMPORT_MAX_SIZE};
+pub use personal_data::{
+    erase_personal_data, export_personal_data, personal_data_form, subscriber_personal_data_form,
+};
+pub use post::{delete_subscriber, resend_confirmation_email, unsubscribe_subscriber};
+
+use crate::utils::e400;
+
+const STATUSES: [(&str, &str); 3] = [
+    ("pending_confirmation", "Pending confirmation"),
+    ("confirmed", "Confirmed"),
+    ("unsubscribed", "Unsubscribed"),
+];
+
+fn status_label(status: &str) -> &'static str {
+    STATUSES
+        .iter()
+        .find(|(s, _)| *s == status)
+        .map(|(_, label)| *label)
+        .unwrap_or("Unknown")
+}
+
+/// The status to filter the subscribers on, if any.
+fn status_filter(status: Option<&str>) -> Result<Option<&str>, actix_web::Error> {
+    match status {
+        None | Some("") => Ok(None),
+        Some(status) if STATUSES.iter().any(|(s, _)| *s == status) => Ok(Some(status)),
+        Some(_) => Err(e400("Unknown subscriber status.")),
+    }
+}
@@ -0,0 +1,465 @@
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha3::Sha3_256;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::startup::SuppressionKey;
+use crate::utils::{e400, e500, flash_messages_html, redirect};
+
+/// The suppression list entry of an address: its case and surrounding
+/// whitespace are ignored, like when looking it up among the subscribers.
+/// The hash is keyed, so that a leaked list cannot be matched against known addresses.
+pub(super) fn email_hash(key: &Secret<String>, email: &str) -> String {
+    let mut mac = Hmac::<Sha3_256>::new_from_slice(key.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(email.trim().to_lowercase().as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub async fn personal_data_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    Ok(personal_data_page(flash_messages, ""))
+}
+
+/// The form prefilled with the address of a subscriber.
+/// The address is looked up, it never shows up in the URL (and in the request logs).
+#[tracing::instrument(skip_all)]
+pub async fn subscriber_personal_data_form(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE id = $1",
+        *subscriber_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscriber")
+    .map_err(e500)?;
+    match subscriber {
+        Some(subscriber) => Ok(personal_data_page(flash_messages, &subscriber.email)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+fn personal_data_page(flash_messages: IncomingFlashMessages, email: &str) -> HttpResponse {
+    let msg_html = flash_messages_html(&flash_messages);
+    let email = htmlescape::encode_minimal(email);
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Personal data</title>
+</head>
+<body>
+    {msg_html}
+    <h2>Export</h2>
+    <form action="/admin/subscribers/personal-data/export" method="post">
+        <label>Email address:
+            <input type="text" name="email" value="{email}">
+        </label>
+        <button type="submit">Download everything we store</button>
+    </form>
+    <h2>Erase</h2>
+    <p>The subscription, its tokens and its delivery history are deleted for good.
+    Only a hash of the address is kept, so that it cannot be imported again.</p>
+    <form action="/admin/subscribers/personal-data/erase" method="post">
+        <label>Email address:
+            <input type="text" name="email" value="{email}">
+        </label>
+        <br>
+        <label>Type the address again to confirm:
+            <input type="text" name="confirm_email">
+        </label>
+        <br>
+        <button type="submit">Erase</button>
+    </form>
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>"#,
+        ))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ExportFormData {
+    email: String,
+}
+
+/// Everything stored about an address, as a JSON document.
+/// The address comes in the body of the request, to stay out of the request logs.
+#[tracing::instrument(name = "Export the personal data of a subscriber", skip_all)]
+pub async fn export_personal_data(
+    form: web::Form<ExportFormData>,
+    pool: web::Data<PgPool>,
+    suppression_key: web::Data<SuppressionKey>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = form.email.trim();
+    if email.is_empty() {
+        return Err(e400("The email address is missing."));
+    }
+    let document = collect_personal_data(&pool, &suppression_key.0, email)
+        .await
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "personal-data-{}.json",
+                Utc::now().format("%Y-%m-%d")
+            ))],
+        })
+        .body(serde_json::to_string_pretty(&document).map_err(e500)?))
+}
+
+async fn collect_personal_data(
+    pool: &PgPool,
+    suppression_key: &Secret<String>,
+    email: &str,
+) -> Result<serde_json::Value, anyhow::Error> {
+    let subscription = sqlx::query!(
+        r#"
+        SELECT id, email, username, status, subscribed_at, confirmed_at, unsubscribed_at,
+            unsubscribe_token, consent_source, custom_fields, paused_until
+        FROM subscriptions
+        WHERE lower(email) = lower($1)
+    "#,
+        email
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscription")?;
+    let (subscription, tokens, email_changes, memberships) = match subscription {
+        Some(s) => {
+            let tokens = sqlx::query!(
+                r#"
+                SELECT subscription_token, created_at, expires_at
+                FROM subscription_tokens
+                WHERE subscription_id = $1
+                ORDER BY created_at
+            "#,
+                s.id
+            )
+            .fetch_all(pool)
+            .await
+            .context("Failed to retrieve the subscription tokens")?
+            .into_iter()
+            .map(|t| {
+                serde_json::json!({
+                    "subscription_token": t.subscription_token,
+                    "created_at": t.created_at.to_rfc3339(),
+                    "expires_at": t.expires_at.to_rfc3339(),
+                })
+            })
+            .collect();
+            let email_changes = sqlx::query!(
+                r#"
+                SELECT new_email, created_at, expires_at
+                FROM email_change_requests
+                WHERE subscriber_id = $1
+                ORDER BY created_at
+            "#,
+                s.id
+            )
+            .fetch_all(pool)
+            .await
+            .context("Failed to retrieve the email change requests")?
+            .into_iter()
+            .map(|r| {
+                serde_json::json!({
+                    "new_email": r.new_email,
+                    "created_at": r.created_at.to_rfc3339(),
+                    "expires_at": r.expires_at.to_rfc3339(),
+                })
+            })
+            .collect();
+            let memberships = sqlx::query!(
+                r#"
+                SELECT l.name, m.status, m.created_at, m.confirmed_at
+                FROM list_memberships m
+                JOIN lists l ON l.list_id = m.list_id
+                WHERE m.subscriber_id = $1
+                ORDER BY l.name
+            "#,
+                s.id
+            )
+            .fetch_all(pool)
+            .await
+            .context("Failed to retrieve the list memberships")?
+            .into_iter()
+            .map(|m| {
+                serde_json::json!({
+                    "list": m.name,
+                    "status": m.status,
+                    "created_at": m.created_at.to_rfc3339(),
+                    "confirmed_at": m.confirmed_at.map(|date| date.to_rfc3339()),
+                })
+            })
+            .collect();
+            let subscription = serde_json::json!({
+                "id": s.id,
+                "email": s.email,
+                "username": s.username,
+                "status": s.status,
+                "subscribed_at": s.subscribed_at.to_rfc3339(),
+                "confirmed_at": s.confirmed_at.map(|date| date.to_rfc3339()),
+                "unsubscribed_at": s.unsubscribed_at.map(|date| date.to_rfc3339()),
+                "unsubscribe_token": s.unsubscribe_token,
+                "consent_source": s.consent_source,
+                "custom_fields": s.custom_fields,
+                "paused_until": s.paused_until.map(|date| date.to_rfc3339()),
+            });
+            (subscription, tokens, email_changes, memberships)
+        }
+        None => (serde_json::Value::Null, Vec::new(), Vec::new(), Vec::new()),
+    };
+    let queued_deliveries: Vec<_> = sqlx::query!(
+        r#"
+        SELECT q.newsletter_issue_id, i.title, q.enqueued_at, q.n_retries, q.execute_after
+        FROM issue_delivery_table q
+        JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+        WHERE lower(q.subscriber_email) = lower($1)
+        ORDER BY q.enqueued_at
+    "#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the queued deliveries")?
+    .into_iter()
+    .map(|d| {
+        serde_json::json!({
+            "newsletter_issue_id": d.newsletter_issue_id,
+            "title": d.title,
+            "enqueued_at": d.enqueued_at.to_rfc3339(),
+            "n_retries": d.n_retries,
+            "execute_after": d.execute_after.to_rfc3339(),
+        })
+    })
+    .collect();
+    let failed_deliveries: Vec<_> = sqlx::query!(
+        r#"
+        SELECT f.newsletter_issue_id, i.title, f.n_attempts, f.last_error, f.enqueued_at,
+            f.failed_at
+        FROM issue_delivery_failures f
+        JOIN newsletter_issues i ON i.newsletter_issue_id = f.newsletter_issue_id
+        WHERE lower(f.subscriber_email) = lower($1)
+        ORDER BY f.failed_at
+    "#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the failed deliveries")?
+    .into_iter()
+    .map(|f| {
+        serde_json::json!({
+            "newsletter_issue_id": f.newsletter_issue_id,
+            "title": f.title,
+            "n_attempts": f.n_attempts,
+            "last_error": f.last_error,
+            "enqueued_at": f.enqueued_at.to_rfc3339(),
+            "failed_at": f.failed_at.to_rfc3339(),
+        })
+    })
+    .collect();
+    let delivery_history: Vec<_> = sqlx::query!(
+        r#"
+        SELECT d.newsletter_issue_id, i.title, d.status, d.provider_message_id, d.error,
+            d.recorded_at
+        FROM newsletter_issue_deliveries d
+        JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+        WHERE lower(d.subscriber_email) = lower($1)
+        ORDER BY d.recorded_at
+    "#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the delivery history")?
+    .into_iter()
+    .map(|d| {
+        serde_json::json!({
+            "newsletter_issue_id": d.newsletter_issue_id,
+            "title": d.title,
+            "status": d.status,
+            "provider_message_id": d.provider_message_id,
+            "error": d.error,
+            "recorded_at": d.recorded_at.to_rfc3339(),
+        })
+    })
+    .collect();
+    let suppressed_at = sqlx::query!(
+        "SELECT suppressed_at FROM suppressed_emails WHERE email_hash = $1",
+        email_hash(suppression_key, email)
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up the suppression list")?
+    .map(|r| r.suppressed_at.to_rfc3339());
+
+    Ok(serde_json::json!({
+        "email": email,
+        "exported_at": Utc::now().to_rfc3339(),
+        "subscription": subscription,
+        "subscription_tokens": tokens,
+        "list_memberships": memberships,
+        "email_change_requests": email_changes,
+        "queued_deliveries": queued_deliveries,
+        "failed_deliveries": failed_deliveries,
+        "delivery_history": delivery_history,
+        "suppressed_at": suppressed_at,
+    }))
+}
+
+#[derive(serde::Deserialize)]
+pub struct EraseFormData {
+    email: String,
+    confirm_email: String,
+}
+
+/// Delete everything stored about an address, for good.
+/// The address itself is not logged, it would outlive the erasure in the logs.
+#[tracing::instrument(name = "Erase the personal data of a subscriber", skip_all)]
+pub async fn erase_personal_data(
+    form: web::Form<EraseFormData>,
+    pool: web::Data<PgPool>,
+    suppression_key: web::Data<SuppressionKey>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = form.email.trim();
+    let form_page = "/admin/subscribers/personal-data";
+    if email.is_empty() {
+        FlashMessage::error("The email address is missing.").send();
+        return Ok(redirect(form_page));
+    }
+    if !email.eq_ignore_ascii_case(form.confirm_email.trim()) {
+        FlashMessage::error("The confirmation does not match the email address.").send();
+        return Ok(redirect(form_page));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    erase(&mut transaction, &suppression_key.0, email)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to erase the personal data")
+        .map_err(e500)?;
+
+    FlashMessage::info("Everything stored about this address has been erased.").send();
+    Ok(redirect("/admin/subscribers/personal-data"))
+}
+
+async fn erase(
+    transaction: &mut Transaction<'_, Postgres>,
+    suppression_key: &Secret<String>,
+    email: &str,
+) -> Result<(), anyhow::Error> {
+    // The confirmation email queue and the email change requests follow the subscription.
+    // A change towards the erased address, asked by another subscriber, goes away too
+    sqlx::query!(
+        "DELETE FROM email_change_requests WHERE lower(new_email) = lower($1)",
+        email
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to delete the email change requests")?;
+    sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens
+        WHERE subscription_id IN (SELECT id FROM subscriptions WHERE lower(email) = lower($1))
+    "#,
+        email
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to delete the subscription tokens")?;
+    sqlx::query!(
+        "DELETE FROM issue_delivery_table WHERE lower(subscriber_email) = lower($1)",
+        email
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to delete the queued deliveries")?;
+    sqlx::query!(
+        "DELETE FROM issue_delivery_failures WHERE lower(subscriber_email) = lower($1)",
+        email
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to delete the failed deliveries")?;
+    sqlx::query!(
+        "DELETE FROM newsletter_issue_deliveries WHERE lower(subscriber_email) = lower($1)",
+        email
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to delete the delivery history")?;
+    sqlx::query!(
+        "DELETE FROM subscriptions WHERE lower(email) = lower($1)",
+        email
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to delete the subscription")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO suppressed_emails (email_hash)
+        VALUES ($1)
+        ON CONFLICT DO NOTHING
+    "#,
+        email_hash(suppression_key, email)
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to add the address to the suppression list")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::email_hash;
+    use secrecy::Secret;
+
+    fn key(key: &str) -> Secret<String> {
+        Secret::new(key.to_string())
+    }
+
+    #[test]
+    fn the_hash_ignores_case_and_surrounding_whitespace() {
+        assert_eq!(
+            email_hash(&key("secret"), "ursula@example.com"),
+            email_hash(&key("secret"), " Ursula@Example.COM\n")
+        );
+    }
+
+    #[test]
+    fn the_hash_depends_on_the_key() {
+        assert_ne!(
+            email_hash(&key("secret"), "ursula@example.com"),
+            email_hash(&key("another-secret"), "ursula@example.com")
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailSender;
+use crate::email_layout::get_default_email_layout;
+use crate::routes::{
+    delete_tokens, generate_subscription_token, send_confirmation_email, store_token,
+};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e500, redirect};
+
+#[tracing::instrument(
+    name = "Resend a confirmation email",
+    skip(pool, email_client, base_url)
+)]
+pub async fn resend_confirmation_email(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailSender>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let details_page = format!("/admin/subscribers/{}", subscriber_id);
+    let subscriber = sqlx::query!(
+        "SELECT email, status FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscriber")
+    .map_err(e500)?;
+    let subscriber = match subscriber {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    if subscriber.status != "pending_confirmation" {
+        FlashMessage::error("This subscriber is not waiting for a confirmation.").send();
+        return Ok(redirect(&details_page));
+    }
+    let recipient = SubscriberEmail::parse(subscriber.email).map_err(e500)?;
+    let pending_lists = sqlx::query!(
+        r#"
+        SELECT l.list_id, l.name
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        WHERE m.subscriber_id = $1 AND m.status = 'pending_confirmation'
+        ORDER BY l.name
+    "#,
+        subscriber_id
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the lists waiting for a confirmation")
+    .map_err(e500)?;
+    if pending_lists.is_empty() {
+        FlashMessage::error("This subscriber is not waiting for the confirmation of any list.")
+            .send();
+        return Ok(redirect(&details_page));
+    }
+
+    // The previous links stop working, only the latest emails can be used.
+    // Each list is confirmed on its own
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    delete_tokens(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to delete the previous subscription tokens")
+        .map_err(e500)?;
+    let mut subscription_tokens = Vec::with_capacity(pending_lists.len());
+    for list in &pending_lists {
+        let subscription_token = generate_subscription_token();
+        store_token(
+            &mut transaction,
+            subscriber_id,
+            list.list_id,
+            &subscription_token,
+        )
+        .await
+        .map_err(e500)?;
+        subscription_tokens.push(subscription_token);
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to store the new subscription tokens")
+        .map_err(e500)?;
+
+    let layout = get_default_email_layout(&pool).await.map_err(e500)?;
+    for (list, subscription_token) in pending_lists.iter().zip(&subscription_tokens) {
+        send_confirmation_email(
+            email_client.get_ref(),
+            layout.as_ref(),
+            &recipient,
+            &list.name,
+            &base_url.0,
+            subscription_token,
+        )
+        .await
+        .map_err(e500)?;
+    }
+    FlashMessage::info("A new confirmation email has been sent.").send();
+    Ok(redirect(&details_page))
+}
+
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(pool))]
+pub async fn unsubscribe_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    // Unsubscribing twice is a no-op, we keep the original unsubscribed_at
+    let result = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed', unsubscribed_at = now()
+        WHERE id = $1 AND status <> 'unsubscribed'
+    "#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to mark the subscriber as unsubscribed")
+    .map_err(e500)?;
+    // A pending confirmation link would subscribe them again
+    delete_tokens(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to delete the subscription tokens")
+        .map_err(e500)?;
+    sqlx::query!(
+        "DELETE FROM list_memberships WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to remove the subscriber from their lists")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to unsubscribe the subscriber")
+        .map_err(e500)?;
+
+    if result.rows_affected() > 0 {
+        FlashMessage::info("The subscriber has been unsubscribed.").send();
+    }
+    Ok(redirect(&format!("/admin/subscribers/{}", subscriber_id)))
+}
+
+#[tracing::instrument(name = "Delete a subscriber", skip(pool))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let subscriber = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE",
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve the subscriber")
+    .map_err(e500)?;
+    let subscriber = match subscriber {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    delete_tokens(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to delete the subscription tokens")
+        .map_err(e500)?;
+    // Issues that are still on their way to the subscriber are not sent anymore
+    sqlx::query!(
+        "DELETE FROM issue_delivery_table WHERE subscriber_email = $1",
+        subscriber.email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to dequeue the pending deliveries")
+    .map_err(e500)?;
+    sqlx::query!(
+        "DELETE FROM issue_delivery_failures WHERE subscriber_email = $1",
+        subscriber.email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the failed deliveries")
+    .map_err(e500)?;
+    sqlx::query!("DELETE FROM subscriptions WHERE id = $1", subscriber_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete the subscriber")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to delete the subscriber")
+        .map_err(e500)?;
+
+    FlashMessage::info("The subscriber has been deleted.").send();
+    Ok(redirect("/admin/subscribers"))
+}
@@ -0,0 +1,177 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use super::get_email_template_names;
+use crate::utils::{e500, flash_messages_html};
+
+struct EmailTemplate {
+    name: String,
+    header_html: String,
+    footer_html: String,
+    css: String,
+    company_address: String,
+    is_default: bool,
+}
+
+pub async fn email_templates(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let msg_html = flash_messages_html(&flash_messages);
+    let templates = get_email_template_names(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for template in &templates {
+        writeln!(
+            rows_html,
+            r#"<tr>
+    <td><a href="/admin/templates/{id}">{name}</a>{default}</td>
+    <td>
+        <form action="/admin/templates/{id}/delete" method="post">
+            <button type="submit">Delete</button>
+        </form>
+    </td>
+</tr>"#,
+            id = template.email_template_id,
+            name = htmlescape::encode_minimal(&template.name),
+            default = if template.is_default {
+                " (default)"
+            } else {
+                ""
+            },
+        )
+        .unwrap();
+    }
+    let content_html = if templates.is_empty() {
+        "<p>No layouts.</p>".to_string()
+    } else {
+        format!("<table>\n{rows_html}</table>")
+    };
+    let form_html = template_form_html("/admin/templates", None);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Email layouts</title>
+</head>
+<body>
+    {msg_html}
+    {content_html}
+    <h2>New layout</h2>
+    {form_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+pub async fn edit_email_template(
+    email_template_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let msg_html = flash_messages_html(&flash_messages);
+    let template = match get_email_template(&pool, *email_template_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(template) => template,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let form_html = template_form_html(
+        &format!("/admin/templates/{}", email_template_id),
+        Some(&template),
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Edit layout</title>
+</head>
+<body>
+    {msg_html}
+    {form_html}
+    <p><a href="/admin/templates">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+/// The form to create a layout, or to edit `template`.
+fn template_form_html(action: &str, template: Option<&EmailTemplate>) -> String {
+    let value = |f: fn(&EmailTemplate) -> &str| {
+        htmlescape::encode_minimal(template.map(f).unwrap_or_default())
+    };
+    format!(
+        r#"<form action="{action}" method="post">
+        <label>Name:<br>
+            <input type="text" name="name" value="{name}">
+        </label>
+        <br>
+        <label>Header (HTML):<br>
+            <textarea name="header_html" rows="5" cols="50">{header_html}</textarea>
+        </label>
+        <br>
+        <label>Footer (HTML):<br>
+            <textarea name="footer_html" rows="5" cols="50">{footer_html}</textarea>
+        </label>
+        <br>
+        <label>CSS:<br>
+            <textarea name="css" rows="10" cols="50">{css}</textarea>
+        </label>
+        <br>
+        <label>Company postal address (required by anti-spam laws):<br>
+            <textarea name="company_address" rows="3" cols="50">{company_address}</textarea>
+        </label>
+        <br>
+        <label>
+            <input type="checkbox" name="is_default"{checked}>
+            Use for the confirmation emails and by default for new issues
+        </label>
+        <br>
+        <button type="submit">Save</button>
+    </form>"#,
+        name = value(|t| &t.name),
+        header_html = value(|t| &t.header_html),
+        footer_html = value(|t| &t.footer_html),
+        css = value(|t| &t.css),
+        company_address = value(|t| &t.company_address),
+        checked = if template.is_some_and(|t| t.is_default) {
+            " checked"
+        } else {
+            ""
+        },
+    )
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_email_template(
+    pool: &PgPool,
+    email_template_id: Uuid,
+) -> Result<Option<EmailTemplate>, anyhow::Error> {
+    let template = sqlx::query_as!(
+        EmailTemplate,
+        r#"
+        SELECT name, header_html, footer_html, css, company_address, is_default
+        FROM email_templates
+        WHERE email_template_id = $1
+    "#,
+        email_template_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the email layout")?;
+    Ok(template)
+}
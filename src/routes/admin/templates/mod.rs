@@ -0,0 +1,75 @@
+mod get;
+mod post;
+
+pub use get::{edit_email_template, email_templates};
+pub use post::{create_email_template, delete_email_template, update_email_template};
+
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::email_layout::get_default_email_layout_id;
+use crate::utils::{e400, e500};
+
+struct EmailTemplateName {
+    email_template_id: Uuid,
+    name: String,
+    is_default: bool,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_email_template_names(pool: &PgPool) -> Result<Vec<EmailTemplateName>, anyhow::Error> {
+    let names = sqlx::query_as!(
+        EmailTemplateName,
+        r#"
+        SELECT email_template_id, name, is_default
+        FROM email_templates
+        ORDER BY name
+    "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the email layouts")?;
+    Ok(names)
+}
+
+/// The layout picked in an issue form: the default one when the field is missing,
+/// no layout at all when it is empty.
+pub(crate) async fn selected_email_template(
+    pool: &PgPool,
+    choice: Option<&str>,
+) -> Result<Option<Uuid>, actix_web::Error> {
+    match choice.map(str::trim) {
+        None => get_default_email_layout_id(pool).await.map_err(e500),
+        Some("") => Ok(None),
+        Some(choice) => Uuid::parse_str(choice).map(Some).map_err(e400),
+    }
+}
+
+/// A `<select>` to pick the layout of an issue.
+pub(crate) async fn email_template_select(
+    pool: &PgPool,
+    selected: Option<Uuid>,
+) -> Result<String, anyhow::Error> {
+    let mut options_html = String::from(r#"<option value="">No layout</option>"#);
+    for template in get_email_template_names(pool).await? {
+        writeln!(
+            options_html,
+            r#"<option value="{id}"{selected}>{name}</option>"#,
+            id = template.email_template_id,
+            selected = if Some(template.email_template_id) == selected {
+                " selected"
+            } else {
+                ""
+            },
+            name = htmlescape::encode_minimal(&template.name),
+        )
+        .unwrap();
+    }
+    Ok(format!(
+        r#"<label>Layout:<br>
+            <select name="email_template_id">{options_html}</select>
+        </label>"#
+    ))
+}
@@ -0,0 +1,188 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::utils::{e500, redirect};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    header_html: String,
+    footer_html: String,
+    css: String,
+    company_address: String,
+    // Checkboxes are only sent when ticked
+    is_default: Option<String>,
+}
+
+impl FormData {
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.name.trim().is_empty() {
+            return Err("The layout needs a name.");
+        }
+        if self.company_address.trim().is_empty() {
+            return Err("The company postal address is required.");
+        }
+        Ok(())
+    }
+}
+
+enum SaveOutcome {
+    Saved,
+    NameTaken,
+    NotFound,
+}
+
+#[tracing::instrument(name = "Create an email layout", skip(form, pool), fields(name = %form.name))]
+pub async fn create_email_template(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Err(e) = form.validate() {
+        FlashMessage::error(e).send();
+        return Ok(redirect("/admin/templates"));
+    }
+    let email_template_id = Uuid::new_v4();
+    match save(&pool, email_template_id, &form, true)
+        .await
+        .map_err(e500)?
+    {
+        SaveOutcome::Saved => {
+            FlashMessage::info("The layout has been saved.").send();
+            Ok(redirect(&format!("/admin/templates/{}", email_template_id)))
+        }
+        _ => {
+            FlashMessage::error(format!("A layout named {} already exists.", form.name)).send();
+            Ok(redirect("/admin/templates"))
+        }
+    }
+}
+
+#[tracing::instrument(name = "Update an email layout", skip(form, pool), fields(name = %form.name))]
+pub async fn update_email_template(
+    email_template_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email_template_id = email_template_id.into_inner();
+    let template_page = format!("/admin/templates/{}", email_template_id);
+    if let Err(e) = form.validate() {
+        FlashMessage::error(e).send();
+        return Ok(redirect(&template_page));
+    }
+    match save(&pool, email_template_id, &form, false)
+        .await
+        .map_err(e500)?
+    {
+        SaveOutcome::Saved => FlashMessage::info("The layout has been saved.").send(),
+        SaveOutcome::NameTaken => {
+            FlashMessage::error(format!("A layout named {} already exists.", form.name)).send()
+        }
+        SaveOutcome::NotFound => {
+            FlashMessage::error("This layout does not exist anymore.").send();
+            return Ok(redirect("/admin/templates"));
+        }
+    }
+    Ok(redirect(&template_page))
+}
+
+#[tracing::instrument(name = "Delete an email layout", skip(pool))]
+pub async fn delete_email_template(
+    email_template_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // The issues using it are sent without layout
+    sqlx::query!(
+        "DELETE FROM email_templates WHERE email_template_id = $1",
+        email_template_id.into_inner()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to delete the email layout")
+    .map_err(e500)?;
+    FlashMessage::info("The layout has been deleted.").send();
+    Ok(redirect("/admin/templates"))
+}
+
+#[tracing::instrument(skip(pool, form))]
+async fn save(
+    pool: &PgPool,
+    email_template_id: Uuid,
+    form: &FormData,
+    create: bool,
+) -> Result<SaveOutcome, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let is_default = form.is_default.is_some();
+    // There is a single default layout
+    if is_default {
+        sqlx::query!(
+            r#"
+            UPDATE email_templates
+            SET is_default = false
+            WHERE is_default AND email_template_id <> $1
+        "#,
+            email_template_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to unset the previous default email layout")?;
+    }
+    let outcome = if create {
+        sqlx::query!(
+            r#"
+            INSERT INTO email_templates (
+                email_template_id, name, header_html, footer_html, css, company_address, is_default
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+            email_template_id,
+            form.name.trim(),
+            form.header_html,
+            form.footer_html,
+            form.css,
+            form.company_address.trim(),
+            is_default,
+        )
+        .execute(&mut *transaction)
+        .await
+    } else {
+        sqlx::query!(
+            r#"
+            UPDATE email_templates
+            SET name = $2, header_html = $3, footer_html = $4, css = $5,
+                company_address = $6, is_default = $7
+            WHERE email_template_id = $1
+        "#,
+            email_template_id,
+            form.name.trim(),
+            form.header_html,
+            form.footer_html,
+            form.css,
+            form.company_address.trim(),
+            is_default,
+        )
+        .execute(&mut *transaction)
+        .await
+    };
+    match outcome {
+        Ok(result) if result.rows_affected() == 0 => return Ok(SaveOutcome::NotFound),
+        Ok(_) => {}
+        Err(e)
+            if e.as_database_error()
+                .is_some_and(|e| e.is_unique_violation()) =>
+        {
+            return Ok(SaveOutcome::NameTaken)
+        }
+        Err(e) => return Err(e).context("Failed to save the email layout"),
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to save an email layout")?;
+    Ok(SaveOutcome::Saved)
+}
@@ -0,0 +1,216 @@
+use actix_web::http::header::{self, HttpDate};
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, SubsecRound, Utc};
+use sha3::{Digest, Sha3_256};
+use sqlx::PgPool;
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use super::issues::{web_content, web_view_link};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::e500;
+
+const FEED_TITLE: &str = "Our newsletter";
+const FEED_DESCRIPTION: &str = "The issues of our newsletter.";
+const ISSUES_IN_FEED: i64 = 20;
+
+struct FeedIssue {
+    slug: String,
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Serve the RSS feed", skip_all)]
+pub async fn rss_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issues = get_feed_issues(&pool).await.map_err(e500)?;
+    let base_url = &base_url.0;
+    let mut items = String::new();
+    for issue in &issues {
+        let link = web_view_link(base_url, &issue.slug);
+        write!(
+            items,
+            r#"
+    <item>
+      <title>{title}</title>
+      <link>{link}</link>
+      <guid isPermaLink="true">{link}</guid>
+      <pubDate>{published_at}</pubDate>
+      <description>{content}</description>
+    </item>"#,
+            title = xml_escape(&issue.title),
+            published_at = issue.published_at.to_rfc2822(),
+            content = xml_escape(&web_content(&issue.html_content, base_url, &issue.slug)),
+        )
+        .unwrap();
+    }
+    let last_build_date = last_modified(&issues)
+        .map(|t| format!("\n    <lastBuildDate>{}</lastBuildDate>", t.to_rfc2822()))
+        .unwrap_or_default();
+    let body = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0">
+  <channel>
+    <title>{title}</title>
+    <link>{base_url}/issues</link>
+    <description>{description}</description>{last_build_date}{items}
+  </channel>
+</rss>
+"#,
+        title = FEED_TITLE,
+        description = FEED_DESCRIPTION,
+    );
+    Ok(feed_response(
+        &request,
+        "application/rss+xml; charset=utf-8",
+        body,
+        last_modified(&issues),
+    ))
+}
+
+#[tracing::instrument(name = "Serve the Atom feed", skip_all)]
+pub async fn atom_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issues = get_feed_issues(&pool).await.map_err(e500)?;
+    let base_url = &base_url.0;
+    let mut entries = String::new();
+    for issue in &issues {
+        let link = web_view_link(base_url, &issue.slug);
+        write!(
+            entries,
+            r#"
+  <entry>
+    <title>{title}</title>
+    <id>{link}</id>
+    <link href="{link}"/>
+    <updated>{published_at}</updated>
+    <content type="html">{content}</content>
+  </entry>"#,
+            title = xml_escape(&issue.title),
+            published_at = issue.published_at.to_rfc3339(),
+            content = xml_escape(&web_content(&issue.html_content, base_url, &issue.slug)),
+        )
+        .unwrap();
+    }
+    // Atom requires an update time, even for a feed without entries
+    let updated = last_modified(&issues).unwrap_or(DateTime::from(SystemTime::UNIX_EPOCH));
+    let body = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>{title}</title>
+  <subtitle>{description}</subtitle>
+  <id>{base_url}/issues</id>
+  <link href="{base_url}/issues"/>
+  <link rel="self" href="{base_url}/feed.atom"/>
+  <author><name>{title}</name></author>
+  <updated>{updated}</updated>{entries}
+</feed>
+"#,
+        title = FEED_TITLE,
+        description = FEED_DESCRIPTION,
+        updated = updated.to_rfc3339(),
+    );
+    Ok(feed_response(
+        &request,
+        "application/atom+xml; charset=utf-8",
+        body,
+        last_modified(&issues),
+    ))
+}
+
+/// The most recent issues of the public archive, newest first.
+#[tracing::instrument(skip(pool))]
+async fn get_feed_issues(pool: &PgPool) -> Result<Vec<FeedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        FeedIssue,
+        r#"
+        SELECT slug AS "slug!", title, html_content, published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE is_public AND status IN ('sending', 'sent')
+        ORDER BY published_at DESC
+        LIMIT $1
+    "#,
+        ISSUES_IN_FEED
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the newsletter issues of the feed")?;
+    Ok(issues)
+}
+
+fn last_modified(issues: &[FeedIssue]) -> Option<DateTime<Utc>> {
+    issues.iter().map(|issue| issue.published_at).max()
+}
+
+/// Feed readers poll often: they get a `304 Not Modified` when the feed has
+/// not changed since their last visit.
+/// The ETag is derived from the body, since an issue leaving the archive
+/// changes the feed without changing the last publication date.
+fn feed_response(
+    request: &HttpRequest,
+    content_type: &str,
+    body: String,
+    last_modified: Option<DateTime<Utc>>,
+) -> HttpResponse {
+    let etag = format!("\"{}\"", hex_digest(body.as_bytes()));
+    // Dates in HTTP headers have a one second precision
+    let last_modified = last_modified.map(|t| HttpDate::from(SystemTime::from(t.trunc_subsecs(0))));
+    let headers = request.headers();
+    // If-Modified-Since is only considered when the client sent no ETag
+    let not_modified = match headers.get(header::IF_NONE_MATCH) {
+        Some(if_none_match) => if_none_match
+            .to_str()
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|tag| tag.trim().trim_start_matches("W/"))
+                    .any(|tag| tag == etag || tag == "*")
+            })
+            .unwrap_or(false),
+        None => match (headers.get(header::IF_MODIFIED_SINCE), last_modified) {
+            (Some(if_modified_since), Some(last_modified)) => if_modified_since
+                .to_str()
+                .ok()
+                .and_then(|value| value.parse::<HttpDate>().ok())
+                .is_some_and(|since| SystemTime::from(last_modified) <= SystemTime::from(since)),
+            _ => false,
+        },
+    };
+
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response.insert_header((header::ETAG, etag));
+    if let Some(last_modified) = last_modified {
+        response.insert_header((header::LAST_MODIFIED, last_modified.to_string()));
+    }
+    if not_modified {
+        response.finish()
+    } else {
+        response
+            .insert_header((header::CONTENT_TYPE, content_type.to_owned()))
+            .body(body)
+    }
+}
+
+fn hex_digest(bytes: &[u8]) -> String {
+    Sha3_256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// `&`, `<`, `>` and quotes are all the escaping XML needs.
+fn xml_escape(s: &str) -> String {
+    htmlescape::encode_minimal(s)
+}
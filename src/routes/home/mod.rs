@@ -1,7 +1,51 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use sqlx::PgPool;
+use std::fmt::Write;
 
-pub async fn home() -> HttpResponse {
-    HttpResponse::Ok()
+use crate::mailing_lists::get_mailing_lists;
+use crate::utils::e500;
+
+pub async fn home(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let mut lists_html = String::new();
+    for list in get_mailing_lists(&pool).await.map_err(e500)? {
+        writeln!(
+            lists_html,
+            r#"<label>
+            <input type="radio" name="list_id" value="{id}"{checked}>
+            {name}
+        </label>
+        <p>{description}</p>"#,
+            id = list.list_id,
+            checked = if list.is_default { " checked" } else { "" },
+            name = htmlescape::encode_minimal(&list.name),
+            description = htmlescape::encode_minimal(&list.description),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(include_str!("home.html"))
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <!-- This is equivalent to a HTTP header -->
+    <meta http-equiv="content-type" content="text/html; charset=utf-8" />
+    <title>Home</title>
+  </head>
+  <body>
+    <p>Welcome to our newsletter!</p>
+    <form action="/subscriptions" method="post">
+        {lists_html}
+        <label>Email:
+            <input type="email" name="email">
+        </label>
+        <label>Name:
+            <input type="text" name="username">
+        </label>
+        <button type="submit">Subscribe</button>
+    </form>
+    <p><a href="/issues">Read past issues</a></p>
+  </body>
+</html>"#,
+        )))
 }
@@ -0,0 +1,194 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::domain::{IssueTemplate, TemplateContext, TemplateFormat};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::e500;
+
+const ISSUES_PER_PAGE: i64 = 20;
+
+/// Visitors are not subscribers: the placeholders get generic values.
+const WEB_VIEW_USERNAME: &str = "reader";
+
+pub fn web_view_link(base_url: &str, slug: &str) -> String {
+    format!("{}/issues/{}", base_url, slug)
+}
+
+/// The HTML content of an issue as shown outside of emails.
+pub(super) fn web_content(html_content: &str, base_url: &str, slug: &str) -> String {
+    let web_view_url = web_view_link(base_url, slug);
+    let home_url = format!("{}/", base_url);
+    let context = TemplateContext {
+        username: WEB_VIEW_USERNAME,
+        unsubscribe_url: &home_url,
+        web_view_url: &web_view_url,
+    };
+    IssueTemplate::parse(html_content)
+        .unwrap_or_else(|_| IssueTemplate::verbatim(html_content))
+        .render(&context, TemplateFormat::Html)
+}
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveParameters {
+    page: Option<i64>,
+}
+
+struct ArchivedIssue {
+    slug: String,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Show the archive of published issues", skip(parameters, pool))]
+pub async fn issues_archive(
+    parameters: web::Query<ArchiveParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let page = parameters.page.unwrap_or(1).max(1);
+    let mut issues = get_archived_issues(&pool, page).await.map_err(e500)?;
+    // One more issue than needed is fetched to know whether there is a next page
+    let has_next_page = issues.len() as i64 > ISSUES_PER_PAGE;
+    issues.truncate(ISSUES_PER_PAGE as usize);
+
+    let mut issues_html = String::new();
+    for issue in &issues {
+        writeln!(
+            issues_html,
+            r#"<li><a href="/issues/{}">{}</a> ({})</li>"#,
+            issue.slug,
+            htmlescape::encode_minimal(&issue.title),
+            issue.published_at.format("%Y-%m-%d")
+        )
+        .unwrap();
+    }
+    let content_html = if issues.is_empty() {
+        "<p>No issues yet.</p>".to_string()
+    } else {
+        format!("<ul>\n{issues_html}</ul>")
+    };
+    let mut pages_html = String::new();
+    if page > 1 {
+        write!(
+            pages_html,
+            r#"<a href="/issues?page={}">Newer issues</a> "#,
+            page - 1
+        )
+        .unwrap();
+    }
+    if has_next_page {
+        write!(
+            pages_html,
+            r#"<a href="/issues?page={}">Older issues</a>"#,
+            page + 1
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Newsletter archive</title>
+    <link rel="alternate" type="application/rss+xml" title="RSS" href="/feed.rss">
+    <link rel="alternate" type="application/atom+xml" title="Atom" href="/feed.atom">
+</head>
+<body>
+    <h1>Newsletter archive</h1>
+    {content_html}
+    <p>{pages_html}</p>
+    <p>Follow along in your feed reader: <a href="/feed.rss">RSS</a>, <a href="/feed.atom">Atom</a></p>
+    <p><a href="/">&lt;- Home</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_archived_issues(
+    pool: &PgPool,
+    page: i64,
+) -> Result<Vec<ArchivedIssue>, anyhow::Error> {
+    // Issues are readable once they started going out, unless they were kept out
+    // of the archive. Paused and cancelled ones might have been stopped for a reason
+    let issues = sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT slug AS "slug!", title, published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE is_public AND status IN ('sending', 'sent')
+        ORDER BY published_at DESC
+        LIMIT $1 OFFSET $2
+    "#,
+        ISSUES_PER_PAGE + 1,
+        (page - 1) * ISSUES_PER_PAGE
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the archived newsletter issues")?;
+    Ok(issues)
+}
+
+struct WebIssue {
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Show the web version of an issue", skip(pool, base_url))]
+pub async fn issue_web_view(
+    slug: web::Path<String>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue = match get_web_issue(&pool, &slug).await.map_err(e500)? {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let content_html = web_content(&issue.html_content, &base_url.0, &slug);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        // The content is written by the admins, but it is served next to
+        // the admin area: it gets the styles it was written with, not scripts
+        .insert_header(("Content-Security-Policy", "script-src 'none'"))
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <h1>{title}</h1>
+    <p>{published_on}</p>
+    <div>{content_html}</div>
+    <p><a href="/issues">&lt;- All issues</a></p>
+</body>
+</html>"#,
+            title = htmlescape::encode_minimal(&issue.title),
+            published_on = issue.published_at.format("%Y-%m-%d"),
+        )))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_web_issue(pool: &PgPool, slug: &str) -> Result<Option<WebIssue>, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        WebIssue,
+        r#"
+        SELECT title, html_content, published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE slug = $1 AND is_public AND status IN ('sending', 'sent')
+    "#,
+        slug
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the newsletter issue")?;
+    Ok(issue)
+}
@@ -1,12 +1,10 @@
 use actix_web::{http::header::ContentType, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
-use std::fmt::Write;
+
+use crate::utils::flash_messages_html;
 
 pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
-    let mut error_html: String = String::new();
-    for m in flash_messages.iter() {
-        writeln!(error_html, "<p><i>{}</i></p>", m.content()).unwrap();
-    }
+    let error_html = flash_messages_html(&flash_messages);
     HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -0,0 +1,760 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{
+    domain::{SubscriberEmail, SubscriberUsername},
+    email_client::EmailSender,
+    email_layout::{apply_layout, get_default_email_layout},
+    routes::generate_subscription_token,
+    startup::ApplicationBaseUrl,
+    utils::{e400, e500, flash_messages_html, redirect},
+};
+
+// How long the link sent to a new address stays valid
+const EMAIL_CHANGE_TOKEN_TTL_HOURS: i64 = 24;
+// Each request sends an email to an address of their choosing, they are capped
+// so that the form cannot be used to flood someone's inbox
+const MAX_EMAIL_CHANGES_PER_HOUR: i64 = 3;
+// Delivery can be paused for a year at most, it is not meant to replace unsubscribing
+const MAX_PAUSE_DAYS: i64 = 365;
+
+// The preferences are found with the unsubscribe token of the subscriber:
+// whoever can unsubscribe them from an email can also change their preferences.
+#[derive(Deserialize)]
+pub struct PreferencesParameters {
+    token: String,
+}
+
+pub fn preferences_link(base_url: &str, unsubscribe_token: &str) -> String {
+    format!("{}/preferences?token={}", base_url, unsubscribe_token)
+}
+
+struct Subscriber {
+    id: Uuid,
+    email: String,
+    username: String,
+    status: String,
+    paused_until: Option<DateTime<Utc>>,
+}
+
+impl Subscriber {
+    fn is_paused(&self) -> bool {
+        self.paused_until.is_some_and(|until| until > Utc::now())
+    }
+}
+
+struct ListChoice {
+    list_id: Uuid,
+    name: String,
+    description: String,
+    // Missing if the subscriber is not on the list
+    status: Option<String>,
+}
+
+#[tracing::instrument(name = "Show the preferences page", skip_all)]
+pub async fn preferences_form(
+    parameters: web::Query<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber = match get_subscriber_from_token(&pool, &parameters.token)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    let msg_html = flash_messages_html(&flash_messages);
+    if subscriber.status == "unsubscribed" {
+        return Ok(preferences_page(&format!(
+            r#"{msg_html}
+    <p>You have unsubscribed from our newsletter.</p>
+    <p>You can subscribe again from our <a href="/">home page</a>.</p>"#
+        )));
+    }
+
+    let token = &parameters.token;
+    let mut lists_html = String::new();
+    for list in get_list_choices(&pool, subscriber.id).await.map_err(e500)? {
+        writeln!(
+            lists_html,
+            r#"<label>
+            <input type="checkbox" name="list_id" value="{id}"{checked}>
+            {name}{pending}
+        </label>
+        <p>{description}</p>"#,
+            id = list.list_id,
+            checked = if list.status.is_some() {
+                " checked"
+            } else {
+                ""
+            },
+            name = htmlescape::encode_minimal(&list.name),
+            pending = if list.status.as_deref() == Some("pending_confirmation") {
+                " (waiting for your confirmation)"
+            } else {
+                ""
+            },
+            description = htmlescape::encode_minimal(&list.description),
+        )
+        .unwrap();
+    }
+    let pause_html = match subscriber.paused_until {
+        Some(until) if subscriber.is_paused() => format!(
+            r#"<p>Delivery is paused until {}.</p>
+    <form action="/preferences/resume?token={token}" method="post">
+        <button type="submit">Resume now</button>
+    </form>"#,
+            until.format("%Y-%m-%d")
+        ),
+        _ => format!(
+            r#"<p>Taking a break? You will not receive any issue until the date you choose.</p>
+    <form action="/preferences/pause?token={token}" method="post">
+        <label>Pause until:
+            <input type="date" name="until">
+        </label>
+        <button type="submit">Pause</button>
+    </form>"#
+        ),
+    };
+    Ok(preferences_page(&format!(
+        r#"{msg_html}
+    <p>You receive our newsletter at {email}.</p>
+    <h2>Your name</h2>
+    <form action="/preferences/username?token={token}" method="post">
+        <label>Name:
+            <input type="text" name="username" value="{username}">
+        </label>
+        <button type="submit">Save</button>
+    </form>
+    <h2>Your lists</h2>
+    <form action="/preferences/lists?token={token}" method="post">
+        {lists_html}
+        <button type="submit">Save</button>
+    </form>
+    <h2>Your email address</h2>
+    <p>We will send a link to the new address, it replaces the current one once you follow it.</p>
+    <form action="/preferences/email?token={token}" method="post">
+        <label>New email address:
+            <input type="email" name="email">
+        </label>
+        <button type="submit">Change</button>
+    </form>
+    <h2>Pause delivery</h2>
+    {pause_html}
+    <h2>Unsubscribe</h2>
+    <form action="/subscriptions/unsubscribe?token={token}" method="post">
+        <button type="submit">Stop receiving our newsletter</button>
+    </form>"#,
+        email = htmlescape::encode_minimal(&subscriber.email),
+        username = htmlescape::encode_minimal(&subscriber.username),
+    )))
+}
+
+fn preferences_page(body: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Your preferences</title>
+</head>
+<body>
+    {body}
+</body>
+</html>"#,
+        ))
+}
+
+#[derive(Deserialize)]
+pub struct UsernameFormData {
+    username: String,
+}
+
+#[tracing::instrument(name = "Update the name of a subscriber", skip_all)]
+pub async fn update_username(
+    parameters: web::Query<PreferencesParameters>,
+    form: web::Form<UsernameFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber = match get_active_subscriber(&pool, &parameters.token).await? {
+        Ok(subscriber) => subscriber,
+        Err(response) => return Ok(response),
+    };
+    let preferences = preferences_link("", &parameters.token);
+    let username = match SubscriberUsername::parse(form.0.username) {
+        Ok(username) => username,
+        Err(_) => {
+            FlashMessage::error("This name cannot be used, please pick another one.").send();
+            return Ok(redirect(&preferences));
+        }
+    };
+    sqlx::query!(
+        "UPDATE subscriptions SET username = $1 WHERE id = $2",
+        username.as_ref(),
+        subscriber.id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the name of the subscriber")
+    .map_err(e500)?;
+    FlashMessage::info("Your name has been updated.").send();
+    Ok(redirect(&preferences))
+}
+
+#[tracing::instrument(name = "Update the lists of a subscriber", skip_all)]
+pub async fn update_lists(
+    parameters: web::Query<PreferencesParameters>,
+    // One `list_id` per ticked checkbox
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber = match get_active_subscriber(&pool, &parameters.token).await? {
+        Ok(subscriber) => subscriber,
+        Err(response) => return Ok(response),
+    };
+    let list_ids = form
+        .0
+        .into_iter()
+        .filter(|(name, _)| name == "list_id")
+        .map(|(_, value)| Uuid::parse_str(&value))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(e400)?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    sqlx::query!(
+        "DELETE FROM list_memberships WHERE subscriber_id = $1 AND list_id <> ALL($2)",
+        subscriber.id,
+        &list_ids
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to remove the subscriber from the lists")
+    .map_err(e500)?;
+    // Each list they join is confirmed on its own, like when subscribing to it.
+    // The lists they are already on are left as they are
+    let joined_list_ids: Vec<Uuid> = sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status)
+        SELECT $1, list_id, 'pending_confirmation'
+        FROM lists
+        WHERE list_id = ANY($2)
+        ON CONFLICT (subscriber_id, list_id) DO NOTHING
+        RETURNING list_id
+    "#,
+        subscriber.id,
+        &list_ids
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to add the subscriber to the lists")
+    .map_err(e500)?
+    .into_iter()
+    .map(|r| r.list_id)
+    .collect();
+    // The confirmation emails are sent by the background worker
+    sqlx::query!(
+        r#"
+        INSERT INTO confirmation_email_queue (subscriber_id, list_id)
+        SELECT $1, list_id FROM UNNEST($2::uuid[]) AS joined(list_id)
+        ON CONFLICT (subscriber_id, list_id) DO NOTHING
+    "#,
+        subscriber.id,
+        &joined_list_ids
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to enqueue the confirmation emails")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the lists of the subscriber")
+        .map_err(e500)?;
+    if joined_list_ids.is_empty() {
+        FlashMessage::info("Your lists have been updated.").send();
+    } else {
+        FlashMessage::info(
+            "Your lists have been updated. \
+            Confirm the new ones with the link we are sending to your inbox.",
+        )
+        .send();
+    }
+    Ok(redirect(&preferences_link("", &parameters.token)))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_list_choices(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ListChoice>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        ListChoice,
+        r#"
+        SELECT l.list_id, l.name, l.description, m.status AS "status?"
+        FROM lists l
+        LEFT JOIN list_memberships m ON m.list_id = l.list_id AND m.subscriber_id = $1
+        ORDER BY l.name
+    "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the lists")?;
+    Ok(lists)
+}
+
+#[derive(Deserialize)]
+pub struct EmailFormData {
+    email: String,
+}
+
+#[tracing::instrument(
+    name = "Request a change of email address",
+    skip_all,
+    fields(subscriber_id = tracing::field::Empty)
+)]
+pub async fn change_email(
+    parameters: web::Query<PreferencesParameters>,
+    form: web::Form<EmailFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailSender>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber = match get_active_subscriber(&pool, &parameters.token).await? {
+        Ok(subscriber) => subscriber,
+        Err(response) => return Ok(response),
+    };
+    tracing::Span::current().record("subscriber_id", tracing::field::display(subscriber.id));
+    let preferences = preferences_link("", &parameters.token);
+    let new_email = match SubscriberEmail::parse(form.0.email.trim().to_string()) {
+        Ok(email) => email,
+        Err(_) => {
+            FlashMessage::error("This is not a valid email address.").send();
+            return Ok(redirect(&preferences));
+        }
+    };
+    if new_email.as_ref().eq_ignore_ascii_case(&subscriber.email) {
+        FlashMessage::info("This is already your email address.").send();
+        return Ok(redirect(&preferences));
+    }
+
+    // The subscriber is locked, so that concurrent requests cannot get past the limit together
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let n_recent_requests = sqlx::query!(
+        r#"
+        SELECT (
+            SELECT COUNT(*) FROM email_change_requests r
+            WHERE r.subscriber_id = s.id AND r.created_at > now() - interval '1 hour'
+        ) AS "count!"
+        FROM subscriptions s
+        WHERE s.id = $1
+        FOR UPDATE
+    "#,
+        subscriber.id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to count the recent email change requests")
+    .map_err(e500)?
+    .count;
+    if n_recent_requests >= MAX_EMAIL_CHANGES_PER_HOUR {
+        FlashMessage::error(
+            "You asked to change your email address too many times. Please try again later.",
+        )
+        .send();
+        return Ok(redirect(&preferences));
+    }
+
+    // The same message is shown when the address belongs to someone else,
+    // so that this page cannot be used to find out who is subscribed
+    if !is_email_taken(&pool, new_email.as_ref())
+        .await
+        .map_err(e500)?
+    {
+        let confirmation_token = generate_subscription_token();
+        sqlx::query!(
+            r#"
+            INSERT INTO email_change_requests (confirmation_token, subscriber_id, new_email, expires_at)
+            VALUES ($1, $2, $3, $4)
+        "#,
+            confirmation_token,
+            subscriber.id,
+            new_email.as_ref(),
+            Utc::now() + Duration::hours(EMAIL_CHANGE_TOKEN_TTL_HOURS)
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to store the email change request")
+        .map_err(e500)?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the email change request")
+            .map_err(e500)?;
+        send_email_change_confirmation(
+            email_client.get_ref(),
+            &pool,
+            &new_email,
+            &base_url.0,
+            &confirmation_token,
+        )
+        .await
+        .map_err(e500)?;
+    }
+    FlashMessage::info(format!(
+        "We sent a link to {}. Your address will change once you follow it.",
+        new_email.as_ref()
+    ))
+    .send();
+    Ok(redirect(&preferences))
+}
+
+async fn is_email_taken(pool: &PgPool, email: &str) -> Result<bool, anyhow::Error> {
+    let record = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE lower(email) = lower($1)",
+        email
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up the new email address")?;
+    Ok(record.is_some())
+}
+
+#[tracing::instrument(skip_all)]
+async fn send_email_change_confirmation(
+    email_client: &dyn EmailSender,
+    pool: &PgPool,
+    recipient: &SubscriberEmail,
+    base_url: &str,
+    confirmation_token: &str,
+) -> Result<(), anyhow::Error> {
+    let layout = get_default_email_layout(pool).await?;
+    let confirmation_link = format!(
+        "{}/preferences/email/confirm?token={}",
+        base_url, confirmation_token
+    );
+    let (html_content, text_content) = apply_layout(
+        layout.as_ref(),
+        format!(
+            "You asked to receive our newsletter at this address.<br />\
+            Click <a href=\"{}\">here</a> to confirm it.",
+            confirmation_link
+        ),
+        format!(
+            "You asked to receive our newsletter at this address.\n\
+            Visit {} to confirm it.",
+            confirmation_link
+        ),
+    );
+    email_client
+        .send_email(
+            recipient,
+            "Confirm your new email address",
+            &html_content,
+            &text_content,
+        )
+        .await
+}
+
+#[tracing::instrument(name = "Confirm a change of email address", skip_all)]
+pub async fn confirm_email_change(
+    parameters: web::Query<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let request = sqlx::query!(
+        r#"
+        SELECT r.subscriber_id, r.new_email, r.expires_at, s.email, s.unsubscribe_token
+        FROM email_change_requests r
+        JOIN subscriptions s ON s.id = r.subscriber_id
+        WHERE r.confirmation_token = $1
+        FOR UPDATE OF s
+    "#,
+        parameters.token
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve the email change request")
+    .map_err(e500)?;
+    let request = match request {
+        Some(request) => request,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    if request.expires_at < Utc::now() {
+        return Ok(HttpResponse::Gone()
+            .content_type(ContentType::html())
+            .body(email_change_page(
+                "<p>This confirmation link has expired.</p>\n    \
+                <p>Please ask for the change again from your preferences.</p>",
+            )));
+    }
+
+    // The address might have been subscribed since the change was requested
+    let taken = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE lower(email) = lower($1) AND id <> $2",
+        request.new_email,
+        request.subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to look up the new email address")
+    .map_err(e500)?
+    .is_some();
+    let body = if taken {
+        "<p>This address is already subscribed to our newsletter, it cannot be used twice.</p>"
+    } else {
+        sqlx::query!(
+            "UPDATE subscriptions SET email = $1 WHERE id = $2",
+            request.new_email,
+            request.subscriber_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to update the email address")
+        .map_err(e500)?;
+        // Issues that are still on their way go to the new address
+        sqlx::query!(
+            "UPDATE issue_delivery_table SET subscriber_email = $1 WHERE subscriber_email = $2",
+            request.new_email,
+            request.email
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to update the queued deliveries")
+        .map_err(e500)?;
+        // So does the record of what was already sent to them, or failed to be.
+        // A failure left behind by a former subscriber of the new address wins.
+        sqlx::query!(
+            r#"
+            UPDATE issue_delivery_failures f SET subscriber_email = $1
+            WHERE f.subscriber_email = $2
+              AND NOT EXISTS (
+                SELECT 1 FROM issue_delivery_failures o
+                WHERE o.newsletter_issue_id = f.newsletter_issue_id
+                  AND o.subscriber_email = $1
+              )
+            "#,
+            request.new_email,
+            request.email
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to update the delivery failures")
+        .map_err(e500)?;
+        sqlx::query!(
+            "UPDATE newsletter_issue_deliveries SET subscriber_email = $1 WHERE subscriber_email = $2",
+            request.new_email,
+            request.email
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to update the delivery history")
+        .map_err(e500)?;
+        "<p>Your email address has been updated.</p>"
+    };
+    // Whatever the outcome, the other links sent to this subscriber are now stale
+    sqlx::query!(
+        "DELETE FROM email_change_requests WHERE subscriber_id = $1",
+        request.subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the email change requests")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the email change")
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(email_change_page(&format!(
+            r#"{body}
+    <p><a href="{}">Back to your preferences</a></p>"#,
+            preferences_link("", &request.unsubscribe_token)
+        ))))
+}
+
+fn email_change_page(body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Email address change</title>
+</head>
+<body>
+    {body}
+</body>
+</html>"#,
+    )
+}
+
+#[derive(Deserialize)]
+pub struct PauseFormData {
+    // As YYYY-MM-DD, delivery resumes at the start of that day
+    until: String,
+}
+
+#[tracing::instrument(name = "Pause delivery for a subscriber", skip_all)]
+pub async fn pause_delivery(
+    parameters: web::Query<PreferencesParameters>,
+    form: web::Form<PauseFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber = match get_active_subscriber(&pool, &parameters.token).await? {
+        Ok(subscriber) => subscriber,
+        Err(response) => return Ok(response),
+    };
+    let preferences = preferences_link("", &parameters.token);
+    let until = match parse_pause_date(&form.until, Utc::now()) {
+        Ok(until) => until,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(redirect(&preferences));
+        }
+    };
+    sqlx::query!(
+        "UPDATE subscriptions SET paused_until = $1 WHERE id = $2",
+        until,
+        subscriber.id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to pause delivery")
+    .map_err(e500)?;
+    FlashMessage::info(format!(
+        "Delivery is paused until {}.",
+        until.format("%Y-%m-%d")
+    ))
+    .send();
+    Ok(redirect(&preferences))
+}
+
+fn parse_pause_date(until: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let date = NaiveDate::parse_from_str(until.trim(), "%Y-%m-%d")
+        .map_err(|_| "Choose the date when delivery should resume.".to_string())?;
+    let until = Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap());
+    if until <= now {
+        return Err("Choose a date in the future.".into());
+    }
+    if until > now + Duration::days(MAX_PAUSE_DAYS) {
+        return Err(format!(
+            "Delivery can be paused for {} days at most.",
+            MAX_PAUSE_DAYS
+        ));
+    }
+    Ok(until)
+}
+
+#[tracing::instrument(name = "Resume delivery for a subscriber", skip_all)]
+pub async fn resume_delivery(
+    parameters: web::Query<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber = match get_active_subscriber(&pool, &parameters.token).await? {
+        Ok(subscriber) => subscriber,
+        Err(response) => return Ok(response),
+    };
+    sqlx::query!(
+        "UPDATE subscriptions SET paused_until = NULL WHERE id = $1",
+        subscriber.id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to resume delivery")
+    .map_err(e500)?;
+    FlashMessage::info("Delivery has been resumed.").send();
+    Ok(redirect(&preferences_link("", &parameters.token)))
+}
+
+/// The subscriber behind a token, unless they have unsubscribed:
+/// their preferences page then only offers to subscribe again.
+async fn get_active_subscriber(
+    pool: &PgPool,
+    token: &str,
+) -> Result<Result<Subscriber, HttpResponse>, actix_web::Error> {
+    match get_subscriber_from_token(pool, token).await.map_err(e500)? {
+        None => Ok(Err(HttpResponse::Unauthorized().finish())),
+        Some(subscriber) if subscriber.status == "unsubscribed" => {
+            Ok(Err(redirect(&preferences_link("", token))))
+        }
+        Some(subscriber) => Ok(Ok(subscriber)),
+    }
+}
+
+#[tracing::instrument(name = "Get subscriber from preferences token", skip_all)]
+async fn get_subscriber_from_token(
+    pool: &PgPool,
+    token: &str,
+) -> Result<Option<Subscriber>, anyhow::Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, email, username, status, paused_until
+        FROM subscriptions
+        WHERE unsubscribe_token = $1
+    "#,
+        token
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the subscriber matching the preferences token")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use claims::{assert_err, assert_ok};
+
+    use super::parse_pause_date;
+
+    #[test]
+    fn delivery_resumes_at_the_start_of_the_chosen_day() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 21, 15, 0, 0).unwrap();
+        let until = assert_ok!(parse_pause_date("2024-04-01", now));
+        assert_eq!(until, Utc.with_ymd_and_hms(2024, 4, 1, 0, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn the_date_must_be_in_the_future() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 21, 15, 0, 0).unwrap();
+        assert_err!(parse_pause_date("2024-03-21", now));
+        assert_err!(parse_pause_date("2023-12-25", now));
+    }
+
+    #[test]
+    fn delivery_cannot_be_paused_for_more_than_a_year() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 21, 15, 0, 0).unwrap();
+        assert_ok!(parse_pause_date("2025-03-21", now));
+        assert_err!(parse_pause_date("2025-03-22", now));
+    }
+
+    #[test]
+    fn the_date_must_be_valid() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 21, 15, 0, 0).unwrap();
+        assert_err!(parse_pause_date("", now));
+        assert_err!(parse_pause_date("2024-02-30", now));
+    }
+}
@@ -1,6 +1,6 @@
 use actix_web::{web, HttpResponse, ResponseError};
 use anyhow::Context;
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use reqwest::StatusCode;
 use sqlx::{PgPool, Postgres, Transaction};
@@ -8,11 +8,17 @@ use uuid::Uuid;
 
 use crate::{
     domain::{NewSubscriber, SubscriberEmail, SubscriberUsername},
-    email_client::EmailClient,
+    email_client::EmailSender,
+    email_layout::{apply_layout, get_default_email_layout, EmailLayout},
+    mailing_lists::selected_mailing_list,
+    routes::{preferences_link, unsubscribe_link},
     startup::ApplicationBaseUrl,
 };
 
-fn generate_subscription_token() -> String {
+// How long a confirmation link stays valid after it has been sent
+const SUBSCRIPTION_TOKEN_TTL_HOURS: i64 = 24;
+
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -24,6 +30,8 @@ fn generate_subscription_token() -> String {
 pub struct FormData {
     email: String,
     username: String,
+    // Missing to join the default list
+    list_id: Option<String>,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -47,13 +55,17 @@ impl TryFrom<FormData> for NewSubscriber {
 pub async fn subscribe(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
+    email_client: web::Data<dyn EmailSender>,
     base_url: web::Data<ApplicationBaseUrl>,
 ) -> Result<HttpResponse, SubscribeError> {
+    let mut form = form.into_inner();
+    let list = selected_mailing_list(&pool, form.list_id.take().as_deref())
+        .await?
+        .ok_or_else(|| SubscribeError::ValidationError("This list does not exist.".into()))?;
     let new_subscriber = form
-        .0
         .try_into()
         .map_err(|e| SubscribeError::ValidationError(e))?;
+    let layout = get_default_email_layout(&pool).await?;
 
     ////////////////////////////////// START OF TRANSACTION
 
@@ -62,16 +74,81 @@ pub async fn subscribe(
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
 
-    // First insert the subscriber to the database with status == pending
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    // The insert goes first: when the same address subscribes twice at once, the
+    // second attempt waits for the first one and then finds its row below
+    let unsubscribe_token = generate_subscription_token();
+    let subscriber_id =
+        match insert_subscriber(&mut transaction, &new_subscriber, &unsubscribe_token)
+            .await
+            .context("Failed to insert new subscriber in the database.")?
+        {
+            // A new subscriber, with status == pending
+            // The unsubscribe token is generated once and lives as long as the subscription
+            Some(subscriber_id) => subscriber_id,
+            None => {
+                let subscriber =
+                    get_subscriber_by_email(&mut transaction, &new_subscriber, list.list_id)
+                        .await
+                        .context("Failed to look up an existing subscriber.")?
+                        .context("The subscriber was deleted while subscribing again.")?;
+                match subscriber {
+                    // Already on the list: there is nothing to store, we let them know by email.
+                    // The response is the same as for a new subscriber, so that this endpoint
+                    // cannot be used to find out whether an address is subscribed.
+                    subscriber
+                        if subscriber.status == "confirmed"
+                            && subscriber.list_status.as_deref() == Some("confirmed") =>
+                    {
+                        drop(transaction);
+                        send_already_subscribed_email(
+                            email_client.get_ref(),
+                            layout.as_ref(),
+                            new_subscriber,
+                            &list.name,
+                            &base_url.as_ref().0,
+                            &subscriber.unsubscribe_token,
+                        )
+                        .await
+                        .context("Failed to send an already subscribed email.")?;
+                        return Ok(subscribe_response());
+                    }
+                    // A subscriber joining another list confirms that one too, without
+                    // affecting the lists they are already on
+                    subscriber if subscriber.status == "confirmed" => {
+                        delete_list_tokens(&mut transaction, subscriber.id, list.list_id)
+                            .await
+                            .context("Failed to delete the previous confirmation tokens.")?;
+                        subscriber.id
+                    }
+                    // Someone who never confirmed, or who left the list, is trying again:
+                    // their previous links are dropped and they get a fresh one below
+                    subscriber => {
+                        delete_list_tokens(&mut transaction, subscriber.id, list.list_id)
+                            .await
+                            .context("Failed to delete the previous confirmation tokens.")?;
+                        mark_subscriber_as_pending(&mut transaction, subscriber.id)
+                            .await
+                            .context("Failed to move the subscriber back to pending.")?;
+                        subscriber.id
+                    }
+                }
+            }
+        };
+
+    add_list_membership(&mut transaction, subscriber_id, list.list_id)
         .await
-        .context("Failed to insert new subscriber in the database.")?;
+        .context("Failed to add the subscriber to the list.")?;
 
     let subscription_token = generate_subscription_token();
 
-    store_token(&mut transaction, subscriber_id, &subscription_token)
-        .await
-        .context("Failed to store the confirmation token for a new subscriber.")?;
+    store_token(
+        &mut transaction,
+        subscriber_id,
+        list.list_id,
+        &subscription_token,
+    )
+    .await
+    .context("Failed to store the confirmation token for a new subscriber.")?;
 
     transaction
         .commit()
@@ -81,14 +158,20 @@ pub async fn subscribe(
     //////////////////////////////// END OF TRANSACTION
 
     send_confirmation_email(
-        email_client,
-        new_subscriber,
+        email_client.get_ref(),
+        layout.as_ref(),
+        &new_subscriber.email,
+        &list.name,
         &base_url.as_ref().0,
         &subscription_token,
     )
     .await
     .context("Failed to send a confirmation email.")?;
-    Ok(HttpResponse::Ok().finish())
+    Ok(subscribe_response())
+}
+
+fn subscribe_response() -> HttpResponse {
+    HttpResponse::Ok().body("Thank you! Please check your inbox for an email from us.")
 }
 
 // Other way, shorten the code, using external library
@@ -204,6 +287,7 @@ impl ResponseError for SubscribeError {
 //     }
 // }
 
+/// The token confirms the subscription to one list.
 #[tracing::instrument(
     name = "Storing subscription token",
     skip(transaction, subscriber_id, subscription_token)
@@ -211,12 +295,23 @@ impl ResponseError for SubscribeError {
 pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
+    list_id: Uuid,
     subscription_token: &str,
 ) -> Result<(), StoreTokenError> {
+    let created_at = Utc::now();
+    let expires_at = created_at + Duration::hours(SUBSCRIPTION_TOKEN_TTL_HOURS);
     sqlx::query!(
-        "INSERT INTO subscription_tokens (subscription_token, subscription_id) VALUES ($1, $2)",
+        r#"
+        INSERT INTO subscription_tokens (
+            subscription_token, subscription_id, list_id, created_at, expires_at
+        )
+        VALUES ($1, $2, $3, $4, $5)
+    "#,
         subscription_token,
-        subscriber_id
+        subscriber_id,
+        list_id,
+        created_at,
+        expires_at
     )
     .execute(&mut **transaction)
     .await
@@ -224,6 +319,61 @@ pub async fn store_token(
     Ok(())
 }
 
+#[tracing::instrument(name = "Deleting previous subscription tokens", skip(transaction))]
+pub async fn delete_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscription_id = $1",
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Deleting previous subscription tokens for a list",
+    skip(transaction)
+)]
+pub async fn delete_list_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscription_id = $1 AND list_id = $2",
+        subscriber_id,
+        list_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// The membership waits for the confirmation of the subscriber,
+/// unless they are on the list already.
+#[tracing::instrument(name = "Adding subscriber to a list", skip(transaction))]
+pub async fn add_list_membership(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status)
+        VALUES ($1, $2, 'pending_confirmation')
+        ON CONFLICT (subscriber_id, list_id) DO NOTHING
+    "#,
+        subscriber_id,
+        list_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
 pub struct StoreTokenError(sqlx::Error);
 
 impl std::fmt::Display for StoreTokenError {
@@ -262,56 +412,157 @@ impl std::error::Error for StoreTokenError {
 
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber, base_url)
+    skip(email_client, layout, recipient, base_url)
 )]
 pub async fn send_confirmation_email(
-    email_client: web::Data<EmailClient>,
-    new_subscriber: NewSubscriber,
+    email_client: &dyn EmailSender,
+    layout: Option<&EmailLayout>,
+    recipient: &SubscriberEmail,
+    list_name: &str,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), anyhow::Error> {
     let confirmation_link = &format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token,
     );
+    let (html_content, text_content) = apply_layout(
+        layout,
+        format!(
+            "Welcome to {}!<br />\
+            Click <a href=\"{}\">here</a> to confirm your subscription.",
+            htmlescape::encode_minimal(list_name),
+            confirmation_link
+        ),
+        format!(
+            "Welcome to {}!\nVisit {} to confirm your subscription.",
+            list_name, confirmation_link
+        ),
+    );
+    email_client
+        .send_email(recipient, "Welcome!", &html_content, &text_content)
+        .await
+}
+
+pub struct ExistingSubscriber {
+    id: Uuid,
+    status: String,
+    unsubscribe_token: String,
+    /// Their membership of the list they are subscribing to, if any
+    list_status: Option<String>,
+}
+
+#[tracing::instrument(
+    name = "Looking up an existing subscriber by email",
+    skip(form, transaction)
+)]
+pub async fn get_subscriber_by_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    form: &NewSubscriber,
+    list_id: Uuid,
+) -> Result<Option<ExistingSubscriber>, sqlx::Error> {
+    // Lock the row so that concurrent attempts for the same email are serialised
+    // The row exists at this point: `insert_subscriber` ran first
+    sqlx::query_as!(
+        ExistingSubscriber,
+        r#"
+        SELECT s.id, s.status, s.unsubscribe_token, m.status AS "list_status?"
+        FROM subscriptions s
+        LEFT JOIN list_memberships m ON m.subscriber_id = s.id AND m.list_id = $2
+        WHERE s.email = $1
+        FOR UPDATE OF s
+    "#,
+        form.email.as_ref(),
+        list_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+}
+
+#[tracing::instrument(name = "Marking subscriber as pending", skip(transaction))]
+pub async fn mark_subscriber_as_pending(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'pending_confirmation', confirmed_at = NULL, unsubscribed_at = NULL
+        WHERE id = $1
+    "#,
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Send an already subscribed email to an existing subscriber",
+    skip(email_client, layout, new_subscriber, base_url, unsubscribe_token)
+)]
+pub async fn send_already_subscribed_email(
+    email_client: &dyn EmailSender,
+    layout: Option<&EmailLayout>,
+    new_subscriber: NewSubscriber,
+    list_name: &str,
+    base_url: &str,
+    unsubscribe_token: &str,
+) -> Result<(), anyhow::Error> {
+    let unsubscribe_link = unsubscribe_link(base_url, unsubscribe_token);
+    let preferences_link = preferences_link(base_url, unsubscribe_token);
+    let (html_content, text_content) = apply_layout(
+        layout,
+        format!(
+            "You are already subscribed to {}, there is nothing else to do.<br />\
+            You can update your preferences <a href=\"{}\">here</a>.<br />\
+            If you want to stop receiving it, click <a href=\"{}\">here</a> to unsubscribe.",
+            htmlescape::encode_minimal(list_name),
+            preferences_link,
+            unsubscribe_link
+        ),
+        format!(
+            "You are already subscribed to {}, there is nothing else to do.\n\
+            You can update your preferences at {}.\n\
+            If you want to stop receiving it, visit {} to unsubscribe.",
+            list_name, preferences_link, unsubscribe_link
+        ),
+    );
     email_client
         .send_email(
             &new_subscriber.email,
-            "Welcome!",
-            &format!(
-                "Welcome to our newsletter!<br />\
-                Click <a href=\"{}\">here</a> to confirm your subscription.",
-                confirmation_link
-            ),
-            &format!(
-                "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-                confirmation_link
-            ),
+            "You are already subscribed!",
+            &html_content,
+            &text_content,
         )
         .await
 }
 
+/// Returns `None` when the address is already known, the existing row is left untouched.
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
-    skip(form, transaction)
+    skip(form, transaction, unsubscribe_token)
 )]
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     form: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
+    unsubscribe_token: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
+    let inserted = sqlx::query!(
         r#"
-        INSERT INTO subscriptions(id, email, username, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        INSERT INTO subscriptions(id, email, username, subscribed_at, status, unsubscribe_token)
+        VALUES ($1, $2, $3, $4, 'pending_confirmation', $5)
+        ON CONFLICT (email) DO NOTHING
+        RETURNING id
     "#,
         subscriber_id,
         form.email.as_ref(),
         form.username.as_ref(),
-        Utc::now()
+        Utc::now(),
+        unsubscribe_token
     )
-    .execute(&mut **transaction)
-    .await
-    .map_err(|e| e)?;
-    Ok(subscriber_id)
+    .fetch_optional(&mut **transaction)
+    .await?;
+    Ok(inserted.map(|r| r.id))
 }
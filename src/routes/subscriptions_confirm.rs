@@ -1,4 +1,5 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
 use sqlx::PgPool;
 use uuid::Uuid;
@@ -12,16 +13,18 @@ pub struct Parameters {
 
 #[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
 pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>) -> HttpResponse {
-    let subscriber_id = match get_subscriber_from_token(&pool, &parameters.subscription_token).await
-    {
-        Ok(id) => id,
+    let subscriber = match get_subscriber_from_token(&pool, &parameters.subscription_token).await {
+        Ok(subscriber) => subscriber,
         _ => return HttpResponse::InternalServerError().finish(),
     };
 
-    match subscriber_id {
+    match subscriber {
         None => HttpResponse::Unauthorized().finish(),
-        Some(subscriber_id) => {
-            match marks_subscriber_status_as_confirmed(&pool, subscriber_id).await {
+        Some(token) if token.expires_at < Utc::now() => expired_token_page(),
+        Some(token) => {
+            match marks_subscriber_status_as_confirmed(&pool, token.subscriber_id, token.list_id)
+                .await
+            {
                 Ok(_) => HttpResponse::Ok().finish(),
                 Err(_) => HttpResponse::InternalServerError().finish(),
             }
@@ -29,22 +32,59 @@ pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>
     }
 }
 
+fn expired_token_page() -> HttpResponse {
+    HttpResponse::Gone().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Confirmation link expired</title>
+</head>
+<body>
+    <p>This confirmation link has expired.</p>
+    <p>Please subscribe again with the same email address and we will send you a new one.</p>
+</body>
+</html>"#,
+    )
+}
+
+/// Confirms the subscriber, if they were not yet, and their membership of the list.
 #[tracing::instrument(name = "Marks status as confirmed", skip(pool, subscriber_id))]
 pub async fn marks_subscriber_status_as_confirmed(
     pool: &PgPool,
     subscriber_id: Uuid,
+    list_id: Uuid,
 ) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
     sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        r#"UPDATE subscriptions SET status = 'confirmed', confirmed_at = now() WHERE id = $1 AND status = 'pending_confirmation'"#,
         subscriber_id
     )
-    .execute(pool)
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    // The membership is gone if they left the list after asking to join it
+    sqlx::query!(
+        r#"UPDATE list_memberships SET status = 'confirmed', confirmed_at = now() WHERE subscriber_id = $1 AND list_id = $2 AND status = 'pending_confirmation'"#,
+        subscriber_id,
+        list_id
+    )
+    .execute(&mut *transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
     })?;
-    Ok(())
+    transaction.commit().await
+}
+
+pub struct ConfirmationToken {
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    expires_at: DateTime<Utc>,
 }
 
 #[tracing::instrument(
@@ -54,9 +94,10 @@ pub async fn marks_subscriber_status_as_confirmed(
 pub async fn get_subscriber_from_token(
     pool: &PgPool,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"SELECT subscription_id FROM subscription_tokens WHERE subscription_token = $1"#,
+) -> Result<Option<ConfirmationToken>, sqlx::Error> {
+    sqlx::query_as!(
+        ConfirmationToken,
+        r#"SELECT subscription_id AS subscriber_id, list_id, expires_at FROM subscription_tokens WHERE subscription_token = $1"#,
         subscription_token
     )
     .fetch_optional(pool)
@@ -64,6 +105,5 @@ pub async fn get_subscriber_from_token(
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
-    })?;
-    Ok(result.map(|r| r.subscription_id))
+    })
 }
@@ -0,0 +1,255 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use anyhow::Context;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::utils::e500;
+
+// Both the page and the one-click form carry the token in the query string,
+// so that the same URL can be used as a link and as a POST target.
+// Issues also carry their list, unsubscribing from them only leaves that list.
+#[derive(Deserialize)]
+pub struct UnsubscribeParameters {
+    token: String,
+    list_id: Option<Uuid>,
+}
+
+pub fn unsubscribe_link(base_url: &str, unsubscribe_token: &str) -> String {
+    format!(
+        "{}/subscriptions/unsubscribe?token={}",
+        base_url, unsubscribe_token
+    )
+}
+
+/// The unsubscribe link of the issues sent to a list.
+pub fn list_unsubscribe_link(base_url: &str, unsubscribe_token: &str, list_id: Uuid) -> String {
+    format!(
+        "{}&list_id={}",
+        unsubscribe_link(base_url, unsubscribe_token),
+        list_id
+    )
+}
+
+// GET must not change anything: mail scanners and link previewers follow links
+// in emails, so we only render a page asking the subscriber to confirm.
+#[tracing::instrument(name = "Show the unsubscribe page", skip(parameters, pool))]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let status = get_status_from_unsubscribe_token(&pool, &parameters.token)
+        .await
+        .map_err(e500)?;
+    if let (Some(subscriber_status), Some(list_id)) = (status.as_deref(), parameters.list_id) {
+        let membership = get_list_membership(&pool, &parameters.token, list_id)
+            .await
+            .map_err(e500)?;
+        let body = match membership {
+            Some(m) if m.status.is_some() && subscriber_status != "unsubscribed" => format!(
+                r#"<p>Do you really want to stop receiving {}?</p>
+    <form action="{}" method="post">
+        <button type="submit">Unsubscribe</button>
+    </form>"#,
+                htmlescape::encode_minimal(&m.name),
+                htmlescape::encode_minimal(&list_unsubscribe_link("", &parameters.token, list_id))
+            ),
+            Some(m) => format!(
+                "<p>You are not subscribed to {} anymore.</p>",
+                htmlescape::encode_minimal(&m.name)
+            ),
+            None => "<p>You are not subscribed to this list anymore.</p>".into(),
+        };
+        return Ok(unsubscribe_page(&body));
+    }
+    let body = match status.as_deref() {
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+        Some("unsubscribed") => "<p>You have already unsubscribed from our newsletter.</p>".into(),
+        Some(_) => format!(
+            r#"<p>Do you really want to stop receiving our newsletter?</p>
+    <form action="/subscriptions/unsubscribe?token={}" method="post">
+        <button type="submit">Unsubscribe</button>
+    </form>"#,
+            parameters.token
+        ),
+    };
+    Ok(unsubscribe_page(&body))
+}
+
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(parameters, pool))]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if get_status_from_unsubscribe_token(&pool, &parameters.token)
+        .await
+        .map_err(e500)?
+        .is_none()
+    {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+    if let Some(list_id) = parameters.list_id {
+        leave_list(&pool, &parameters.token, list_id)
+            .await
+            .map_err(e500)?;
+        return Ok(unsubscribe_page(
+            "<p>You have been unsubscribed. You will not receive any more issues of this list.</p>",
+        ));
+    }
+    mark_subscriber_as_unsubscribed(&pool, &parameters.token)
+        .await
+        .map_err(e500)?;
+    Ok(unsubscribe_page(
+        "<p>You have been unsubscribed. You will not receive any more issues from us.</p>",
+    ))
+}
+
+fn unsubscribe_page(body: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    {body}
+</body>
+</html>"#,
+        ))
+}
+
+#[tracing::instrument(name = "Get subscriber status from unsubscribe token", skip_all)]
+async fn get_status_from_unsubscribe_token(
+    pool: &PgPool,
+    unsubscribe_token: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"SELECT status FROM subscriptions WHERE unsubscribe_token = $1"#,
+        unsubscribe_token
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the subscriber matching the unsubscribe token")?;
+    Ok(result.map(|r| r.status))
+}
+
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip_all)]
+async fn mark_subscriber_as_unsubscribed(
+    pool: &PgPool,
+    unsubscribe_token: &str,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    // Unsubscribing twice is a no-op, we keep the original unsubscribed_at
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed', unsubscribed_at = now()
+        WHERE unsubscribe_token = $1 AND status <> 'unsubscribed'
+    "#,
+        unsubscribe_token
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to mark the subscriber as unsubscribed")?;
+    // They leave every list, subscribing again only brings them back to the one they pick
+    sqlx::query!(
+        r#"
+        DELETE FROM list_memberships
+        WHERE subscriber_id = (SELECT id FROM subscriptions WHERE unsubscribe_token = $1)
+    "#,
+        unsubscribe_token
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to remove the subscriber from their lists")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the unsubscription")?;
+    Ok(())
+}
+
+struct ListMembership {
+    name: String,
+    status: Option<String>,
+}
+
+#[tracing::instrument(
+    name = "Get the list membership of a subscriber",
+    skip(pool, unsubscribe_token)
+)]
+async fn get_list_membership(
+    pool: &PgPool,
+    unsubscribe_token: &str,
+    list_id: Uuid,
+) -> Result<Option<ListMembership>, anyhow::Error> {
+    let membership = sqlx::query_as!(
+        ListMembership,
+        r#"
+        SELECT l.name, m.status AS "status?"
+        FROM lists l
+        LEFT JOIN list_memberships m ON m.list_id = l.list_id
+            AND m.subscriber_id = (SELECT id FROM subscriptions WHERE unsubscribe_token = $1)
+        WHERE l.list_id = $2
+    "#,
+        unsubscribe_token,
+        list_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the list membership matching the unsubscribe token")?;
+    Ok(membership)
+}
+
+/// One-click unsubscribe from an issue: the subscriber only leaves the list of the issue.
+/// Leaving their last list unsubscribes them altogether.
+#[tracing::instrument(
+    name = "Remove a subscriber from a list",
+    skip(pool, unsubscribe_token)
+)]
+async fn leave_list(
+    pool: &PgPool,
+    unsubscribe_token: &str,
+    list_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        r#"
+        DELETE FROM list_memberships
+        WHERE subscriber_id = (SELECT id FROM subscriptions WHERE unsubscribe_token = $1)
+            AND list_id = $2
+    "#,
+        unsubscribe_token,
+        list_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to remove the subscriber from the list")?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions s
+        SET status = 'unsubscribed', unsubscribed_at = now()
+        WHERE unsubscribe_token = $1 AND status <> 'unsubscribed' AND NOT EXISTS (
+            SELECT 1 FROM list_memberships m WHERE m.subscriber_id = s.id
+        )
+    "#,
+        unsubscribe_token
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to mark the subscriber as unsubscribed")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the unsubscription")?;
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use std::{net::TcpListener, time::Duration};
+use std::{net::TcpListener, sync::Arc, time::Duration};
 
 use actix_session::{storage::RedisSessionStore, SessionMiddleware};
 use actix_web::{
@@ -16,10 +16,24 @@ use tracing_actix_web::TracingLogger;
 use crate::{
     authentication::reject_anonymous_users,
     configurations::Settings,
-    email_client::EmailClient,
+    email_client::EmailSender,
     routes::{
-        admin_dashboard, change_password, change_password_form, confirm, health_check, home, login,
-        login_form, logout, publish_newsletter, publish_newsletter_form, subscribe,
+        admin_dashboard, atom_feed, cancel_newsletter_issue, change_email, change_password,
+        change_password_form, confirm, confirm_email_change, create_email_template,
+        create_mailing_list, create_newsletter_draft, delete_email_template,
+        delete_newsletter_draft, delete_subscriber, delivery_failures, edit_email_template,
+        edit_mailing_list, edit_newsletter_draft, email_templates, erase_personal_data,
+        export_personal_data, export_subscribers, health_check, home, import_subscribers,
+        import_subscribers_form, issue_web_view, issues_archive, login, login_form, logout,
+        mailing_lists, newsletter_drafts, newsletter_issue_status, pause_delivery,
+        pause_newsletter_issue, personal_data_form, preferences_form, preview_newsletter_draft,
+        publish_newsletter, publish_newsletter_form, requeue_delivery_failure,
+        reschedule_newsletter_issue, resend_confirmation_email, resume_delivery,
+        resume_newsletter_issue, rss_feed, send_test_email, subscribe, subscriber_details,
+        subscriber_personal_data_form, subscribers, unsubscribe, unsubscribe_form,
+        unsubscribe_subscriber, update_email_template, update_lists, update_mailing_list,
+        update_newsletter_draft, update_newsletter_issue_visibility, update_username,
+        SUBSCRIBER_IMPORT_MAX_SIZE,
     },
 };
 
@@ -44,6 +58,7 @@ impl Application {
             email_client,
             configuration.application.base_url,
             configuration.application.hmac_secret,
+            configuration.application.suppression_secret,
             configuration.redis_uri,
         )
         .await?;
@@ -66,17 +81,22 @@ impl Application {
 // a raw `String` would expose us to conflicts.
 pub struct ApplicationBaseUrl(pub String);
 
+// The key of the hashes on the suppression list, wrapped for the same reason.
+pub struct SuppressionKey(pub Secret<String>);
+
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
-    email_client: EmailClient,
+    email_client: Arc<dyn EmailSender>,
     base_url: String,
     hmac_secret: Secret<String>,
+    suppression_secret: Secret<String>,
     redis_uri: Secret<String>,
 ) -> Result<Server, anyhow::Error> {
     let db_pool = web::Data::new(db_pool);
-    let email_client = Data::new(email_client);
+    let email_client: Data<dyn EmailSender> = Data::from(email_client);
     let base_url = Data::new(ApplicationBaseUrl(base_url));
+    let suppression_key = Data::new(SuppressionKey(suppression_secret));
     let message_store =
         CookieMessageStore::builder(Key::from(hmac_secret.expose_secret().as_bytes())).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
@@ -94,7 +114,26 @@ pub async fn run(
             .route("/health_check", web::get().to(health_check))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route("/preferences", web::get().to(preferences_form))
+            .route("/preferences/username", web::post().to(update_username))
+            .route("/preferences/lists", web::post().to(update_lists))
+            .route("/preferences/email", web::post().to(change_email))
+            .route(
+                "/preferences/email/confirm",
+                web::get().to(confirm_email_change),
+            )
+            .route("/preferences/pause", web::post().to(pause_delivery))
+            .route("/preferences/resume", web::post().to(resume_delivery))
             .route("/", web::get().to(home))
+            .route("/issues", web::get().to(issues_archive))
+            .route("/feed.rss", web::get().to(rss_feed))
+            .route("/feed.atom", web::get().to(atom_feed))
+            .route("/issues/{slug}", web::get().to(issue_web_view))
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
             .service(
@@ -102,7 +141,110 @@ pub async fn run(
                     .wrap(from_fn(reject_anonymous_users))
                     .route("/newsletters", web::post().to(publish_newsletter))
                     .route("/newsletters", web::get().to(publish_newsletter_form))
+                    .route(
+                        "/newsletters/{newsletter_issue_id}",
+                        web::get().to(newsletter_issue_status),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/schedule",
+                        web::post().to(reschedule_newsletter_issue),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/cancel",
+                        web::post().to(cancel_newsletter_issue),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/pause",
+                        web::post().to(pause_newsletter_issue),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/resume",
+                        web::post().to(resume_newsletter_issue),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/visibility",
+                        web::post().to(update_newsletter_issue_visibility),
+                    )
+                    .route("/drafts", web::get().to(newsletter_drafts))
+                    .route("/drafts", web::post().to(create_newsletter_draft))
+                    .route("/drafts/{draft_id}", web::get().to(edit_newsletter_draft))
+                    .route(
+                        "/drafts/{draft_id}",
+                        web::post().to(update_newsletter_draft),
+                    )
+                    .route(
+                        "/drafts/{draft_id}/preview",
+                        web::get().to(preview_newsletter_draft),
+                    )
+                    .route(
+                        "/drafts/{draft_id}/delete",
+                        web::post().to(delete_newsletter_draft),
+                    )
+                    .route("/drafts/{draft_id}/test", web::post().to(send_test_email))
+                    .route("/templates", web::get().to(email_templates))
+                    .route("/templates", web::post().to(create_email_template))
+                    .route(
+                        "/templates/{email_template_id}",
+                        web::get().to(edit_email_template),
+                    )
+                    .route(
+                        "/templates/{email_template_id}",
+                        web::post().to(update_email_template),
+                    )
+                    .route(
+                        "/templates/{email_template_id}/delete",
+                        web::post().to(delete_email_template),
+                    )
+                    .route("/lists", web::get().to(mailing_lists))
+                    .route("/lists", web::post().to(create_mailing_list))
+                    .route("/lists/{list_id}", web::get().to(edit_mailing_list))
+                    .route("/lists/{list_id}", web::post().to(update_mailing_list))
+                    .route("/subscribers", web::get().to(subscribers))
+                    .route("/subscribers/export", web::get().to(export_subscribers))
+                    .route(
+                        "/subscribers/personal-data",
+                        web::get().to(personal_data_form),
+                    )
+                    .route(
+                        "/subscribers/personal-data/export",
+                        web::post().to(export_personal_data),
+                    )
+                    .route(
+                        "/subscribers/personal-data/erase",
+                        web::post().to(erase_personal_data),
+                    )
+                    .service(
+                        web::resource("/subscribers/import")
+                            .app_data(web::FormConfig::default().limit(SUBSCRIBER_IMPORT_MAX_SIZE))
+                            .route(web::get().to(import_subscribers_form))
+                            .route(web::post().to(import_subscribers)),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(subscriber_details),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/personal-data",
+                        web::get().to(subscriber_personal_data_form),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/resend",
+                        web::post().to(resend_confirmation_email),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/unsubscribe",
+                        web::post().to(unsubscribe_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/delete",
+                        web::post().to(delete_subscriber),
+                    )
                     .route("/dashboard", web::get().to(admin_dashboard))
+                    .route("/deliveries/failures", web::get().to(delivery_failures))
+                    .route(
+                        "/deliveries/failures/requeue",
+                        web::post().to(requeue_delivery_failure),
+                    )
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
                     .route("/logout", web::post().to(logout)),
@@ -110,6 +252,7 @@ pub async fn run(
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(suppression_key.clone())
     })
     .listen(listener)?
     .run();
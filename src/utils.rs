@@ -1,7 +1,9 @@
 use core::fmt;
 
 use actix_web::HttpResponse;
+use actix_web_flash_messages::IncomingFlashMessages;
 use reqwest::header::LOCATION;
+use std::fmt::Write;
 
 pub fn e500<T>(e: T) -> actix_web::error::Error
 where
@@ -22,3 +24,18 @@ pub fn redirect(location: &str) -> HttpResponse {
         .insert_header((LOCATION, location))
         .finish()
 }
+
+/// The flash messages of a page, one paragraph each.
+/// They are escaped here, so that they can quote what the user typed.
+pub fn flash_messages_html(flash_messages: &IncomingFlashMessages) -> String {
+    let mut html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(
+            html,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(m.content())
+        )
+        .unwrap();
+    }
+    html
+}
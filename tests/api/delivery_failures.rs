@@ -0,0 +1,279 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, when_sending_newsletter,
+    BatchAccepted, TestApp,
+};
+use wiremock::ResponseTemplate;
+
+/// Publish an issue to the (confirmed) subscribers and return its id.
+async fn publish_newsletter(app: &TestApp) -> uuid::Uuid {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+/// Make the pending deliveries due, with a single attempt left.
+async fn exhaust_attempts(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE issue_delivery_table SET n_retries = $1",
+        app.worker.max_attempts - 1
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+/// A transient error: the deliveries are retried.
+fn unavailable_provider() -> ResponseTemplate {
+    ResponseTemplate::new(503)
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_delivery_failures() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_delivery_failures().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_requeue_a_delivery() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_requeue_delivery(&serde_json::json!({
+            "newsletter_issue_id": uuid::Uuid::new_v4().to_string(),
+            "subscriber_email": "ursula_le_guin@gmail.com",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_later_with_a_backoff() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(unavailable_provider())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter(&app).await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let task = sqlx::query!(
+        "SELECT n_retries, execute_after > now() AS \"is_delayed!\" FROM issue_delivery_table"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(task.n_retries, 1);
+    assert!(task.is_delayed);
+    let failures = sqlx::query!("SELECT subscriber_email FROM issue_delivery_failures")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(failures.is_empty());
+}
+
+#[tokio::test]
+async fn deliveries_that_run_out_of_attempts_are_moved_to_the_failures() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(unavailable_provider())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_issue_id = publish_newsletter(&app).await;
+    exhaust_attempts(&app).await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let pending = sqlx::query!("SELECT subscriber_email FROM issue_delivery_table")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(pending.is_empty());
+    let failure = sqlx::query!(
+        "SELECT newsletter_issue_id, subscriber_email, n_attempts, last_error \
+        FROM issue_delivery_failures"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(failure.newsletter_issue_id, newsletter_issue_id);
+    assert_eq!(failure.n_attempts, app.worker.max_attempts);
+    assert!(failure.last_error.contains("503 Service Unavailable"));
+
+    let html_page = app.get_delivery_failures_html().await;
+    assert!(html_page.contains(&failure.subscriber_email));
+    assert!(html_page.contains("503 Service Unavailable"));
+}
+
+#[tokio::test]
+async fn requeued_deliveries_are_sent_again() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let _mock_guard = when_sending_newsletter()
+        .respond_with(unavailable_provider())
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let newsletter_issue_id = publish_newsletter(&app).await;
+    exhaust_attempts(&app).await;
+    app.dispatch_all_pending_emails().await;
+    drop(_mock_guard);
+    let subscriber_email = sqlx::query!("SELECT subscriber_email FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .subscriber_email;
+
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Requeue the delivery
+    let response = app
+        .post_requeue_delivery(&serde_json::json!({
+            "newsletter_issue_id": newsletter_issue_id.to_string(),
+            "subscriber_email": subscriber_email,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/deliveries/failures");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_delivery_failures_html().await;
+    assert!(html_page.contains(&format!(
+        "<p><i>The delivery to {} has been requeued.</i></p>",
+        subscriber_email
+    )));
+    assert!(html_page.contains("No failed deliveries."));
+
+    // Act - Part 3 - Deliver it
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let pending = sqlx::query!("SELECT subscriber_email FROM issue_delivery_table")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(pending.is_empty());
+    // Mock verifies on Drop that the delivery went out
+}
+
+#[tokio::test]
+async fn the_deliveries_of_a_cancelled_issue_cannot_be_requeued() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let _mock_guard = when_sending_newsletter()
+        .respond_with(unavailable_provider())
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let newsletter_issue_id = publish_newsletter(&app).await;
+    exhaust_attempts(&app).await;
+    app.dispatch_all_pending_emails().await;
+    drop(_mock_guard);
+    let subscriber_email = sqlx::query!("SELECT subscriber_email FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .subscriber_email;
+    // e.g. cancelled while other deliveries were still being retried
+    sqlx::query!("UPDATE newsletter_issues SET status = 'cancelled'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Try to requeue the delivery
+    let response = app
+        .post_requeue_delivery(&serde_json::json!({
+            "newsletter_issue_id": newsletter_issue_id.to_string(),
+            "subscriber_email": subscriber_email,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/deliveries/failures");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_delivery_failures_html().await;
+    assert!(html_page.contains("<p><i>This newsletter issue has been cancelled.</i></p>"));
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let failures = sqlx::query!("SELECT subscriber_email FROM issue_delivery_failures")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failures.len(), 1);
+    // Mock verifies on Drop that nothing went out
+}
+
+#[tokio::test]
+async fn the_queued_deliveries_of_a_cancelled_issue_are_not_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter(&app).await;
+    // A task left behind, whatever put it there
+    sqlx::query!("UPDATE newsletter_issues SET status = 'cancelled'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let pending = sqlx::query!("SELECT subscriber_email FROM issue_delivery_table")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(pending.len(), 1);
+    // Mock verifies on Drop that nothing went out
+}
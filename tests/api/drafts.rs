@@ -0,0 +1,393 @@
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, when_sending_newsletter,
+    BatchAccepted, TestApp,
+};
+
+fn draft_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Draft title",
+        "text_content": "Draft body as plain text",
+        "html_content": "<p>Draft body as HTML</p>",
+    })
+}
+
+/// Save a draft and return its id.
+async fn create_draft(app: &TestApp) -> String {
+    let response = app.post_create_draft(&draft_body()).await;
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    location.trim_start_matches("/admin/drafts/").to_owned()
+}
+
+async fn issue_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_drafts() {
+    // Arrange
+    let app = spawn_app().await;
+    let draft_id = uuid::Uuid::new_v4().to_string();
+
+    // Act
+    let responses = [
+        app.get_drafts().await,
+        app.post_create_draft(&draft_body()).await,
+        app.get_draft(&draft_id).await,
+        app.post_update_draft(&draft_id, &draft_body()).await,
+        app.get_draft_preview(&draft_id).await,
+        app.post_delete_draft(&draft_id).await,
+        app.post_send_test_email(&draft_id, &serde_json::json!({ "recipients": "" }))
+            .await,
+    ];
+
+    // Assert
+    for response in &responses {
+        assert_is_redirect_to(response, "/login");
+    }
+}
+
+#[tokio::test]
+async fn drafts_are_saved_without_being_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Save the draft
+    let draft_id = create_draft(&app).await;
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_draft_html(&draft_id).await;
+    assert!(html_page.contains("<p><i>The draft has been saved.</i></p>"));
+    assert!(html_page.contains("Draft body as plain text"));
+
+    // Act - Part 3 - Run the worker
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(issue_status(&app).await, "draft");
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("Draft title"));
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn drafts_can_be_edited() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+
+    // Act
+    let response = app
+        .post_update_draft(
+            &draft_id,
+            &serde_json::json!({
+                "title": "New title",
+                "text_content": "New body as plain text",
+                "html_content": "<p>New body as HTML</p>",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{}", draft_id));
+
+    // Assert
+    let html_page = app.get_draft_html(&draft_id).await;
+    assert!(html_page.contains("New title"));
+    assert!(html_page.contains("New body as plain text"));
+    assert!(html_page.contains("&lt;p&gt;New body as HTML&lt;/p&gt;"));
+}
+
+#[tokio::test]
+async fn drafts_can_be_previewed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+
+    // Act
+    let response = app.get_draft_preview(&draft_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"srcdoc="&lt;p&gt;Draft body as HTML&lt;/p&gt;""#));
+    assert!(html_page.contains("<pre>Draft body as plain text</pre>"));
+}
+
+#[tokio::test]
+async fn drafts_can_be_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+
+    // Act - Part 1 - Delete the draft
+    let response = app.post_delete_draft(&draft_id).await;
+    assert_is_redirect_to(&response, "/admin/drafts");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("<p><i>The draft has been deleted.</i></p>"));
+    assert!(html_page.contains("No drafts."));
+
+    // Assert
+    assert_eq!(app.get_draft(&draft_id).await.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn publishing_a_draft_sends_it_to_the_confirmed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Publish the draft
+    let publish_request_body = serde_json::json!({
+        "draft_id": draft_id,
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&publish_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issue has been accepted - emails will go out shortly.</i></p>"
+    ));
+
+    // Act - Part 3 - Send it
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(issue_status(&app).await, "sent");
+    let received_requests = app.email_server.received_requests().await.unwrap();
+    let received = received_requests.last().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&received.body).unwrap();
+    assert_eq!(body[0]["Subject"], "Draft title");
+    // Published issues are not drafts anymore
+    assert_eq!(app.get_draft(&draft_id).await.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn a_draft_can_only_be_published_once() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "draft_id": draft_id,
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.get_publish_newsletter_html().await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "draft_id": draft_id,
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Assert
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>This draft has already been published.</i></p>"));
+    let n_tasks = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM issue_delivery_table")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_tasks, 1);
+}
+
+#[tokio::test]
+async fn test_emails_are_sent_to_the_given_addresses_only() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Send the test email
+    let response = app
+        .post_send_test_email(
+            &draft_id,
+            &serde_json::json!({ "recipients": "editor@example.com, reviewer@example.com" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{}", draft_id));
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_draft_html(&draft_id).await;
+    assert!(html_page.contains(
+        "<p><i>The test email has been sent to editor@example.com, reviewer@example.com.</i></p>"
+    ));
+
+    // Assert
+    let received_requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value =
+        serde_json::from_slice(&received_requests.last().unwrap().body).unwrap();
+    let recipients: Vec<&str> = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|email| email["To"].as_str().unwrap())
+        .collect();
+    assert_eq!(recipients, ["editor@example.com", "reviewer@example.com"]);
+    assert_eq!(body[0]["Subject"], "Draft title");
+    assert!(body[0]["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("<p>Draft body as HTML</p>"));
+    // Nothing was enqueued nor recorded for the subscribers
+    assert_eq!(issue_status(&app).await, "draft");
+    let n_tasks = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM issue_delivery_table")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_tasks, 0);
+    let n_deliveries = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM newsletter_issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_deliveries, 0);
+}
+
+#[tokio::test]
+async fn test_emails_to_invalid_addresses_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_send_test_email(
+        &draft_id,
+        &serde_json::json!({ "recipients": "editor@example.com, reviewer" }),
+    )
+    .await;
+
+    // Assert
+    let html_page = app.get_draft_html(&draft_id).await;
+    assert!(html_page.contains("<p><i>reviewer is not a valid email address.</i></p>"));
+    // Mock verifies on Drop that we haven't sent any email
+}
+
+#[tokio::test]
+async fn drafts_written_in_markdown_are_previewed_as_html_and_plain_text() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let response = app
+        .post_create_draft(&serde_json::json!({
+            "title": "Draft title",
+            "text_content": "",
+            "html_content": "",
+            "markdown_content": "# Hello\n\nSome *news*.",
+        }))
+        .await;
+    let draft_id = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .trim_start_matches("/admin/drafts/")
+        .to_owned();
+
+    // Act
+    let html_page = app.get_draft_preview(&draft_id).await.text().await.unwrap();
+
+    // Assert
+    assert!(html_page.contains("Some &lt;em&gt;news&lt;/em&gt;."));
+    assert!(html_page.contains("<pre># Hello\n\nSome news.</pre>"));
+    let html_page = app.get_draft_html(&draft_id).await;
+    assert!(html_page.contains("# Hello\n\nSome *news*."));
+}
+
+#[tokio::test]
+async fn drafts_keep_their_author_and_last_edit_time() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+    let created = sqlx::query!("SELECT created_at, updated_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act - Part 1 - Edit the draft
+    let response = app
+        .post_update_draft(
+            &draft_id,
+            &serde_json::json!({
+                "title": "New title",
+                "subject_preview": "What is new this month",
+                "text_content": "New body as plain text",
+                "html_content": "<p>New body as HTML</p>",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{}", draft_id));
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_draft_html(&draft_id).await;
+    assert!(html_page.contains(r#"value="What is new this month""#));
+
+    // Assert
+    let issue = sqlx::query!(
+        r#"
+        SELECT i.created_at, i.updated_at, i.subject_preview, u.username
+        FROM newsletter_issues i JOIN users u ON u.user_id = i.author_user_id
+    "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(issue.created_at, created.created_at);
+    assert!(issue.updated_at > created.updated_at);
+    assert_eq!(
+        issue.subject_preview.as_deref(),
+        Some("What is new this month")
+    );
+    assert_eq!(issue.username, app.test_user.username);
+}
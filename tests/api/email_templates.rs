@@ -0,0 +1,273 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, when_sending_newsletter,
+    BatchAccepted, TestApp,
+};
+
+fn layout_body(name: &str, is_default: bool) -> serde_json::Value {
+    let mut body = serde_json::json!({
+        "name": name,
+        "header_html": "<h1>The Inbox Orchestrator</h1>",
+        "footer_html": "<p>Thanks for reading!</p>",
+        "css": "h1 { color: #1a73e8; }",
+        "company_address": "1 Main Street\nSpringfield",
+    });
+    if is_default {
+        body["is_default"] = "on".into();
+    }
+    body
+}
+
+/// Create a layout and return its id.
+async fn create_layout(app: &TestApp, name: &str, is_default: bool) -> String {
+    let response = app
+        .post_create_email_template(&layout_body(name, is_default))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    location
+        .strip_prefix("/admin/templates/")
+        .expect("Expected to be redirected to the new layout")
+        .to_string()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_email_layouts() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let list = app.get_email_templates().await;
+    let create = app
+        .post_create_email_template(&layout_body("Default", true))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&list, "/login");
+    assert_is_redirect_to(&create, "/login");
+}
+
+#[tokio::test]
+async fn layouts_can_be_created_listed_and_edited() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Create a layout
+    let email_template_id = create_layout(&app, "Branded", true).await;
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_email_template_html(&email_template_id).await;
+    assert!(html_page.contains("<p><i>The layout has been saved.</i></p>"));
+    assert!(html_page.contains("&lt;h1&gt;The Inbox Orchestrator&lt;/h1&gt;"));
+
+    // Act - Part 3 - The layout is listed as the default one
+    let html_page = app.get_email_templates_html().await;
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/templates/{}">Branded</a> (default)"#,
+        email_template_id
+    )));
+
+    // Act - Part 4 - Rename it
+    let response = app
+        .post_update_email_template(&email_template_id, &layout_body("Renamed", true))
+        .await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/templates/{}", email_template_id),
+    );
+
+    // Assert
+    let template = sqlx::query!("SELECT name, is_default FROM email_templates")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(template.name, "Renamed");
+    assert!(template.is_default);
+}
+
+#[tokio::test]
+async fn a_layout_without_a_company_address_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let mut body = layout_body("Branded", false);
+    body["company_address"] = "  ".into();
+
+    // Act - Part 1 - Submit the layout
+    let response = app.post_create_email_template(&body).await;
+    assert_is_redirect_to(&response, "/admin/templates");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_email_templates_html().await;
+    assert!(html_page.contains("<p><i>The company postal address is required.</i></p>"));
+
+    // Assert
+    let templates = sqlx::query!("SELECT name FROM email_templates")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(templates.is_empty());
+}
+
+#[tokio::test]
+async fn layout_names_must_be_unique() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_layout(&app, "Branded", false).await;
+
+    // Act - Part 1 - Submit a layout with the same name
+    let response = app
+        .post_create_email_template(&layout_body("Branded", false))
+        .await;
+    assert_is_redirect_to(&response, "/admin/templates");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_email_templates_html().await;
+    assert!(html_page.contains("<p><i>A layout named Branded already exists.</i></p>"));
+}
+
+#[tokio::test]
+async fn only_one_layout_is_the_default() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_layout(&app, "First", true).await;
+
+    // Act
+    create_layout(&app, "Second", true).await;
+
+    // Assert
+    let defaults = sqlx::query!("SELECT name FROM email_templates WHERE is_default")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(defaults.len(), 1);
+    assert_eq!(defaults[0].name, "Second");
+}
+
+#[tokio::test]
+async fn confirmation_emails_use_the_default_layout() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_layout(&app, "Branded", true).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions("username=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains("<h1>The Inbox Orchestrator</h1>"));
+    assert!(html_body.contains("h1 { color: #1a73e8; }"));
+    assert!(html_body.contains("1 Main Street<br>Springfield"));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .ends_with("--\n1 Main Street\nSpringfield"));
+    // The confirmation link still works through the layout
+    let confirmation_links = app.get_confirmation_link(email_request);
+    assert_eq!(confirmation_links.html, confirmation_links.plain_text);
+}
+
+#[tokio::test]
+async fn issues_are_rendered_through_the_selected_layout() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let email_template_id = create_layout(&app, "Branded", false).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "email_template_id": email_template_id,
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body[0]["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains("<h1>The Inbox Orchestrator</h1>"));
+    assert!(html_body.contains("<p>Newsletter body as HTML</p>"));
+    assert!(html_body.contains("<p>Thanks for reading!</p>"));
+    assert!(html_body.contains("1 Main Street<br>Springfield"));
+    let text_body = body[0]["TextBody"].as_str().unwrap();
+    assert!(text_body.contains("Newsletter body as plain text"));
+    assert!(text_body.contains("1 Main Street\nSpringfield"));
+}
+
+#[tokio::test]
+async fn issues_can_be_sent_without_a_layout() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    create_layout(&app, "Branded", true).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "email_template_id": "",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body[0]["HtmlBody"].as_str().unwrap();
+    assert!(!html_body.contains("The Inbox Orchestrator"));
+    assert!(!body[0]["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("Springfield"));
+}
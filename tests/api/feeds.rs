@@ -0,0 +1,189 @@
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::helpers::{spawn_app, TestApp};
+
+/// Add an issue that went out at `published_at`.
+async fn insert_issue(
+    app: &TestApp,
+    slug: &str,
+    html_content: &str,
+    published_at: DateTime<Utc>,
+    is_public: bool,
+) {
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, title, text_content, html_content,
+            published_at, status, slug, is_public
+        )
+        VALUES ($1, $2, '', $3, $4, 'sent', $5, $6)
+    "#,
+        uuid::Uuid::new_v4(),
+        format!("Title of {}", slug),
+        html_content,
+        published_at,
+        slug,
+        is_public,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+fn march_first() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 3, 1, 9, 30, 0).unwrap()
+}
+
+#[tokio::test]
+async fn the_rss_feed_lists_the_public_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_issue(
+        &app,
+        "march",
+        "<p>Hello {{ username }}</p>",
+        march_first(),
+        true,
+    )
+    .await;
+    insert_issue(&app, "internal", "<p>Internal</p>", march_first(), false).await;
+
+    // Act
+    let response = app.get_feed("feed.rss", &[]).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/rss+xml; charset=utf-8"
+    );
+    let feed = response.text().await.unwrap();
+    let link = format!("{}/issues/march", app.base_url);
+    assert!(feed.contains("<title>Title of march</title>"));
+    assert!(feed.contains(&format!("<link>{}</link>", link)));
+    assert!(feed.contains("<pubDate>Fri, 1 Mar 2024 09:30:00 +0000</pubDate>"));
+    assert!(feed.contains("<description>&lt;p&gt;Hello reader&lt;/p&gt;</description>"));
+    assert!(!feed.contains("internal"));
+}
+
+#[tokio::test]
+async fn the_atom_feed_lists_the_public_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_issue(&app, "march", "<p>News</p>", march_first(), true).await;
+    insert_issue(&app, "internal", "<p>Internal</p>", march_first(), false).await;
+
+    // Act
+    let response = app.get_feed("feed.atom", &[]).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/atom+xml; charset=utf-8"
+    );
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#));
+    assert!(feed.contains(&format!("<id>{}/issues/march</id>", app.base_url)));
+    assert!(feed.contains("<updated>2024-03-01T09:30:00+00:00</updated>"));
+    assert!(feed.contains(r#"<content type="html">&lt;p&gt;News&lt;/p&gt;</content>"#));
+    assert!(!feed.contains("internal"));
+}
+
+#[tokio::test]
+async fn feeds_carry_caching_headers() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_issue(
+        &app,
+        "february",
+        "<p>Old</p>",
+        march_first() - chrono::Duration::days(20),
+        true,
+    )
+    .await;
+    insert_issue(&app, "march", "<p>News</p>", march_first(), true).await;
+
+    for feed in ["feed.rss", "feed.atom"] {
+        // Act
+        let response = app.get_feed(feed, &[]).await;
+
+        // Assert
+        assert_eq!(
+            response.headers()["Last-Modified"],
+            "Fri, 01 Mar 2024 09:30:00 GMT"
+        );
+        assert!(response.headers().contains_key("ETag"));
+    }
+}
+
+#[tokio::test]
+async fn feeds_are_not_sent_again_when_the_etag_matches() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_issue(&app, "march", "<p>News</p>", march_first(), true).await;
+    let response = app.get_feed("feed.rss", &[]).await;
+    let etag = response.headers()["ETag"].to_str().unwrap().to_owned();
+
+    // Act - Part 1 - Nothing changed
+    let response = app.get_feed("feed.rss", &[("If-None-Match", &etag)]).await;
+    assert_eq!(response.status().as_u16(), 304);
+    assert_eq!(response.headers()["ETag"].to_str().unwrap(), etag);
+    assert!(response.text().await.unwrap().is_empty());
+
+    // Act - Part 2 - A new issue is published
+    insert_issue(
+        &app,
+        "april",
+        "<p>More news</p>",
+        march_first() + chrono::Duration::days(31),
+        true,
+    )
+    .await;
+    let response = app.get_feed("feed.rss", &[("If-None-Match", &etag)]).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("Title of april"));
+}
+
+#[tokio::test]
+async fn feeds_are_not_sent_again_when_not_modified_since() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_issue(&app, "march", "<p>News</p>", march_first(), true).await;
+
+    // Act
+    let unchanged = app
+        .get_feed(
+            "feed.atom",
+            &[("If-Modified-Since", "Fri, 01 Mar 2024 09:30:00 GMT")],
+        )
+        .await;
+    let changed = app
+        .get_feed(
+            "feed.atom",
+            &[("If-Modified-Since", "Thu, 29 Feb 2024 09:30:00 GMT")],
+        )
+        .await;
+
+    // Assert
+    assert_eq!(unchanged.status().as_u16(), 304);
+    assert_eq!(changed.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn an_empty_feed_is_valid() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_feed("feed.atom", &[]).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(!response.headers().contains_key("Last-Modified"));
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains("<updated>1970-01-01T00:00:00+00:00</updated>"));
+    assert!(!feed.contains("<entry>"));
+}
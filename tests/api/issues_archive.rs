@@ -0,0 +1,357 @@
+use chrono::{TimeZone, Utc};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, when_sending_newsletter,
+    BatchAccepted, TestApp,
+};
+
+/// Publish an issue right away and return its id.
+async fn publish_newsletter(
+    app: &TestApp,
+    title: &str,
+    html_content: &str,
+    public: bool,
+) -> String {
+    let mut newsletter_request_body = serde_json::json!({
+        "title": title,
+        "text_content": "Newsletter body as plain text",
+        "html_content": html_content,
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    if public {
+        newsletter_request_body["public"] = "on".into();
+    }
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues ORDER BY published_at DESC LIMIT 1"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id
+    .to_string()
+}
+
+async fn issue_slug(app: &TestApp, newsletter_issue_id: &str) -> String {
+    sqlx::query!(
+        "SELECT slug FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        uuid::Uuid::parse_str(newsletter_issue_id).unwrap()
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .slug
+    .unwrap()
+}
+
+#[tokio::test]
+async fn public_issues_are_listed_and_readable_in_the_archive() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id = publish_newsletter(
+        &app,
+        "Our March Update",
+        "<p>Hello {{ username }}, this is <b>news</b>.</p>",
+        true,
+    )
+    .await;
+
+    // Act - Part 1 - The archive lists the issue
+    let html_page = app.get_issues_archive_html(1).await;
+    assert!(html_page.contains(r#"<a href="/issues/our-march-update">Our March Update</a>"#));
+
+    // Act - Part 2 - Read the issue
+    assert_eq!(
+        issue_slug(&app, &newsletter_issue_id).await,
+        "our-march-update"
+    );
+    let response = app.get_issue_web_view("our-march-update").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Security-Policy"],
+        "script-src 'none'"
+    );
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>Our March Update</h1>"));
+    assert!(html_page.contains("<p>Hello reader, this is <b>news</b>.</p>"));
+}
+
+#[tokio::test]
+async fn issues_kept_out_of_the_archive_cannot_be_read() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id =
+        publish_newsletter(&app, "Internal memo", "<p>Internal</p>", false).await;
+
+    // Act
+    let html_page = app.get_issues_archive_html(1).await;
+    let response = app
+        .get_issue_web_view(&issue_slug(&app, &newsletter_issue_id).await)
+        .await;
+
+    // Assert
+    assert!(html_page.contains("<p>No issues yet.</p>"));
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn drafts_are_not_in_the_archive() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let response = app
+        .post_create_draft(&serde_json::json!({
+            "title": "Work in progress",
+            "text_content": "Draft body",
+            "html_content": "<p>Draft body</p>",
+            "public": "on",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    // Act
+    let html_page = app.get_issues_archive_html(1).await;
+
+    // Assert
+    assert!(!html_page.contains("Work in progress"));
+}
+
+#[tokio::test]
+async fn issues_with_the_same_title_get_different_slugs() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let first = publish_newsletter(&app, "Weekly news", "<p>First</p>", true).await;
+
+    // Act
+    let second = publish_newsletter(&app, "Weekly news", "<p>Second</p>", true).await;
+
+    // Assert
+    assert_eq!(issue_slug(&app, &first).await, "weekly-news");
+    let second_slug = issue_slug(&app, &second).await;
+    assert_eq!(
+        second_slug,
+        format!("weekly-news-{}", &second.replace('-', "")[..8])
+    );
+    let html_page = app
+        .get_issue_web_view(&second_slug)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<p>Second</p>"));
+}
+
+#[tokio::test]
+async fn the_archive_is_paginated() {
+    // Arrange
+    let app = spawn_app().await;
+    for i in 0..21 {
+        sqlx::query!(
+            r#"
+            INSERT INTO newsletter_issues (
+                newsletter_issue_id, title, text_content, html_content,
+                published_at, status, slug, is_public
+            )
+            VALUES ($1, $2, '', '', $3, 'sent', $4, TRUE)
+        "#,
+            uuid::Uuid::new_v4(),
+            format!("Issue {}", i),
+            Utc.with_ymd_and_hms(2024, 1, i + 1, 9, 0, 0).unwrap(),
+            format!("issue-{}", i),
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+
+    // Act
+    let first_page = app.get_issues_archive_html(1).await;
+    let second_page = app.get_issues_archive_html(2).await;
+
+    // Assert - the most recent issues come first
+    assert!(first_page.contains(r#"<a href="/issues/issue-20">Issue 20</a> (2024-01-21)"#));
+    assert!(!first_page.contains(r#"<a href="/issues/issue-0">"#));
+    assert!(first_page.contains(r#"<a href="/issues?page=2">Older issues</a>"#));
+    assert!(second_page.contains(r#"<a href="/issues/issue-0">Issue 0</a>"#));
+    assert!(second_page.contains(r#"<a href="/issues?page=1">Newer issues</a>"#));
+    assert!(!second_page.contains("Older issues"));
+}
+
+#[tokio::test]
+async fn emails_of_public_issues_link_to_their_web_version() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter(
+        &app,
+        "Our March Update",
+        r#"<p><a href="{{ web_view_url }}">Read it online</a></p>"#,
+        true,
+    )
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let web_view_url = format!("{}/issues/our-march-update", app.base_url);
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body[0]["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains(&format!(
+        r#"<a href="{}">View this issue in your browser</a>"#,
+        web_view_url
+    )));
+    assert!(html_body.contains(&format!(r#"<a href="{}">Read it online</a>"#, web_view_url)));
+    assert!(body[0]["TextBody"].as_str().unwrap().contains(&format!(
+        "View this issue in your browser: {}",
+        web_view_url
+    )));
+}
+
+#[tokio::test]
+async fn emails_of_internal_issues_do_not_link_to_a_web_version() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter(&app, "Internal memo", "<p>Internal</p>", false).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(!body[0]["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("View this issue in your browser"));
+}
+
+#[tokio::test]
+async fn the_web_view_placeholder_is_empty_in_internal_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter(
+        &app,
+        "Internal memo",
+        r#"<p><a href="{{ web_view_url }}">Read it online</a></p>"#,
+        false,
+    )
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body[0]["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains(r#"<a href="">Read it online</a>"#));
+    assert!(!html_body.contains("/issues/"));
+}
+
+#[tokio::test]
+async fn issues_with_the_same_title_published_at_once_get_different_slugs() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    tokio::join!(
+        publish_newsletter(&app, "Weekly news", "<p>First</p>", true),
+        publish_newsletter(&app, "Weekly news", "<p>Second</p>", true),
+    );
+
+    // Assert
+    let slugs = sqlx::query!(r#"SELECT DISTINCT slug AS "slug!" FROM newsletter_issues"#)
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(slugs.len(), 2);
+}
+
+#[tokio::test]
+async fn issues_can_be_removed_from_the_archive() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id =
+        publish_newsletter(&app, "Our March Update", "<p>News</p>", true).await;
+
+    // Act - Part 1 - Remove the issue from the archive
+    let response = app
+        .post_newsletter_issue_visibility(&newsletter_issue_id, false)
+        .await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/{}", newsletter_issue_id),
+    );
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_newsletter_issue_html(&newsletter_issue_id).await;
+    assert!(html_page
+        .contains("<p><i>The newsletter issue has been removed from the public archive.</i></p>"));
+    assert!(html_page.contains("Not in the public archive."));
+
+    // Assert
+    let response = app.get_issue_web_view("our-march-update").await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_change_the_visibility_of_an_issue() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_newsletter_issue_visibility(&uuid::Uuid::new_v4().to_string(), true)
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,354 @@
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, when_sending_email,
+    when_sending_newsletter, BatchAccepted, ConfirmationLinks, TestApp,
+};
+
+/// Create a list and return its id.
+async fn create_list(app: &TestApp, name: &str) -> String {
+    let response = app
+        .post_create_mailing_list(&serde_json::json!({
+            "name": name,
+            "description": "News about our products",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    location
+        .strip_prefix("/admin/lists/")
+        .expect("Expected to be redirected to the new list")
+        .to_string()
+}
+
+async fn subscribe_to_list(app: &TestApp, email: &str, list_id: &str) -> ConfirmationLinks {
+    let _mock_guard = when_sending_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "username": "Ursula",
+        "email": email,
+        "list_id": list_id,
+    }))
+    .unwrap();
+    let response = app.post_subscriptions(body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_link(&email_request)
+}
+
+async fn publish_to_list(app: &TestApp, list_id: &str) {
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "list_id": list_id,
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+async fn membership_status(app: &TestApp, email: &str, list_id: &str) -> Option<String> {
+    sqlx::query!(
+        r#"
+        SELECT m.status
+        FROM list_memberships m
+        JOIN subscriptions s ON s.id = m.subscriber_id
+        WHERE s.email = $1 AND m.list_id = $2
+    "#,
+        email,
+        uuid::Uuid::parse_str(list_id).unwrap()
+    )
+    .fetch_optional(&app.db_pool)
+    .await
+    .unwrap()
+    .map(|r| r.status)
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_mailing_lists() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let list = app.get_mailing_lists().await;
+    let create = app
+        .post_create_mailing_list(&serde_json::json!({"name": "Product updates"}))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&list, "/login");
+    assert_is_redirect_to(&create, "/login");
+}
+
+#[tokio::test]
+async fn lists_can_be_created_and_their_names_are_unique() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Create a list
+    create_list(&app, "Product updates").await;
+    let html_page = app.get_mailing_lists_html().await;
+    assert!(html_page.contains("Product updates"));
+    assert!(html_page.contains("Newsletter</a> (default)"));
+
+    // Act - Part 2 - Use the same name again
+    let response = app
+        .post_create_mailing_list(&serde_json::json!({"name": "Product updates"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/lists");
+
+    // Assert
+    let html_page = app.get_mailing_lists_html().await;
+    assert!(html_page.contains("<p><i>A list named Product updates already exists.</i></p>"));
+}
+
+#[tokio::test]
+async fn there_is_always_a_default_list() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let default_list = sqlx::query!("SELECT list_id FROM lists WHERE is_default")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id
+        .to_string();
+
+    // Act
+    let response = app
+        .post_update_mailing_list(&default_list, &serde_json::json!({"name": "Newsletter"}))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/lists/{}", default_list));
+    let saved = sqlx::query!(
+        "SELECT is_default FROM lists WHERE list_id::text = $1",
+        default_list
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(saved.is_default);
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "username": "Ursula",
+        "email": "ursula@example.com",
+        "list_id": uuid::Uuid::new_v4().to_string(),
+    }))
+    .unwrap();
+
+    // Act
+    let response = app.post_subscriptions(body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn issues_only_go_to_the_confirmed_members_of_their_list() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let list_id = create_list(&app, "Product updates").await;
+    // On the default list only
+    create_confirmed_subscriber(&app).await;
+    let confirmation_link = subscribe_to_list(&app, "ursula@example.com", &list_id).await;
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        membership_status(&app, "ursula@example.com", &list_id)
+            .await
+            .as_deref(),
+        Some("confirmed")
+    );
+
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_to_list(&app, &list_id).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let batch_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let emails: Vec<serde_json::Value> = serde_json::from_slice(&batch_request.body).unwrap();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0]["To"], "ursula@example.com");
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_confirm_each_list_they_join() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let list_id = create_list(&app, "Product updates").await;
+    create_confirmed_subscriber(&app).await;
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+
+    // Act - Part 1 - Join another list
+    let confirmation_link = subscribe_to_list(&app, &email, &list_id).await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("Welcome to Product updates!"));
+    assert_eq!(
+        membership_status(&app, &email, &list_id).await.as_deref(),
+        Some("pending_confirmation")
+    );
+
+    // Act - Part 2 - Nothing is sent to them on that list until they confirm
+    {
+        let _mock_guard = Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount_as_scoped(&app.email_server)
+            .await;
+        publish_to_list(&app, &list_id).await;
+        app.dispatch_all_pending_emails().await;
+    }
+
+    // Act - Part 3 - Confirm
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    assert_eq!(
+        membership_status(&app, &email, &list_id).await.as_deref(),
+        Some("confirmed")
+    );
+    let subscriber = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscriber.status, "confirmed");
+}
+
+#[tokio::test]
+async fn unsubscribing_leaves_every_list() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let list_id = create_list(&app, "Product updates").await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber = sqlx::query!("SELECT email, unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let confirmation_link = subscribe_to_list(&app, &subscriber.email, &list_id).await;
+    reqwest::get(confirmation_link.html).await.unwrap();
+
+    // Act
+    app.post_unsubscribe(&subscriber.unsubscribe_token).await;
+
+    // Assert
+    let n_memberships = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM list_memberships"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_memberships, 0);
+}
+
+#[tokio::test]
+async fn one_click_unsubscribing_from_an_issue_only_leaves_its_list() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let list_id = create_list(&app, "Product updates").await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber = sqlx::query!("SELECT email, unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let confirmation_link = subscribe_to_list(&app, &subscriber.email, &list_id).await;
+    reqwest::get(confirmation_link.html).await.unwrap();
+
+    // Act - Part 1 - Leave the new list
+    let response = app
+        .post_list_unsubscribe(&subscriber.unsubscribe_token, &list_id)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Assert
+    assert_eq!(
+        membership_status(&app, &subscriber.email, &list_id).await,
+        None
+    );
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+
+    // Act - Part 2 - Leave the default list, their last one
+    let default_list_id = sqlx::query!("SELECT list_id FROM lists WHERE is_default")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id
+        .to_string();
+    app.post_list_unsubscribe(&subscriber.unsubscribe_token, &default_list_id)
+        .await;
+
+    // Assert
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
@@ -9,9 +9,24 @@
 // get bigger, we can create api/health_check/mod.rs and refactor codes.
 mod admin_dashboard;
 mod change_password;
+mod delivery_failures;
+mod drafts;
+mod email_templates;
+mod feeds;
 mod health_check;
 mod helpers;
+mod issues_archive;
 mod login;
+mod mailing_lists;
 mod newsletter;
+mod newsletter_broadcast;
+mod newsletter_issue_status;
+mod newsletter_schedule;
+mod personal_data;
+mod preferences;
+mod subscribers_admin;
+mod subscribers_export;
+mod subscribers_import;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
@@ -1,15 +1,11 @@
 use std::time::Duration;
 
-use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
-use fake::faker::internet::en::SafeEmail;
-use fake::faker::name::en::Name;
-use fake::Fake;
-use wiremock::matchers::{any, method, path};
-use wiremock::{Mock, MockBuilder, ResponseTemplate};
-
-fn when_sending_email() -> MockBuilder {
-    Mock::given(path("/email")).and(method("POST"))
-}
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+    when_sending_newsletter, BatchAccepted,
+};
+use wiremock::matchers::any;
+use wiremock::{Mock, Respond, ResponseTemplate};
 
 #[tokio::test]
 pub async fn transient_errors_do_not_cause_duplicate_deliveries_on_retries() {
@@ -26,15 +22,12 @@ pub async fn transient_errors_do_not_cause_duplicate_deliveries_on_retries() {
 
     app.test_user.login(&app).await; // login as admin
 
-    when_sending_email()
-        .respond_with(ResponseTemplate::new(200))
-        .up_to_n_times(1)
-        .expect(1)
-        .mount(&app.email_server)
-        .await;
-
-    when_sending_email()
-        .respond_with(ResponseTemplate::new(500))
+    // The first attempt only reaches one of the two subscribers
+    when_sending_newsletter()
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            {"ErrorCode": 0, "Message": "OK"},
+            {"ErrorCode": 100, "Message": "Maintenance"}
+        ])))
         .up_to_n_times(1)
         .expect(1)
         .mount(&app.email_server)
@@ -42,59 +35,72 @@ pub async fn transient_errors_do_not_cause_duplicate_deliveries_on_retries() {
 
     // As an admin, sending out newsletter issue to subscribers
     let response = app.post_publish_newsletter(&newsletter_request_body).await;
-    assert_eq!(response.status().as_u16(), 500);
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
 
-    // Resend
-    when_sending_email()
-        .respond_with(ResponseTemplate::new(200))
+    // Resend, without waiting for the backoff
+    sqlx::query!("UPDATE issue_delivery_table SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
         .named("Delievery retry")
+        .expect(1)
         .mount(&app.email_server)
         .await;
+    app.dispatch_all_pending_emails().await;
 
-    let response = app.post_publish_newsletter(&newsletter_request_body).await;
-    assert_eq!(response.status().as_u16(), 303);
+    // Only the email that failed is sent again
+    let requests = app.email_server.received_requests().await.unwrap();
+    let first_attempt: serde_json::Value =
+        serde_json::from_slice(&requests[requests.len() - 2].body).unwrap();
+    let retry: serde_json::Value =
+        serde_json::from_slice(&requests[requests.len() - 1].body).unwrap();
+    assert_eq!(retry.as_array().unwrap().len(), 1);
+    assert_eq!(retry[0]["To"], first_attempt[1]["To"]);
 }
 
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let name: String = Name().fake();
-    let email: String = SafeEmail().fake();
-
-    let body = serde_urlencoded::to_string(&serde_json::json!(
-        {
-            "username": name,
-            "email": email
-        }
-    ))
-    .unwrap();
+#[tokio::test]
+async fn permanently_rejected_deliveries_are_not_retried() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
 
-    let _mock_guard = when_sending_email()
-        .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
+    when_sending_newsletter()
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            {
+                "ErrorCode": 406,
+                "Message": "You tried to send to a recipient that has been marked as inactive."
+            }
+        ])))
         .expect(1)
-        .mount_as_scoped(&app.email_server)
+        .mount(&app.email_server)
         .await;
-    app.post_subscriptions(body.into())
-        .await
-        .error_for_status()
-        .unwrap();
 
-    let email_request = &app
-        .email_server
-        .received_requests()
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let pending = sqlx::query!("SELECT subscriber_email FROM issue_delivery_table")
+        .fetch_all(&app.db_pool)
         .await
-        .unwrap()
-        .pop()
         .unwrap();
-    app.get_confirmation_link(email_request)
-}
-
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await.html;
-    reqwest::get(confirmation_link)
+    assert!(pending.is_empty());
+    let failure = sqlx::query!("SELECT n_attempts, last_error FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
         .await
-        .unwrap()
-        .error_for_status()
         .unwrap();
+    assert_eq!(failure.n_attempts, 1);
+    assert!(failure.last_error.contains("marked as inactive"));
 }
 
 #[tokio::test]
@@ -137,8 +143,8 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
 
-    when_sending_email()
-        .respond_with(ResponseTemplate::new(200))
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -163,6 +169,140 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     // Mock verifies on Drop that we have sent the newsletter email
 }
 
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_unsubscribed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unsubscribe_token;
+    app.post_unsubscribe(&token)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn newsletters_carry_an_unsubscribe_link() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let token = sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unsubscribe_token;
+
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let link = format!("/subscriptions/unsubscribe?token={}", token);
+    assert!(body[0]["HtmlBody"].as_str().unwrap().contains(&link));
+    assert!(body[0]["TextBody"].as_str().unwrap().contains(&link));
+}
+
+#[tokio::test]
+async fn newsletters_carry_one_click_unsubscribe_headers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let token = sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unsubscribe_token;
+
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let headers = body[0]["Headers"].as_array().unwrap();
+    assert_eq!(headers.len(), 2);
+    assert_eq!(headers[0]["Name"], "List-Unsubscribe");
+    // Only the list of the issue is left in one click
+    let list_id = sqlx::query!("SELECT list_id FROM lists WHERE is_default")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id;
+    assert_eq!(
+        headers[0]["Value"],
+        format!(
+            "<{}/subscriptions/unsubscribe?token={}&list_id={}>",
+            app.base_url, token, list_id
+        )
+    );
+    assert_eq!(headers[1]["Name"], "List-Unsubscribe-Post");
+    assert_eq!(headers[1]["Value"], "List-Unsubscribe=One-Click");
+}
+
 #[tokio::test]
 async fn you_must_be_logged_in_to_see_the_newsletter_form() {
     // Arrange
@@ -197,8 +337,8 @@ pub async fn newsletter_creation_is_idempotent() {
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
 
-    when_sending_email()
-        .respond_with(ResponseTemplate::new(200))
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -239,8 +379,12 @@ pub async fn concurrent_requests_are_handled_gracefully() {
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
 
-    when_sending_email()
-        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+    when_sending_newsletter()
+        .respond_with(|request: &wiremock::Request| {
+            BatchAccepted
+                .respond(request)
+                .set_delay(Duration::from_secs(2))
+        })
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -271,3 +415,346 @@ pub async fn concurrent_requests_are_handled_gracefully() {
 
     app.dispatch_all_pending_emails().await;
 }
+
+#[tokio::test]
+async fn newsletters_are_sent_to_all_confirmed_subscribers_in_a_single_batch() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body.as_array().unwrap().len(), 3);
+    let pending = sqlx::query!("SELECT COUNT(*) AS count FROM issue_delivery_table")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(pending.count, Some(0));
+}
+
+#[tokio::test]
+async fn only_the_failed_emails_of_a_batch_are_retried() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    when_sending_newsletter()
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            {"ErrorCode": 0, "Message": "OK"},
+            {"ErrorCode": 500, "Message": "Internal server error"}
+        ])))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let failed_recipient = body[1]["To"].as_str().unwrap();
+    let pending = sqlx::query!("SELECT subscriber_email, n_retries FROM issue_delivery_table")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].subscriber_email, failed_recipient);
+    assert_eq!(pending[0].n_retries, 1);
+}
+
+#[tokio::test]
+async fn placeholders_are_rendered_for_every_recipient() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let subscriber = sqlx::query!("SELECT username, unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Hello {{ username }}! Leave at {{unsubscribe_url}}",
+        "html_content": "<p>Hello {{ username }}!</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let text_body = body[0]["TextBody"].as_str().unwrap();
+    assert!(text_body.contains(&format!(
+        "Hello {}! Leave at {}/subscriptions/unsubscribe?token={}",
+        subscriber.username, app.base_url, subscriber.unsubscribe_token
+    )));
+    let html_body = body[0]["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains(&format!(
+        "<p>Hello {}!</p>",
+        htmlescape::encode_minimal(&subscriber.username)
+    )));
+}
+
+#[tokio::test]
+async fn issues_with_unknown_placeholders_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Submit newsletter form
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Hello {{ first_name }}!",
+        "html_content": "<p>Hello!</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(
+        "<p><i>The plain text content is invalid: {{ first_name }} is not a known placeholder.</i></p>"
+    ));
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issues.is_empty());
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn unknown_placeholders_are_shown_as_they_were_typed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Submit newsletter form
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Hello {{ <b> }}!",
+        "html_content": "<p>Hello!</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_publish_newsletter_html().await;
+
+    // Assert - Escaped once, when the message is rendered
+    assert!(html_page.contains(
+        "<p><i>The plain text content is invalid: {{ &lt;b&gt; }} is not a known placeholder.</i></p>"
+    ));
+}
+
+#[tokio::test]
+async fn issues_written_in_markdown_are_sent_as_html_and_plain_text() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "",
+        "html_content": "",
+        "markdown_content": "Hello **{{ username }}**, read [the post](https://example.com).",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issue = sqlx::query!("SELECT markdown_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        issue.markdown_content.as_deref(),
+        Some("Hello **{{ username }}**, read [the post](https://example.com).")
+    );
+    let username = sqlx::query!("SELECT username FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .username;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body[0]["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains(&format!(
+        "<strong>{}</strong>",
+        htmlescape::encode_minimal(&username)
+    )));
+    assert!(html_body.contains(r#"<a style="color: #1a73e8;" href="https://example.com""#));
+    let text_body = body[0]["TextBody"].as_str().unwrap();
+    assert!(text_body.contains(&format!(
+        "Hello {}, read the post (https://example.com).",
+        username
+    )));
+}
+
+#[tokio::test]
+async fn published_issues_record_their_author_and_subject_preview() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Publish the issue
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "subject_preview": "  Three things you missed  ",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 1 - The metadata is stored
+    let issue = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, subject_preview, author_user_id, created_at, updated_at
+        FROM newsletter_issues
+    "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        issue.subject_preview.as_deref(),
+        Some("Three things you missed")
+    );
+    assert!(issue.author_user_id.is_some());
+    assert!(issue.updated_at >= issue.created_at);
+    let html_page = app
+        .get_newsletter_issue_html(&issue.newsletter_issue_id.to_string())
+        .await;
+    assert!(html_page.contains(&format!("<p>Written by {}</p>", app.test_user.username)));
+
+    // Assert - Part 2 - The subject preview comes first in the email
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body[0]["HtmlBody"].as_str().unwrap();
+    assert!(html_body.starts_with(
+        r#"<div style="display: none; max-height: 0; overflow: hidden;">Three things you missed</div>"#
+    ));
+    assert!(!body[0]["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("Three things you missed"));
+}
+
+#[tokio::test]
+async fn an_empty_subject_preview_is_not_stored() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "subject_preview": "   ",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+
+    // Assert
+    let issue = sqlx::query!("SELECT subject_preview FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issue.subject_preview.is_none());
+}
@@ -0,0 +1,204 @@
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, when_sending_newsletter,
+    BatchAccepted, TestApp,
+};
+
+/// Publish an issue to the (confirmed) subscribers and return its id.
+async fn publish_newsletter(app: &TestApp) -> String {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+        .to_string()
+}
+
+async fn issue_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+async fn n_pending_deliveries(app: &TestApp) -> i64 {
+    sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM issue_delivery_table")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_pause_or_resume_an_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    let newsletter_issue_id = uuid::Uuid::new_v4().to_string();
+
+    // Act
+    let pause = app.post_pause_newsletter_issue(&newsletter_issue_id).await;
+    let resume = app.post_resume_newsletter_issue(&newsletter_issue_id).await;
+
+    // Assert
+    assert_is_redirect_to(&pause, "/login");
+    assert_is_redirect_to(&resume, "/login");
+}
+
+#[tokio::test]
+async fn paused_issues_are_not_delivered() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_issue_id = publish_newsletter(&app).await;
+
+    // Act - Part 1 - Pause
+    let response = app.post_pause_newsletter_issue(&newsletter_issue_id).await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/{}", newsletter_issue_id),
+    );
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_newsletter_issue_html(&newsletter_issue_id).await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been paused.</i></p>"));
+    assert!(html_page.contains("Paused"));
+    assert!(html_page.contains("Resume"));
+
+    // Act - Part 3 - Run the worker
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(issue_status(&app).await, "paused");
+    assert_eq!(n_pending_deliveries(&app).await, 1);
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn resumed_issues_are_delivered() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_issue_id = publish_newsletter(&app).await;
+    app.post_pause_newsletter_issue(&newsletter_issue_id).await;
+
+    // Act - Part 1 - Resume
+    let response = app.post_resume_newsletter_issue(&newsletter_issue_id).await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/{}", newsletter_issue_id),
+    );
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_newsletter_issue_html(&newsletter_issue_id).await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been resumed.</i></p>"));
+
+    // Act - Part 3 - Run the worker
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(issue_status(&app).await, "sent");
+    assert_eq!(n_pending_deliveries(&app).await, 0);
+    // Mock verifies on Drop that we have sent the newsletter email
+}
+
+#[tokio::test]
+async fn only_issues_being_sent_can_be_paused() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_issue_id = publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Act
+    app.post_pause_newsletter_issue(&newsletter_issue_id).await;
+
+    // Assert
+    let html_page = app.get_newsletter_issue_html(&newsletter_issue_id).await;
+    assert!(html_page.contains("<p><i>This newsletter issue is not being sent.</i></p>"));
+    assert_eq!(issue_status(&app).await, "sent");
+}
+
+#[tokio::test]
+async fn cancelling_an_issue_being_sent_drops_the_remaining_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            {"ErrorCode": 0, "Message": "OK"},
+            {"ErrorCode": 500, "Message": "Internal server error"}
+        ])))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_issue_id = publish_newsletter(&app).await;
+    // One delivery goes out, the other one is waiting for a retry
+    app.dispatch_all_pending_emails().await;
+
+    // Act - Part 1 - Cancel
+    let response = app.post_cancel_newsletter_issue(&newsletter_issue_id).await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/{}", newsletter_issue_id),
+    );
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_newsletter_issue_html(&newsletter_issue_id).await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been cancelled.</i></p>"));
+    assert!(html_page.contains("Cancelled"));
+    assert!(html_page.contains("<p>1 recipient(s) had already received it.</p>"));
+
+    // Assert
+    assert_eq!(issue_status(&app).await, "cancelled");
+    assert_eq!(n_pending_deliveries(&app).await, 0);
+}
+
+#[tokio::test]
+async fn paused_issues_can_be_cancelled() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id = publish_newsletter(&app).await;
+    app.post_pause_newsletter_issue(&newsletter_issue_id).await;
+
+    // Act
+    app.post_cancel_newsletter_issue(&newsletter_issue_id).await;
+
+    // Assert
+    let html_page = app.get_newsletter_issue_html(&newsletter_issue_id).await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been cancelled.</i></p>"));
+    assert!(html_page.contains("<p>0 recipient(s) had already received it.</p>"));
+    assert_eq!(issue_status(&app).await, "cancelled");
+    assert_eq!(n_pending_deliveries(&app).await, 0);
+}
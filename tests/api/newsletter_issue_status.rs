@@ -0,0 +1,140 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, when_sending_newsletter,
+    BatchAccepted, TestApp,
+};
+
+/// Publish an issue to the (confirmed) subscribers and return its id.
+async fn publish_newsletter(app: &TestApp) -> String {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+        .to_string()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_progress_of_an_issue() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .get_newsletter_issue(&uuid::Uuid::new_v4().to_string())
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_unknown_issue_returns_404() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .get_newsletter_issue(&uuid::Uuid::new_v4().to_string())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_issue_page_shows_the_delivery_progress() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_issue_id = publish_newsletter(&app).await;
+
+    // Act - Part 1 - Before the worker goes through the queue
+    let html_page = app.get_newsletter_issue_html(&newsletter_issue_id).await;
+    assert!(html_page.contains("<h1>Newsletter title</h1>"));
+    assert!(html_page.contains("<tr><th>Total recipients</th><td>2</td></tr>"));
+    assert!(html_page.contains("<tr><th>Pending</th><td>2</td></tr>"));
+    assert!(html_page.contains("<tr><th>Sent</th><td>0</td></tr>"));
+    assert!(html_page.contains(r#"http-equiv="refresh""#));
+
+    // Act - Part 2 - Once everything went out
+    app.dispatch_all_pending_emails().await;
+    let html_page = app.get_newsletter_issue_html(&newsletter_issue_id).await;
+    assert!(html_page.contains("<tr><th>Total recipients</th><td>2</td></tr>"));
+    assert!(html_page.contains("<tr><th>Pending</th><td>0</td></tr>"));
+    assert!(html_page.contains("<tr><th>Sent</th><td>2</td></tr>"));
+    assert!(!html_page.contains(r#"http-equiv="refresh""#));
+}
+
+#[tokio::test]
+async fn the_issue_is_listed_on_the_publish_page() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let newsletter_issue_id = publish_newsletter(&app).await;
+
+    // Assert
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/newsletters/{}">Newsletter title</a>"#,
+        newsletter_issue_id
+    )));
+}
+
+#[tokio::test]
+async fn the_worker_records_the_outcome_of_every_delivery() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter(&app).await;
+    // One of the subscribers leaves before the issue goes out
+    let token = sqlx::query!("SELECT unsubscribe_token FROM subscriptions LIMIT 1")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unsubscribe_token;
+    app.post_unsubscribe(&token)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let deliveries = sqlx::query!(
+        "SELECT status, provider_message_id FROM newsletter_issue_deliveries ORDER BY status"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(deliveries.len(), 2);
+    assert_eq!(deliveries[0].status, "sent");
+    assert!(deliveries[0].provider_message_id.is_some());
+    assert_eq!(deliveries[1].status, "skipped");
+    assert!(deliveries[1].provider_message_id.is_none());
+}
@@ -0,0 +1,312 @@
+use chrono::{DateTime, Duration, Utc};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, when_sending_newsletter,
+    BatchAccepted, TestApp,
+};
+
+/// Format a send time the way a `datetime-local` input does.
+fn send_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M").to_string()
+}
+
+/// Schedule an issue for the (confirmed) subscribers and return its id.
+async fn schedule_newsletter(app: &TestApp, scheduled_for: &str) -> String {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "scheduled_for": scheduled_for
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+        .to_string()
+}
+
+async fn issue_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_not_delivered_before_their_send_time() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let scheduled_for = send_time(Utc::now() + Duration::days(1));
+
+    // Act - Part 1 - Schedule the issue
+    schedule_newsletter(&app, &scheduled_for).await;
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(&format!(
+        "<p><i>The newsletter issue has been scheduled for {} UTC.</i></p>",
+        scheduled_for.replace('T', " ")
+    )));
+
+    // Act - Part 3 - Run the worker
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(issue_status(&app).await, "scheduled");
+    // The recipients are only known once the issue is due
+    let tasks = sqlx::query!("SELECT subscriber_email FROM issue_delivery_table")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(tasks.is_empty());
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_delivered_once_due() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    schedule_newsletter(&app, &send_time(Utc::now() + Duration::hours(1))).await;
+    let scheduled = sqlx::query!("SELECT updated_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act - Time flies
+    sqlx::query!("UPDATE newsletter_issues SET scheduled_for = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(issue_status(&app).await, "sent");
+    let sent = sqlx::query!("SELECT updated_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(sent.updated_at > scheduled.updated_at);
+    // Mock verifies on Drop that we have sent the newsletter email
+}
+
+#[tokio::test]
+async fn subscribers_who_confirm_after_scheduling_receive_the_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    schedule_newsletter(&app, &send_time(Utc::now() + Duration::hours(1))).await;
+    create_confirmed_subscriber(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Time flies
+    sqlx::query!("UPDATE newsletter_issues SET scheduled_for = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(issue_status(&app).await, "sent");
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let emails: Vec<serde_json::Value> = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(emails.len(), 1);
+    // Mock verifies on Drop that we have sent the newsletter email
+}
+
+#[tokio::test]
+async fn due_issues_cannot_be_paused_before_the_worker_picks_them_up() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id =
+        schedule_newsletter(&app, &send_time(Utc::now() + Duration::hours(1))).await;
+
+    // Act - Time flies, the worker has not run yet
+    sqlx::query!("UPDATE newsletter_issues SET scheduled_for = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let html_page = app.get_newsletter_issue_html(&newsletter_issue_id).await;
+
+    // Assert
+    assert!(html_page.contains("Starting"));
+    assert!(!html_page.contains("/pause"));
+    assert!(html_page.contains("/cancel"));
+}
+
+#[tokio::test]
+async fn a_send_time_in_the_past_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Submit newsletter form
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "scheduled_for": send_time(Utc::now() - Duration::hours(1))
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The send time must be in the future.</i></p>"));
+    let issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issues.is_empty());
+}
+
+#[tokio::test]
+async fn scheduled_issues_can_be_rescheduled() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let newsletter_issue_id =
+        schedule_newsletter(&app, &send_time(Utc::now() + Duration::days(1))).await;
+    let new_send_time = send_time(Utc::now() + Duration::days(2));
+
+    // Act - Part 1 - Reschedule
+    let response = app
+        .post_reschedule_newsletter_issue(
+            &newsletter_issue_id,
+            &serde_json::json!({ "scheduled_for": new_send_time }),
+        )
+        .await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/{}", newsletter_issue_id),
+    );
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_newsletter_issue_html(&newsletter_issue_id).await;
+    assert!(html_page.contains(&format!(
+        "<p><i>The newsletter issue has been rescheduled for {} UTC.</i></p>",
+        new_send_time.replace('T', " ")
+    )));
+
+    // Assert
+    let issue = sqlx::query!("SELECT scheduled_for FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(send_time(issue.scheduled_for.unwrap()), new_send_time);
+}
+
+#[tokio::test]
+async fn scheduled_issues_can_be_cancelled() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_issue_id =
+        schedule_newsletter(&app, &send_time(Utc::now() + Duration::days(1))).await;
+
+    // Act - Part 1 - Cancel
+    let response = app.post_cancel_newsletter_issue(&newsletter_issue_id).await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/{}", newsletter_issue_id),
+    );
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_newsletter_issue_html(&newsletter_issue_id).await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been cancelled.</i></p>"));
+    assert!(html_page.contains("Cancelled"));
+
+    // Act - Part 3 - Run the worker
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(issue_status(&app).await, "cancelled");
+    let tasks = sqlx::query!("SELECT subscriber_email FROM issue_delivery_table")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(tasks.is_empty());
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn issues_that_went_out_cannot_be_cancelled() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_issue_id = schedule_newsletter(&app, "").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Act
+    app.post_cancel_newsletter_issue(&newsletter_issue_id).await;
+
+    // Assert
+    let html_page = app.get_newsletter_issue_html(&newsletter_issue_id).await;
+    assert!(html_page.contains("<p><i>This newsletter issue can no longer be cancelled.</i></p>"));
+    assert_eq!(issue_status(&app).await, "sent");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_reschedule_or_cancel_an_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    let newsletter_issue_id = uuid::Uuid::new_v4().to_string();
+
+    // Act
+    let reschedule = app
+        .post_reschedule_newsletter_issue(
+            &newsletter_issue_id,
+            &serde_json::json!({ "scheduled_for": send_time(Utc::now() + Duration::days(1)) }),
+        )
+        .await;
+    let cancel = app.post_cancel_newsletter_issue(&newsletter_issue_id).await;
+
+    // Assert
+    assert_is_redirect_to(&reschedule, "/login");
+    assert_is_redirect_to(&cancel, "/login");
+}
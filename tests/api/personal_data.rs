@@ -0,0 +1,265 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, when_sending_newsletter,
+    BatchAccepted, TestApp,
+};
+
+/// A confirmed subscriber who received one issue, returning their email.
+async fn subscriber_with_history(app: &TestApp) -> String {
+    create_confirmed_subscriber(app).await;
+    app.test_user.login(app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Our March Update",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+    sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email
+}
+
+async fn count(app: &TestApp, table: &str) -> i64 {
+    sqlx::query_scalar::<_, i64>(&format!("SELECT COUNT(*) FROM {}", table))
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_or_erase_personal_data() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+
+    // Act - Part 1 - Export
+    let response = app.post_personal_data_export(&email).await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - Erase
+    let response = app
+        .post_erase_personal_data(&serde_json::json!({
+            "email": email,
+            "confirm_email": email
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(count(&app, "subscriptions").await, 1);
+}
+
+#[tokio::test]
+async fn the_export_contains_everything_stored_about_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = subscriber_with_history(&app).await;
+
+    // Act
+    let response = app.post_personal_data_export(&email.to_uppercase()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "application/json"
+    );
+    let document: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(document["subscription"]["email"], email.as_str());
+    assert_eq!(document["subscription"]["status"], "confirmed");
+    assert!(document["subscription"]["confirmed_at"].is_string());
+    assert!(document["subscription"]["unsubscribe_token"].is_string());
+    assert_eq!(document["subscription_tokens"].as_array().unwrap().len(), 1);
+    let history = document["delivery_history"].as_array().unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0]["title"], "Our March Update");
+    assert_eq!(history[0]["status"], "sent");
+    assert!(document["queued_deliveries"].as_array().unwrap().is_empty());
+    assert!(document["suppressed_at"].is_null());
+}
+
+#[tokio::test]
+async fn the_export_of_an_unknown_address_is_empty() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let document: serde_json::Value = app
+        .post_personal_data_export("nobody@example.com")
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(document["subscription"].is_null());
+    assert!(document["delivery_history"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn the_export_needs_an_address() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_personal_data_export("  ").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_subscriber_page_leads_to_their_personal_data_without_their_address_in_urls() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = subscriber_with_history(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+        .to_string();
+
+    // Act - Part 1 - Open the subscriber page
+    let html_page = app.get_subscriber_html(&subscriber_id).await;
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/subscribers/{}/personal-data">"#,
+        subscriber_id
+    )));
+    assert!(!html_page.contains("?email="));
+
+    // Act - Part 2 - Follow the erasure link
+    let html_page = app.get_subscriber_personal_data_html(&subscriber_id).await;
+
+    // Assert
+    assert!(html_page.contains(&format!(r#"name="email" value="{}""#, email)));
+}
+
+#[tokio::test]
+async fn erasure_deletes_everything_and_only_keeps_a_hash_of_the_address() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = subscriber_with_history(&app).await;
+    // An issue still on its way to them
+    let newsletter_issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    sqlx::query!(
+        "INSERT INTO issue_delivery_table (newsletter_issue_id, subscriber_email) VALUES ($1, $2)",
+        newsletter_issue_id,
+        email
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act - Part 1 - Erase
+    let response = app
+        .post_erase_personal_data(&serde_json::json!({
+            "email": email,
+            "confirm_email": email.to_uppercase()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers/personal-data");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_personal_data_html().await;
+    assert!(
+        html_page.contains("<p><i>Everything stored about this address has been erased.</i></p>")
+    );
+    assert!(!html_page.contains(&email));
+
+    // Assert
+    for table in [
+        "subscriptions",
+        "subscription_tokens",
+        "issue_delivery_table",
+        "issue_delivery_failures",
+        "newsletter_issue_deliveries",
+    ] {
+        assert_eq!(count(&app, table).await, 0, "{} was not emptied", table);
+    }
+    let suppressed = sqlx::query!("SELECT email_hash FROM suppressed_emails")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(suppressed.len(), 1);
+    assert!(!suppressed[0].email_hash.contains(&email));
+    let document: serde_json::Value = app
+        .post_personal_data_export(&email)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(document["subscription"].is_null());
+    assert!(document["suppressed_at"].is_string());
+}
+
+#[tokio::test]
+async fn erasure_must_be_confirmed() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = subscriber_with_history(&app).await;
+
+    // Act
+    let response = app
+        .post_erase_personal_data(&serde_json::json!({
+            "email": email,
+            "confirm_email": "someone-else@example.com"
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 303);
+    let html_page = app.get_personal_data_html().await;
+    assert!(html_page.contains("The confirmation does not match the email address."));
+    assert_eq!(count(&app, "subscriptions").await, 1);
+    assert_eq!(count(&app, "newsletter_issue_deliveries").await, 1);
+    assert_eq!(count(&app, "suppressed_emails").await, 0);
+}
+
+#[tokio::test]
+async fn erased_addresses_cannot_be_imported_again() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_erase_personal_data(&serde_json::json!({
+        "email": "ursula@example.com",
+        "confirm_email": "ursula@example.com"
+    }))
+    .await;
+
+    // Act
+    let html_page = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv": "email,username\nUrsula@Example.com,Ursula\npat@example.com,Pat\n",
+            "status": "confirmed",
+            "consent_source": "Paper form",
+            "mode": "import"
+        }))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains("Nothing was imported"));
+    assert!(html_page
+        .contains("<li>Line 2: Ursula@Example.com asked for their data to be erased.</li>"));
+    assert_eq!(count(&app, "subscriptions").await, 0);
+}
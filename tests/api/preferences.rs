@@ -0,0 +1,522 @@
+use chrono::{Duration, Utc};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, when_sending_email,
+    when_sending_newsletter, BatchAccepted, TestApp,
+};
+
+async fn get_unsubscribe_token(app: &TestApp) -> String {
+    sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions")
+        .unsubscribe_token
+}
+
+fn preferences_page(token: &str) -> String {
+    format!("/preferences?token={}", token)
+}
+
+async fn publish_newsletter(app: &TestApp) {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn preferences_with_an_unknown_token_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app.get_preferences("not-a-real-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_preferences(
+            "username",
+            "not-a-real-token",
+            &serde_json::json!({"username": "Ursula"}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_preferences_page_shows_the_subscriber_details() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+    let saved = sqlx::query!("SELECT email, username FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.get_preferences(&token).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains(&saved.email));
+    assert!(html.contains(&htmlescape::encode_minimal(&saved.username)));
+    assert!(html.contains(&format!(
+        r#"action="/subscriptions/unsubscribe?token={}" method="post""#,
+        token
+    )));
+}
+
+#[tokio::test]
+async fn subscribers_can_update_their_name() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+
+    let response = app
+        .post_preferences(
+            "username",
+            &token,
+            &serde_json::json!({"username": "Ursula Le Guin"}),
+        )
+        .await;
+
+    assert_is_redirect_to(&response, &preferences_page(&token));
+    let saved = sqlx::query!("SELECT username FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.username, "Ursula Le Guin");
+    let html = app.get_preferences_html(&token).await;
+    assert!(html.contains("Your name has been updated."));
+}
+
+#[tokio::test]
+async fn invalid_names_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+    let before = sqlx::query!("SELECT username FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .username;
+
+    let response = app
+        .post_preferences("username", &token, &serde_json::json!({"username": ""}))
+        .await;
+
+    assert_is_redirect_to(&response, &preferences_page(&token));
+    let after = sqlx::query!("SELECT username FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .username;
+    assert_eq!(before, after);
+    let html = app.get_preferences_html(&token).await;
+    assert!(html.contains("This name cannot be used"));
+}
+
+#[tokio::test]
+async fn the_email_address_changes_once_the_new_one_is_confirmed() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+    when_sending_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_preferences(
+            "email",
+            &token,
+            &serde_json::json!({"email": "ursula@example.com"}),
+        )
+        .await;
+    assert_is_redirect_to(&response, &preferences_page(&token));
+
+    // Nothing changes until the new address is confirmed
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(saved.email, "ursula@example.com");
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula@example.com");
+    let confirmation_link = app.get_confirmation_link(&email_request);
+    assert_eq!(confirmation_link.html.path(), "/preferences/email/confirm");
+
+    let response = reqwest::get(confirmation_link.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula@example.com");
+    // The link cannot be used twice
+    let response = reqwest::get(app.get_confirmation_link(&email_request).html)
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_delivery_history_follows_the_new_email_address() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+    when_sending_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_preferences(
+        "email",
+        &token,
+        &serde_json::json!({"email": "ursula@example.com"}),
+    )
+    .await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let response = reqwest::get(app.get_confirmation_link(&email_request).html)
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let delivery = sqlx::query!("SELECT subscriber_email FROM newsletter_issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.subscriber_email, "ursula@example.com");
+}
+
+#[tokio::test]
+async fn the_address_of_another_subscriber_is_not_revealed_nor_taken() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    let subscribers = sqlx::query!("SELECT email, unsubscribe_token FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let (first, second) = (&subscribers[0], &subscribers[1]);
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_preferences(
+            "email",
+            &first.unsubscribe_token,
+            &serde_json::json!({"email": second.email}),
+        )
+        .await;
+
+    assert_is_redirect_to(&response, &preferences_page(&first.unsubscribe_token));
+    // The same message as for an address nobody uses
+    let html = app.get_preferences_html(&first.unsubscribe_token).await;
+    assert!(html.contains("Your address will change once you follow it."));
+    let n_requests = sqlx::query!(r#"SELECT count(*) AS "count!" FROM email_change_requests"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_requests, 0);
+}
+
+#[tokio::test]
+async fn email_changes_are_rate_limited() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+    when_sending_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    for n in 0..4 {
+        let response = app
+            .post_preferences(
+                "email",
+                &token,
+                &serde_json::json!({"email": format!("ursula-{}@example.com", n)}),
+            )
+            .await;
+        assert_is_redirect_to(&response, &preferences_page(&token));
+    }
+
+    let html = app.get_preferences_html(&token).await;
+    assert!(html.contains("You asked to change your email address too many times."));
+    let n_requests = sqlx::query!(r#"SELECT count(*) AS "count!" FROM email_change_requests"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_requests, 3);
+    // Mock verifies on Drop that the last request did not send an email
+}
+
+#[tokio::test]
+async fn expired_email_change_links_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+    when_sending_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_preferences(
+        "email",
+        &token,
+        &serde_json::json!({"email": "ursula@example.com"}),
+    )
+    .await;
+    sqlx::query!("UPDATE email_change_requests SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let response = reqwest::get(app.get_confirmation_link(&email_request).html)
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 410);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(saved.email, "ursula@example.com");
+}
+
+#[tokio::test]
+async fn paused_subscribers_do_not_receive_issues() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+    app.test_user.login(&app).await;
+    let until = (Utc::now() + Duration::days(30))
+        .format("%Y-%m-%d")
+        .to_string();
+
+    let response = app
+        .post_preferences("pause", &token, &serde_json::json!({"until": until}))
+        .await;
+    assert_is_redirect_to(&response, &preferences_page(&token));
+    let html = app.get_preferences_html(&token).await;
+    assert!(html.contains(&format!("Delivery is paused until {}.", until)));
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let delivery = sqlx::query!("SELECT status FROM newsletter_issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.status, "skipped");
+}
+
+#[tokio::test]
+async fn resumed_subscribers_receive_issues_again() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+    app.test_user.login(&app).await;
+    let until = (Utc::now() + Duration::days(30))
+        .format("%Y-%m-%d")
+        .to_string();
+    app.post_preferences("pause", &token, &serde_json::json!({"until": until}))
+        .await;
+
+    let response = app
+        .post_preferences("resume", &token, &serde_json::json!({}))
+        .await;
+    assert_is_redirect_to(&response, &preferences_page(&token));
+
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn delivery_cannot_be_paused_in_the_past_or_for_more_than_a_year() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+
+    for until in [
+        Utc::now() - Duration::days(1),
+        Utc::now() + Duration::days(400),
+    ] {
+        let until = until.format("%Y-%m-%d").to_string();
+        let response = app
+            .post_preferences("pause", &token, &serde_json::json!({"until": until}))
+            .await;
+        assert_is_redirect_to(&response, &preferences_page(&token));
+    }
+
+    let saved = sqlx::query!("SELECT paused_until FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.paused_until.is_none());
+}
+
+#[tokio::test]
+async fn newsletters_carry_a_preferences_link() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let link = preferences_page(&token);
+    assert!(body[0]["HtmlBody"].as_str().unwrap().contains(&link));
+    assert!(body[0]["TextBody"].as_str().unwrap().contains(&link));
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_cannot_change_their_preferences() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+    app.post_unsubscribe(&token)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let response = app
+        .post_preferences(
+            "username",
+            &token,
+            &serde_json::json!({"username": "Ursula Le Guin"}),
+        )
+        .await;
+
+    assert_is_redirect_to(&response, &preferences_page(&token));
+    let saved = sqlx::query!("SELECT username FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(saved.username, "Ursula Le Guin");
+    let html = app.get_preferences_html(&token).await;
+    assert!(html.contains("You have unsubscribed from our newsletter."));
+}
+
+#[tokio::test]
+async fn subscribers_can_choose_their_lists() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+    let list_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO lists (list_id, name, is_default) VALUES ($1, 'Product updates', false)",
+        list_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let html = app.get_preferences_html(&token).await;
+    assert!(html.contains("Product updates"));
+
+    // Leave the default list and join the new one
+    let response = app
+        .post_preferences("lists", &token, &[("list_id", list_id.to_string())])
+        .await;
+
+    assert_is_redirect_to(&response, &preferences_page(&token));
+    let memberships = sqlx::query!("SELECT list_id, status FROM list_memberships")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(memberships.len(), 1);
+    assert_eq!(memberships[0].list_id, list_id);
+    assert_eq!(memberships[0].status, "pending_confirmation");
+    let html = app.get_preferences_html(&token).await;
+    assert!(html.contains("Your lists have been updated."));
+
+    // The new list is confirmed from the email sent by the worker
+    when_sending_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_link(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let membership = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(membership.status, "confirmed");
+}
@@ -0,0 +1,403 @@
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+    when_sending_email, when_sending_newsletter, BatchAccepted, TestApp,
+};
+
+/// Insert a subscriber directly, to control when they subscribed.
+async fn insert_subscriber(
+    app: &TestApp,
+    email: &str,
+    username: &str,
+    status: &str,
+    minutes_ago: i64,
+) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, username, subscribed_at, status, unsubscribe_token)
+        VALUES ($1, $2, $3, $4, $5, $6)
+    "#,
+        subscriber_id,
+        email,
+        username,
+        Utc::now() - Duration::minutes(minutes_ago),
+        status,
+        Uuid::new_v4().to_string()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+async fn subscriber_id(app: &TestApp) -> String {
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+        .to_string()
+}
+
+/// The link to the following page, as found in the list.
+fn next_page_link(html: &str) -> Option<String> {
+    let end = html.find(r#"">Next page</a>"#)?;
+    let start = html[..end].rfind(r#"href=""#)? + r#"href=""#.len();
+    Some(html[start..end].to_string())
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed", 1)
+        .await
+        .to_string();
+
+    // Act - Part 1 - The list
+    let response = app.get_subscribers("").await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - The details
+    let response = app.get_subscriber(&subscriber_id).await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 3 - The actions
+    let response = app.post_subscriber_action(&subscriber_id, "delete").await;
+    assert_is_redirect_to(&response, "/login");
+    let saved = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.n, 1);
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered_by_status_and_searched() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed", 3).await;
+    insert_subscriber(
+        &app,
+        "pending@example.com",
+        "Pat",
+        "pending_confirmation",
+        2,
+    )
+    .await;
+    insert_subscriber(&app, "left@example.com", "Ursula Left", "unsubscribed", 1).await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Everybody is listed, newest first
+    let html_page = app.get_subscribers_html("").await;
+    let left = html_page.find("left@example.com").unwrap();
+    let pending = html_page.find("pending@example.com").unwrap();
+    let ursula = html_page.find("ursula@example.com").unwrap();
+    assert!(left < pending && pending < ursula);
+
+    // Act - Part 2 - Filter on the status
+    let html_page = app
+        .get_subscribers_html("status=pending_confirmation")
+        .await;
+    assert!(html_page.contains("pending@example.com"));
+    assert!(!html_page.contains("ursula@example.com"));
+    assert!(!html_page.contains("left@example.com"));
+
+    // Act - Part 3 - Search in the emails and the names, ignoring the case
+    let html_page = app.get_subscribers_html("q=URSULA").await;
+    assert!(html_page.contains("ursula@example.com"));
+    assert!(html_page.contains("left@example.com"));
+    assert!(!html_page.contains("pending@example.com"));
+
+    // Act - Part 4 - Both at once
+    let html_page = app.get_subscribers_html("status=confirmed&q=ursula").await;
+    assert!(html_page.contains("ursula@example.com"));
+    assert!(!html_page.contains("left@example.com"));
+}
+
+#[tokio::test]
+async fn search_terms_are_not_wildcards() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed", 1).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let html_page = app.get_subscribers_html("q=%25").await;
+
+    // Assert
+    assert!(!html_page.contains("ursula@example.com"));
+    assert!(html_page.contains("No subscribers."));
+}
+
+#[tokio::test]
+async fn an_unknown_status_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_subscribers("status=vip").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated_without_repeating_anybody() {
+    // Arrange
+    let app = spawn_app().await;
+    for i in 0..60 {
+        insert_subscriber(
+            &app,
+            &format!("reader{:02}@example.com", i),
+            "Reader",
+            "confirmed",
+            i,
+        )
+        .await;
+    }
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - The newest subscribers come first
+    let html_page = app.get_subscribers_html("q=reader").await;
+    assert!(html_page.contains("reader00@example.com"));
+    assert!(html_page.contains("reader49@example.com"));
+    assert!(!html_page.contains("reader50@example.com"));
+    let next_page = next_page_link(&html_page).unwrap();
+    // The search is kept from one page to the next
+    assert!(next_page.contains("q=reader"));
+
+    // A newcomer does not shift the following pages
+    insert_subscriber(&app, "newcomer@example.com", "Reader", "confirmed", 0).await;
+
+    // Act - Part 2 - The following page
+    let html_page = app
+        .api_client
+        .get(format!("{}{}", &app.address, next_page))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(!html_page.contains("reader49@example.com"));
+    assert!(html_page.contains("reader50@example.com"));
+    assert!(html_page.contains("reader59@example.com"));
+    assert!(next_page_link(&html_page).is_none());
+    assert!(html_page.contains("First page"));
+}
+
+#[tokio::test]
+async fn an_invalid_page_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_subscribers("after=yesterday").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_details_page_shows_the_dates_and_the_delivery_history() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_newsletter()
+        .respond_with(BatchAccepted)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Our March Update",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+    let subscriber_id = subscriber_id(&app).await;
+
+    // Act
+    let html_page = app.get_subscriber_html(&subscriber_id).await;
+
+    // Assert
+    let saved = sqlx::query!("SELECT email, confirmed_at FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(html_page.contains(&saved.email));
+    assert!(html_page.contains("Confirmed"));
+    assert!(html_page.contains(
+        &saved
+            .confirmed_at
+            .unwrap()
+            .format("%Y-%m-%d %H:%M UTC")
+            .to_string()
+    ));
+    assert!(html_page.contains("<td>Our March Update</td>"));
+    assert!(html_page.contains("<td>sent</td>"));
+    // Only pending subscribers can be sent a confirmation again
+    assert!(!html_page.contains("/resend"));
+}
+
+#[tokio::test]
+async fn the_details_page_of_an_unknown_subscriber_is_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_subscriber(&Uuid::new_v4().to_string()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn confirming_a_subscription_records_when_it_happened() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    let saved = sqlx::query!("SELECT confirmed_at FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.confirmed_at.is_none());
+
+    // Act
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let saved = sqlx::query!("SELECT confirmed_at FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.confirmed_at.is_some());
+}
+
+#[tokio::test]
+async fn a_new_confirmation_email_replaces_the_previous_one() {
+    // Arrange
+    let app = spawn_app().await;
+    let first_links = create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriber_action(&subscriber_id, "resend").await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let html_page = app.get_subscriber_html(&subscriber_id).await;
+    assert!(html_page.contains("<p><i>A new confirmation email has been sent.</i></p>"));
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let new_links = app.get_confirmation_link(&email_request);
+    assert_ne!(new_links.html, first_links.html);
+    // The previous link does not work anymore, the new one does
+    let response = reqwest::get(first_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    reqwest::get(new_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_are_not_sent_a_confirmation_email_again() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriber_action(&subscriber_id, "resend").await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let html_page = app.get_subscriber_html(&subscriber_id).await;
+    assert!(html_page.contains("This subscriber is not waiting for a confirmation."));
+}
+
+#[tokio::test]
+async fn admins_can_unsubscribe_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_subscriber_action(&subscriber_id, "unsubscribe")
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let saved = sqlx::query!("SELECT status, unsubscribed_at FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+    assert!(saved.unsubscribed_at.is_some());
+    // The confirmation link cannot bring them back
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let html_page = app.get_subscriber_html(&subscriber_id).await;
+    assert!(html_page.contains("<p><i>The subscriber has been unsubscribed.</i></p>"));
+    assert!(!html_page.contains("/unsubscribe\""));
+}
+
+#[tokio::test]
+async fn admins_can_delete_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_subscriber_action(&subscriber_id, "delete").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("<p><i>The subscriber has been deleted.</i></p>"));
+    let saved = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.n, 0);
+    let response = app.get_subscriber(&subscriber_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
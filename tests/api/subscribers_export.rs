@@ -0,0 +1,337 @@
+use chrono::{TimeZone, Utc};
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// Insert a subscriber directly, to control when they subscribed.
+async fn insert_subscriber(
+    app: &TestApp,
+    email: &str,
+    status: &str,
+    subscribed_on: (i32, u32, u32),
+    custom_fields: serde_json::Value,
+) {
+    let (year, month, day) = subscribed_on;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (
+            id, email, username, subscribed_at, status, unsubscribe_token, custom_fields
+        )
+        VALUES ($1, $2, 'Reader', $3, $4, $5, $6)
+    "#,
+        Uuid::new_v4(),
+        email,
+        Utc.with_ymd_and_hms(year, month, day, 12, 0, 0).unwrap(),
+        status,
+        Uuid::new_v4().to_string(),
+        custom_fields
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_export_subscribers("").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_csv_with_their_custom_fields() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "confirmed",
+        (2024, 1, 10),
+        serde_json::json!({ "company": "Earthsea, Ltd" }),
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "pat@example.com",
+        "pending_confirmation",
+        (2024, 2, 10),
+        serde_json::json!({ "city": "Lyon" }),
+    )
+    .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_export_subscribers("").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "text/csv; charset=utf-8"
+    );
+    let disposition = response.headers().get("Content-Disposition").unwrap();
+    assert!(disposition.to_str().unwrap().starts_with("attachment"));
+    let body = response.text().await.unwrap();
+    let lines: Vec<&str> = body.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(
+        lines[0],
+        "id,email,username,status,subscribed_at,confirmed_at,unsubscribed_at,consent_source,lists,city,company"
+    );
+    // Oldest first, custom fields in their own columns
+    assert!(lines[1].contains(",ursula@example.com,Reader,confirmed,2024-01-10T12:00:00+00:00,"));
+    assert!(lines[1].ends_with(",,\"Earthsea, Ltd\""));
+    assert!(lines[2].contains(",pat@example.com,"));
+    assert!(lines[2].ends_with(",Lyon,"));
+}
+
+#[tokio::test]
+async fn csv_exports_are_safe_to_open_in_a_spreadsheet() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "confirmed",
+        (2024, 1, 10),
+        serde_json::json!({ "company": "=HYPERLINK(\"http://evil.example\")", "status": "VIP" }),
+    )
+    .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let body = app.get_export_subscribers("").await.text().await.unwrap();
+
+    // Assert
+    let lines: Vec<&str> = body.lines().collect();
+    assert_eq!(
+        lines[0],
+        "id,email,username,status,subscribed_at,confirmed_at,unsubscribed_at,consent_source,lists,company,custom_status"
+    );
+    assert!(lines[1].ends_with(r#","'=HYPERLINK(""http://evil.example"")",VIP"#));
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_ndjson() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "confirmed",
+        (2024, 1, 10),
+        serde_json::json!({ "company": "Earthsea Ltd" }),
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "pat@example.com",
+        "unsubscribed",
+        (2024, 2, 10),
+        serde_json::json!({}),
+    )
+    .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_export_subscribers("format=ndjson").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "application/x-ndjson"
+    );
+    let body = response.text().await.unwrap();
+    let subscribers: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(subscribers.len(), 2);
+    assert_eq!(subscribers[0]["email"], "ursula@example.com");
+    assert_eq!(subscribers[0]["status"], "confirmed");
+    assert_eq!(subscribers[0]["custom_fields"]["company"], "Earthsea Ltd");
+    assert_eq!(subscribers[0]["confirmed_at"], serde_json::Value::Null);
+    assert_eq!(subscribers[1]["email"], "pat@example.com");
+    assert_eq!(subscribers[1]["status"], "unsubscribed");
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_with_the_lists_they_confirmed() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "confirmed",
+        (2024, 1, 10),
+        serde_json::json!({}),
+    )
+    .await;
+    sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, name)
+        VALUES (gen_random_uuid(), 'Events'), (gen_random_uuid(), 'Offers')
+    "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    // Not confirmed yet for the offers
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status)
+        SELECT s.id, l.list_id,
+            CASE WHEN l.name = 'Offers' THEN 'pending_confirmation' ELSE 'confirmed' END
+        FROM subscriptions s, lists l
+    "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    // Act
+    let csv = app.get_export_subscribers("").await.text().await.unwrap();
+    let ndjson = app
+        .get_export_subscribers("format=ndjson")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    let lines: Vec<&str> = csv.lines().collect();
+    assert!(lines[1].ends_with(",Events; Newsletter"));
+    let subscriber: serde_json::Value = serde_json::from_str(ndjson.trim()).unwrap();
+    assert_eq!(
+        subscriber["lists"],
+        serde_json::json!(["Events", "Newsletter"])
+    );
+}
+
+#[tokio::test]
+async fn the_export_can_be_filtered_by_status_and_subscription_date() {
+    // Arrange
+    let app = spawn_app().await;
+    let none = serde_json::json!({});
+    insert_subscriber(
+        &app,
+        "december@example.com",
+        "confirmed",
+        (2023, 12, 31),
+        none.clone(),
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "january@example.com",
+        "confirmed",
+        (2024, 1, 1),
+        none.clone(),
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "pending@example.com",
+        "pending_confirmation",
+        (2024, 1, 15),
+        none.clone(),
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "endofjan@example.com",
+        "confirmed",
+        (2024, 1, 31),
+        none.clone(),
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "february@example.com",
+        "confirmed",
+        (2024, 2, 1),
+        none,
+    )
+    .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let body = app
+        .get_export_subscribers("status=confirmed&from=2024-01-01&to=2024-01-31")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    // Both ends of the range are included
+    assert!(body.contains("january@example.com"));
+    assert!(body.contains("endofjan@example.com"));
+    assert!(!body.contains("december@example.com"));
+    assert!(!body.contains("february@example.com"));
+    assert!(!body.contains("pending@example.com"));
+}
+
+#[tokio::test]
+async fn invalid_export_parameters_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for query in [
+        "format=xlsx",
+        "status=vip",
+        "from=last-week",
+        "to=2024-13-01",
+    ] {
+        // Act
+        let response = app.get_export_subscribers(query).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The export did not fail with {}",
+            query
+        );
+    }
+}
+
+#[tokio::test]
+async fn large_lists_are_exported_in_full() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, username, subscribed_at, status, unsubscribe_token)
+        SELECT gen_random_uuid(), 'reader' || i || '@example.com', 'Reader', now(), 'confirmed',
+            md5(random()::text || i)
+        FROM generate_series(1, 3000) AS i
+    "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    // Act
+    let csv = app.get_export_subscribers("").await.text().await.unwrap();
+    let ndjson = app
+        .get_export_subscribers("format=ndjson")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(csv.lines().count(), 3001);
+    assert_eq!(ndjson.lines().count(), 3000);
+    assert!(csv.contains("reader3000@example.com"));
+}
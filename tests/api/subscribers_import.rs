@@ -0,0 +1,453 @@
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+    when_sending_email, TestApp,
+};
+
+async fn n_subscribers(app: &TestApp) -> i64 {
+    sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act - Part 1 - The form
+    let response = app.get_import_subscribers().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - The import
+    let response = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv": "email,username\nursula@example.com,Ursula\n",
+            "status": "confirmed",
+            "consent_source": "Paper form",
+            "mode": "import"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(n_subscribers(&app).await, 0);
+}
+
+#[tokio::test]
+async fn a_dry_run_reports_every_invalid_line_and_saves_nothing() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv": "email,username\n\
+                ursula@example.com,Ursula\n\
+                not-an-email,Pat\n\
+                sam@example.com,<Sam>\n",
+            "status": "pending_confirmation",
+            "mode": "check"
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("1 subscribers are ready to be imported"));
+    assert!(html_page.contains("<li>Line 3: Fail to parse email not-an-email</li>"));
+    assert!(html_page.contains("<li>Line 4: &lt;Sam&gt; is not a valid subscriber name.</li>"));
+    // The list is kept in the form to be fixed
+    assert!(html_page.contains("ursula@example.com,Ursula"));
+    assert_eq!(n_subscribers(&app).await, 0);
+}
+
+#[tokio::test]
+async fn a_dry_run_of_a_valid_list_saves_nothing() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let html_page = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv": "email,username\nursula@example.com,Ursula\npat@example.com,Pat\n",
+            "status": "pending_confirmation",
+            "mode": "check"
+        }))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains("The list is valid: 2 subscribers are ready to be imported."));
+    assert_eq!(n_subscribers(&app).await, 0);
+}
+
+#[tokio::test]
+async fn nothing_is_imported_if_a_line_is_invalid() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let html_page = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv": "email,username\nursula@example.com,Ursula\nnot-an-email,Pat\n",
+            "status": "confirmed",
+            "consent_source": "Paper form",
+            "mode": "import"
+        }))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains("Nothing was imported"));
+    assert!(html_page.contains("Line 3"));
+    assert_eq!(n_subscribers(&app).await, 0);
+}
+
+#[tokio::test]
+async fn subscribers_already_on_the_list_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    app.test_user.login(&app).await;
+
+    // Act
+    let html_page = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv": format!("email,username\n{},Ursula\n", email.to_uppercase()),
+            "status": "confirmed",
+            "consent_source": "Paper form",
+            "mode": "import"
+        }))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains("is already subscribed."));
+    assert_eq!(n_subscribers(&app).await, 1);
+}
+
+#[tokio::test]
+async fn known_subscribers_can_be_imported_into_another_list() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    let list_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO lists (list_id, name, is_default) VALUES ($1, 'Product updates', false)",
+        list_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv": format!("email,username\n{},Ursula\n", email),
+            "status": "confirmed",
+            "consent_source": "Paper form",
+            "list_id": list_id.to_string(),
+            "mode": "import"
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    assert_eq!(n_subscribers(&app).await, 1);
+    let membership = sqlx::query!(
+        "SELECT status FROM list_memberships WHERE list_id = $1",
+        list_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(membership.status, "confirmed");
+}
+
+#[tokio::test]
+async fn known_pending_subscribers_imported_as_confirmed_are_confirmed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    let list_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO lists (list_id, name, is_default) VALUES ($1, 'Product updates', false)",
+        list_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv": format!("email,username\n{},Ursula\n", email),
+            "status": "confirmed",
+            "consent_source": "Paper form",
+            "list_id": list_id.to_string(),
+            "mode": "import"
+        }))
+        .await;
+
+    // Assert - They get the issues of the list
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let saved = sqlx::query!("SELECT status, confirmed_at, consent_source FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+    assert!(saved.confirmed_at.is_some());
+    assert_eq!(saved.consent_source.as_deref(), Some("Paper form"));
+    let membership = sqlx::query!(
+        "SELECT status FROM list_memberships WHERE list_id = $1",
+        list_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(membership.status, "confirmed");
+}
+
+#[tokio::test]
+async fn pending_subscribers_can_wait_for_the_confirmation_of_several_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    let list_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO lists (list_id, name, is_default) VALUES ($1, 'Product updates', false)",
+        list_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Import into the default list
+    let response = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv": "email,username\nursula@example.com,Ursula\n",
+            "status": "pending_confirmation",
+            "mode": "import"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    // Act - Part 2 - Import into another list before the first confirmation is sent
+    let response = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv": "email,username\nursula@example.com,Ursula\n",
+            "status": "pending_confirmation",
+            "list_id": list_id.to_string(),
+            "mode": "import"
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let n_queued =
+        sqlx::query!(r#"SELECT COUNT(DISTINCT list_id) AS "count!" FROM confirmation_email_queue"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .count;
+    assert_eq!(n_queued, 2);
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_need_a_consent_source() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let html_page = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv": "email,username\nursula@example.com,Ursula\n",
+            "status": "confirmed",
+            "consent_source": "  ",
+            "mode": "import"
+        }))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains("Nothing was imported"));
+    assert!(html_page.contains("Tell where the consent of confirmed subscribers comes from"));
+    assert_eq!(n_subscribers(&app).await, 0);
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_are_imported_with_their_consent_source_and_custom_fields() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    when_sending_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Import
+    let response = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv": "email,username,company\n\
+                ursula@example.com,Ursula,Earthsea Ltd\n\
+                pat@example.com,Pat,\n",
+            "status": "confirmed",
+            "consent_source": "Paper form at the 2023 meetup",
+            "mode": "import"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("<p><i>2 subscribers have been imported.</i></p>"));
+    assert!(html_page.contains("ursula@example.com"));
+    assert!(html_page.contains("pat@example.com"));
+
+    // Act - Part 3 - The details
+    let saved = sqlx::query!(
+        "SELECT id, status, confirmed_at, consent_source FROM subscriptions WHERE email = 'ursula@example.com'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.status, "confirmed");
+    assert!(saved.confirmed_at.is_some());
+    assert_eq!(
+        saved.consent_source.as_deref(),
+        Some("Paper form at the 2023 meetup")
+    );
+    let html_page = app.get_subscriber_html(&saved.id.to_string()).await;
+    assert!(html_page.contains("<tr><th>company</th><td>Earthsea Ltd</td></tr>"));
+    assert!(html_page.contains("Paper form at the 2023 meetup"));
+
+    // Nobody is asked to confirm again
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn pending_subscribers_get_their_confirmation_email_from_the_worker() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Import
+    let response = app
+        .post_import_subscribers(&serde_json::json!({
+            "csv": "email,username\nursula@example.com,Ursula\npat@example.com,Pat\n",
+            "status": "pending_confirmation",
+            "mode": "import"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("2 subscribers have been imported"));
+    // The emails are not sent while the admin waits
+    assert!(app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty());
+    let n_pending = sqlx::query!(
+        "SELECT COUNT(*) AS \"n!\" FROM subscriptions WHERE status = 'pending_confirmation'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .n;
+    assert_eq!(n_pending, 2);
+
+    // Act - Part 2 - The worker sends them
+    when_sending_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let confirmation_links = app.get_confirmation_link(&email_requests[0]);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let n_confirmed =
+        sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM subscriptions WHERE status = 'confirmed'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .n;
+    assert_eq!(n_confirmed, 1);
+    let n_queued = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM confirmation_email_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn failed_confirmation_emails_are_retried_later() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_import_subscribers(&serde_json::json!({
+        "csv": "email,username\nursula@example.com,Ursula\n",
+        "status": "pending_confirmation",
+        "mode": "import"
+    }))
+    .await;
+    when_sending_email()
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let queued = sqlx::query!(
+        "SELECT n_retries, execute_after > now() AS \"later!\" FROM confirmation_email_queue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(queued.n_retries, 1);
+    assert!(queued.later);
+}
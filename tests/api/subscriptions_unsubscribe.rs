@@ -0,0 +1,151 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn get_unsubscribe_token(app: &TestApp) -> String {
+    sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions")
+        .unsubscribe_token
+}
+
+#[tokio::test]
+pub async fn unsubscribe_without_token_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(&format!("{}/subscriptions/unsubscribe", app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400)
+}
+
+#[tokio::test]
+pub async fn unsubscribe_with_an_unknown_token_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = app.get_unsubscribe("not-a-real-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_unsubscribe("not-a-real-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+pub async fn visiting_the_unsubscribe_link_does_not_unsubscribe() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+
+    let response = app.get_unsubscribe(&token).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains(&format!(
+        r#"action="/subscriptions/unsubscribe?token={}" method="post""#,
+        token
+    )));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+pub async fn the_unsubscribe_link_of_an_issue_names_its_list() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+    let list_id = sqlx::query!("SELECT list_id FROM lists WHERE is_default")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id
+        .to_string();
+
+    let response = app
+        .api_client
+        .get(&format!("{}/subscriptions/unsubscribe", app.address))
+        .query(&[("token", token.as_str()), ("list_id", list_id.as_str())])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("Do you really want to stop receiving Newsletter?"));
+    assert!(html.contains(&format!(
+        r#"action="/subscriptions/unsubscribe?token={}&amp;list_id={}" method="post""#,
+        token, list_id
+    )));
+}
+
+#[tokio::test]
+pub async fn posting_to_the_unsubscribe_link_unsubscribes_a_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+
+    let response = app.post_unsubscribe(&token).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status, unsubscribed_at FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions");
+    assert_eq!(saved.status, "unsubscribed");
+    assert!(saved.unsubscribed_at.is_some());
+}
+
+#[tokio::test]
+pub async fn unsubscribing_twice_keeps_the_original_timestamp() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+
+    app.post_unsubscribe(&token)
+        .await
+        .error_for_status()
+        .unwrap();
+    let first = sqlx::query!("SELECT unsubscribed_at FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unsubscribed_at;
+    let response = app.post_unsubscribe(&token).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let second = sqlx::query!("SELECT unsubscribed_at FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unsubscribed_at;
+    assert_eq!(first, second);
+    let html = app.get_unsubscribe(&token).await.text().await.unwrap();
+    assert!(html.contains("You have already unsubscribed"));
+}
+
+#[tokio::test]
+pub async fn one_click_unsubscribe_requests_are_accepted() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+
+    // This is what mailbox providers send on behalf of the user (RFC 8058)
+    let response = app
+        .api_client
+        .post(&format!("{}/subscriptions/unsubscribe", &app.address))
+        .query(&[("token", &token)])
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions");
+    assert_eq!(saved.status, "unsubscribed");
+}